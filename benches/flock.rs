@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use game::{
+    config::SimulationConfig,
+    logic::boid::{boid_mgr::BoidManager, traits::Updatable},
+    math::vec::Vector2,
+};
+
+/*
+ * The view port grows with the flock so density stays the same as the
+ * default 30 boids on 800x600, otherwise 100k boids would all see each other.
+ */
+fn flock(amount: u64, parallel: bool) -> BoidManager {
+    let default = SimulationConfig::default();
+    let scale = (amount as f32 / default.boids_amount as f32).sqrt();
+    let config = SimulationConfig {
+        boids_amount: amount,
+        view_port_size: Vector2::new(
+            default.view_port_size.x * scale,
+            default.view_port_size.y * scale,
+        ),
+        parallel,
+        ..default
+    };
+    let mut boid_manager = BoidManager::new(config);
+    boid_manager.spawn_boid(amount);
+    boid_manager.update();
+    boid_manager
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    group.sample_size(10);
+    for amount in [10_000, 100_000] {
+        for (name, parallel) in [("serial", false), ("parallel", true)] {
+            let mut boid_manager = flock(amount, parallel);
+            group.bench_with_input(BenchmarkId::new(name, amount), &amount, |b, _| {
+                b.iter(|| boid_manager.update())
+            });
+        }
+    }
+    group.finish();
+}
+
+/* Replaces the whole flock, as a replay does on every recorded tick it shows. */
+fn set_boids(c: &mut Criterion) {
+    let mut group = c.benchmark_group("set_boids");
+    group.sample_size(10);
+    for amount in [10_000, 100_000] {
+        let boids = flock(amount, true).boids().to_vec();
+        let mut boid_manager = BoidManager::default();
+        group.bench_with_input(BenchmarkId::from_parameter(amount), &amount, |b, _| {
+            b.iter(|| boid_manager.set_boids(boids.clone()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update, set_boids);
+criterion_main!(benches);
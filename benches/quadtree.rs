@@ -0,0 +1,204 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use game::{
+    config::SimulationConfig,
+    constants::{MAX_BOID_IN_AREA, VIEW_DISTANCE},
+    logic::boid::boid_impl::Boid,
+    math::{
+        quadtree::{quadt::QuadTree, region::Region, traits::SubInto},
+        vec::{V2f32, Vector2},
+    },
+};
+use rand::SeedableRng;
+
+/* Counts every allocation, so the two trees can be compared by more than time. */
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/*
+ * The tree BoidManager used before the arena one: a Box per node, boids copied
+ * into the leaves and copied again into every query result. Kept here only as
+ * the baseline, rebuilt from scratch each tick exactly as the manager did.
+ */
+enum BoxedQuadTree {
+    Leaf {
+        boundary: Region,
+        boids: Vec<Boid>,
+    },
+    Root {
+        boundary: Region,
+        neighbours: [Box<BoxedQuadTree>; 4],
+    },
+}
+
+impl BoxedQuadTree {
+    fn new(boundary: Region) -> Self {
+        BoxedQuadTree::Leaf {
+            boundary,
+            boids: Vec::with_capacity(MAX_BOID_IN_AREA),
+        }
+    }
+
+    fn boundary(&self) -> &Region {
+        match self {
+            BoxedQuadTree::Leaf { boundary, .. } | BoxedQuadTree::Root { boundary, .. } => boundary,
+        }
+    }
+
+    fn insert(&mut self, boid: Boid) -> bool {
+        match self {
+            BoxedQuadTree::Leaf { boundary, boids } => {
+                if !boundary.contains_boid(&boid) {
+                    return false;
+                }
+                if boids.len() < MAX_BOID_IN_AREA {
+                    boids.push(boid);
+                    return true;
+                }
+                let neighbours = Region::sub_into(boundary).map(|r| Box::new(Self::new(r)));
+                let old = std::mem::take(boids);
+                *self = BoxedQuadTree::Root {
+                    boundary: boundary.clone(),
+                    neighbours,
+                };
+                for b in old {
+                    self.insert(b);
+                }
+                self.insert(boid)
+            }
+            BoxedQuadTree::Root {
+                boundary,
+                neighbours,
+            } => boundary.contains_boid(&boid) && neighbours.iter_mut().any(|n| n.insert(boid)),
+        }
+    }
+
+    fn query_radius(&self, center: V2f32, radius: f32, found: &mut Vec<Boid>) {
+        if self.boundary().distance_squared_to(center) > radius * radius {
+            return;
+        }
+        match self {
+            BoxedQuadTree::Leaf { boids, .. } => found.extend(boids.iter().filter(|b| {
+                let d = b.position - center;
+                d.x * d.x + d.y * d.y <= radius * radius
+            })),
+            BoxedQuadTree::Root { neighbours, .. } => {
+                for n in neighbours {
+                    n.query_radius(center, radius, found);
+                }
+            }
+        }
+    }
+}
+
+fn flock(amount: usize) -> (Region, Vec<Boid>) {
+    let default = SimulationConfig::default();
+    let scale = (amount as f32 / default.boids_amount as f32).sqrt();
+    let size = Vector2::new(
+        default.view_port_size.x * scale,
+        default.view_port_size.y * scale,
+    );
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(50);
+    let boids = (0..amount)
+        .map(|i| {
+            let position = Vector2::random_from_vec(
+                Vector2::new(1.0, size.x - 1.0),
+                Vector2::new(1.0, size.y - 1.0),
+                &mut rng,
+            );
+            Boid::new(i, position, Vector2::zero())
+        })
+        .collect();
+    (Region::new(Vector2::zero(), size), boids)
+}
+
+/* One tick worth of work: build the tree, then one neighbour query per boid. */
+fn boxed_tick(boundary: &Region, boids: &[Boid]) -> usize {
+    let mut tree = BoxedQuadTree::new(boundary.clone());
+    for b in boids {
+        tree.insert(*b);
+    }
+    let mut found_total = 0;
+    for b in boids {
+        let mut found = Vec::with_capacity(MAX_BOID_IN_AREA);
+        tree.query_radius(b.position, VIEW_DISTANCE, &mut found);
+        found_total += found.len();
+    }
+    found_total
+}
+
+fn arena_tick(
+    tree: &mut QuadTree,
+    boundary: &Region,
+    boids: &[Boid],
+    found: &mut Vec<u32>,
+) -> usize {
+    tree.rebuild(boundary.clone(), boids);
+    let mut found_total = 0;
+    for b in boids {
+        found.clear();
+        tree.query_radius(boids, b.position, VIEW_DISTANCE, found);
+        found_total += found.len();
+    }
+    found_total
+}
+
+fn allocations_of(f: impl FnOnce() -> usize) -> (usize, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let found = f();
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, found)
+}
+
+fn build_and_query(c: &mut Criterion) {
+    let amount = 50_000;
+    let (boundary, boids) = flock(amount);
+    let mut tree = QuadTree::new(boundary.clone());
+    let mut found = Vec::new();
+    /* Warm up once, the arena tree is meant to be reused between ticks. */
+    arena_tick(&mut tree, &boundary, &boids, &mut found);
+
+    let (boxed_allocations, boxed_found) = allocations_of(|| boxed_tick(&boundary, &boids));
+    let (arena_allocations, arena_found) =
+        allocations_of(|| arena_tick(&mut tree, &boundary, &boids, &mut found));
+    /* The boxed tree silently drops boids lying on a split line, the arena one keeps them. */
+    assert!(arena_found >= boxed_found);
+    println!(
+        "allocations per tick with {} boids: boxed {}, arena {}",
+        amount, boxed_allocations, arena_allocations
+    );
+
+    let mut group = c.benchmark_group("quadtree");
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::new("boxed", amount), &amount, |b, _| {
+        b.iter(|| boxed_tick(&boundary, &boids))
+    });
+    group.bench_with_input(BenchmarkId::new("arena", amount), &amount, |b, _| {
+        b.iter(|| arena_tick(&mut tree, &boundary, &boids, &mut found))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, build_and_query);
+criterion_main!(benches);
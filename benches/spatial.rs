@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use game::{
+    config::SimulationConfig,
+    constants::VIEW_DISTANCE,
+    logic::boid::boid_impl::Boid,
+    math::{spatial::SpatialIndexKind, vec::Vector2},
+};
+use rand::SeedableRng;
+
+/*
+ * Same area for every amount, so the amounts are different densities:
+ * about 3, 25 and 130 boids per view circle.
+ */
+fn flock(config: &SimulationConfig, amount: usize) -> Vec<Boid> {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(amount as u64);
+    (0..amount)
+        .map(|i| {
+            let position = Vector2::random_from_vec(
+                Vector2::new(0.0, config.view_port_size.x),
+                Vector2::new(0.0, config.view_port_size.y),
+                &mut rng,
+            );
+            Boid::new(i, position, Vector2::zero())
+        })
+        .collect()
+}
+
+/* One tick worth of work: build, then one neighbour query per boid. */
+fn build_and_query(c: &mut Criterion) {
+    let config = SimulationConfig {
+        view_port_size: Vector2::new(3200.0, 2400.0),
+        ..Default::default()
+    };
+    let mut group = c.benchmark_group("spatial");
+    group.sample_size(10);
+    for amount in [1_000, 10_000, 50_000] {
+        let boids = flock(&config, amount);
+        for kind in SpatialIndexKind::ALL {
+            if kind == SpatialIndexKind::BruteForce && amount > 10_000 {
+                continue;
+            }
+            let mut index = kind.create(&config);
+            let mut found = Vec::new();
+            group.bench_with_input(
+                BenchmarkId::new(kind.to_string(), amount),
+                &amount,
+                |b, _| {
+                    b.iter(|| {
+                        index.build(config.view_port(), &boids);
+                        let mut total = 0;
+                        for boid in &boids {
+                            found.clear();
+                            index.query_radius(&boids, boid.position, VIEW_DISTANCE, &mut found);
+                            total += found.len();
+                        }
+                        total
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build_and_query);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use crate::math::{quadtree::region::Region, vec::V2f32};
+use crate::math::vec::V2f32;
 
 #[derive(Debug)]
 pub struct Camera {
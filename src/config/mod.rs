@@ -0,0 +1,612 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{
+        BOIDS_AMOUNT, BOID_MASS, CATCH_RADIUS, FRAME_RATE, MAX_BOID_FORCE, MAX_BOID_SPEED,
+        PREDATOR_MAX_SPEED, PREDATOR_VIEW_DISTANCE, SCREEN_SIZE, TICK_RATE, VIEW_ANGLE,
+        VIEW_DISTANCE, VIEW_PORT_SIZE,
+    },
+    logic::{
+        behaviour::{
+            pipeline::{BehaviourConfig, CombineMode},
+            registry::{default_behaviours, default_predator_behaviours},
+        },
+        boid::{
+            integrator::Integrator,
+            species::{CatchAction, Species},
+        },
+        obstacle::Obstacle,
+    },
+    math::{
+        quadtree::region::Region,
+        spatial::SpatialIndexKind,
+        vec::{V2f32, V2u32, Vector2},
+    },
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String),
+    Parse(String),
+    UnknownFormat(String),
+    UnknownKey(String),
+    UnknownBehaviour(String),
+    InvalidValue { key: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "cannot parse config: {}", e),
+            ConfigError::UnknownFormat(path) => {
+                write!(f, "unknown config format of {}, use .toml or .ron", path)
+            }
+            ConfigError::UnknownKey(key) => write!(f, "unknown config key {}", key),
+            ConfigError::UnknownBehaviour(name) => write!(f, "unknown behaviour {}", name),
+            ConfigError::InvalidValue { key, reason } => {
+                write!(f, "invalid value for {}: {}", key, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/*
+ * Everything that can be tuned without a recompile.
+ * Defaults are taken from `constants`, so an empty file gives the old behaviour.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub screen_size: V2u32,
+    pub view_port_size: V2f32,
+    pub boids_amount: u64,
+    /* Simulation steps per second, every step advances the world by 1 / tick_rate seconds. */
+    pub tick_rate: f32,
+    /* Frames per second the window is drawn at, 1 to 200. */
+    pub frame_rate: u32,
+    /* Units per second. */
+    pub max_boid_speed: f32,
+    /* Largest steering force, in units per second squared times mass. */
+    pub max_boid_force: f32,
+    /* Steering forces are divided by it, heavier boids turn slower. */
+    pub mass: f32,
+    /* How boids are moved every step: explicit_euler, semi_implicit_euler, velocity_verlet or rk4. */
+    pub integrator: Integrator,
+    pub view_distance: f32,
+    /* Field of view in degrees, the rest is a blind spot behind the boid. 360 sees all around. */
+    pub view_angle: f32,
+    /* Neighbours behind an occluder are not seen. */
+    pub occlusion: bool,
+    pub bound_margin: f32,
+    /* How the behaviours are combined: weighted_sum, prioritized or dithered. */
+    pub steering: CombineMode,
+    /* The steering pipeline, in priority order. Names must be unique. */
+    pub behaviours: Vec<BehaviourConfig>,
+    /* Calculate steering on the rayon pool, turn off for step-by-step debugging. */
+    pub parallel: bool,
+    /* Seed of the simulation RNG, a random one is picked (and logged) when missing. */
+    pub seed: Option<u64>,
+    /* Neighbour search backend: quad_tree, grid, kd_tree or brute_force. */
+    pub spatial_index: SpatialIndexKind,
+    /* Everything above describes the prey, this the predators. */
+    pub predator: PredatorConfig,
+    /* Circles, rects and polygons boids steer around and never pass through. */
+    pub obstacles: Vec<Obstacle>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredatorConfig {
+    /* Spawned next to `boids_amount` prey. */
+    pub amount: u64,
+    pub max_speed: f32,
+    pub mass: f32,
+    pub view_distance: f32,
+    /* Prey closer than this to a predator is caught. */
+    pub catch_radius: f32,
+    /* remove or respawn the caught prey. */
+    pub on_catch: CatchAction,
+    pub behaviours: Vec<BehaviourConfig>,
+}
+
+impl Default for PredatorConfig {
+    fn default() -> Self {
+        Self {
+            amount: 0,
+            max_speed: PREDATOR_MAX_SPEED,
+            mass: BOID_MASS,
+            view_distance: PREDATOR_VIEW_DISTANCE,
+            catch_radius: CATCH_RADIUS,
+            on_catch: CatchAction::default(),
+            behaviours: default_predator_behaviours(),
+        }
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            screen_size: SCREEN_SIZE,
+            view_port_size: VIEW_PORT_SIZE,
+            boids_amount: BOIDS_AMOUNT,
+            tick_rate: TICK_RATE,
+            frame_rate: FRAME_RATE,
+            max_boid_speed: MAX_BOID_SPEED,
+            max_boid_force: MAX_BOID_FORCE,
+            mass: BOID_MASS,
+            integrator: Integrator::default(),
+            view_distance: VIEW_DISTANCE,
+            view_angle: VIEW_ANGLE,
+            occlusion: false,
+            bound_margin: 100.0,
+            steering: CombineMode::default(),
+            behaviours: default_behaviours(),
+            parallel: true,
+            seed: None,
+            spatial_index: SpatialIndexKind::default(),
+            predator: PredatorConfig::default(),
+            obstacles: Vec::new(),
+        }
+    }
+}
+
+impl SimulationConfig {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(e.to_string()))?;
+        let config: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => {
+                toml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            Some("ron") => {
+                ron::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            _ => return Err(ConfigError::UnknownFormat(path.display().to_string())),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /*
+     * Overrides a single value, `key` is the field name as written in the config file.
+     * Vectors are given as `x,y`. Behaviours are addressed by name, as in
+     * `behaviours.separation.weight` or `behaviours.separation.radius` for a param.
+     * Predator settings are prefixed with `predator.`, like `predator.max_speed`
+     * or `predator.behaviours.pursue.weight`.
+     */
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "screen_size" => self.screen_size = parse_vector("screen_size", value)?,
+            "view_port_size" => self.view_port_size = parse_vector("view_port_size", value)?,
+            "boids_amount" => self.boids_amount = parse_value("boids_amount", value)?,
+            "tick_rate" => self.tick_rate = parse_value("tick_rate", value)?,
+            "frame_rate" => self.frame_rate = parse_value("frame_rate", value)?,
+            "max_boid_speed" => self.max_boid_speed = parse_value("max_boid_speed", value)?,
+            "max_boid_force" => self.max_boid_force = parse_value("max_boid_force", value)?,
+            "mass" => self.mass = parse_value("mass", value)?,
+            "integrator" => self.integrator = parse_value("integrator", value)?,
+            "view_distance" => self.view_distance = parse_value("view_distance", value)?,
+            "view_angle" => self.view_angle = parse_value("view_angle", value)?,
+            "occlusion" => self.occlusion = parse_value("occlusion", value)?,
+            "bound_margin" => self.bound_margin = parse_value("bound_margin", value)?,
+            "parallel" => self.parallel = parse_value("parallel", value)?,
+            "seed" => self.seed = Some(parse_value("seed", value)?),
+            "spatial_index" => self.spatial_index = parse_value("spatial_index", value)?,
+            "steering" => self.steering = parse_value("steering", value)?,
+            "predator.amount" => self.predator.amount = parse_value("predator.amount", value)?,
+            "predator.max_speed" => {
+                self.predator.max_speed = parse_value("predator.max_speed", value)?
+            }
+            "predator.mass" => self.predator.mass = parse_value("predator.mass", value)?,
+            "predator.view_distance" => {
+                self.predator.view_distance = parse_value("predator.view_distance", value)?
+            }
+            "predator.catch_radius" => {
+                self.predator.catch_radius = parse_value("predator.catch_radius", value)?
+            }
+            "predator.on_catch" => {
+                self.predator.on_catch = parse_value("predator.on_catch", value)?
+            }
+            _ => {
+                let (species, rest) = match key.strip_prefix("predator.") {
+                    Some(rest) => (Species::Predator, rest),
+                    None => (Species::Prey, key),
+                };
+                match rest
+                    .strip_prefix("behaviours.")
+                    .and_then(|k| k.split_once('.'))
+                {
+                    Some((name, field)) => self.set_behaviour(species, name, field, value)?,
+                    None => return Err(ConfigError::UnknownKey(key.to_string())),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_behaviour(
+        &mut self,
+        species: Species,
+        name: &str,
+        field: &str,
+        value: &str,
+    ) -> Result<(), ConfigError> {
+        let behaviour = self
+            .behaviours_of_mut(species)
+            .iter_mut()
+            .find(|b| b.name == name)
+            .ok_or_else(|| ConfigError::UnknownBehaviour(name.to_string()))?;
+        match field {
+            "weight" => behaviour.weight = parse_value("behaviours", value)?,
+            "enabled" => behaviour.enabled = parse_value("behaviours", value)?,
+            "probability" => behaviour.probability = parse_value("behaviours", value)?,
+            "key" => behaviour.key = Some(value.to_string()),
+            /* Checked against the behaviour when the pipeline is built. */
+            param => behaviour.params.set(param, value),
+        }
+        Ok(())
+    }
+
+    pub fn max_speed(&self, species: Species) -> f32 {
+        match species {
+            Species::Prey => self.max_boid_speed,
+            Species::Predator => self.predator.max_speed,
+        }
+    }
+
+    pub fn mass(&self, species: Species) -> f32 {
+        match species {
+            Species::Prey => self.mass,
+            Species::Predator => self.predator.mass,
+        }
+    }
+
+    pub fn view_distance(&self, species: Species) -> f32 {
+        match species {
+            Species::Prey => self.view_distance,
+            Species::Predator => self.predator.view_distance,
+        }
+    }
+
+    pub fn behaviours_of(&self, species: Species) -> &[BehaviourConfig] {
+        match species {
+            Species::Prey => &self.behaviours,
+            Species::Predator => &self.predator.behaviours,
+        }
+    }
+
+    pub fn behaviours_of_mut(&mut self, species: Species) -> &mut Vec<BehaviourConfig> {
+        match species {
+            Species::Prey => &mut self.behaviours,
+            Species::Predator => &mut self.predator.behaviours,
+        }
+    }
+
+    /* A behaviour of the prey, see `behaviours_of` for the predators'. */
+    pub fn behaviour(&self, name: &str) -> Option<&BehaviourConfig> {
+        self.behaviours.iter().find(|b| b.name == name)
+    }
+
+    pub fn behaviour_mut(&mut self, name: &str) -> Option<&mut BehaviourConfig> {
+        self.behaviours.iter_mut().find(|b| b.name == name)
+    }
+
+    /* Same as `set`, but takes the `key=value` form used on the command line. */
+    pub fn set_from_str(&mut self, assignment: &str) -> Result<(), ConfigError> {
+        match assignment.split_once('=') {
+            Some((key, value)) => self.set(key.trim(), value.trim()),
+            None => Err(ConfigError::Parse(format!(
+                "expected key=value, got {}",
+                assignment
+            ))),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.screen_size.x == 0 || self.screen_size.y == 0 {
+            return Err(invalid("screen_size", "must not be zero-sized"));
+        }
+        if !(self.view_port_size.x > 0.0 && self.view_port_size.y > 0.0) {
+            return Err(invalid("view_port_size", "must be positive"));
+        }
+        let positive = [
+            ("tick_rate", self.tick_rate),
+            ("max_boid_speed", self.max_boid_speed),
+            ("max_boid_force", self.max_boid_force),
+            ("mass", self.mass),
+            ("view_distance", self.view_distance),
+            ("predator.max_speed", self.predator.max_speed),
+            ("predator.mass", self.predator.mass),
+            ("predator.view_distance", self.predator.view_distance),
+        ];
+        for (key, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(invalid(key, "must be a positive number"));
+            }
+        }
+        if !(1..=200).contains(&self.frame_rate) {
+            return Err(invalid("frame_rate", "must be in 1..=200"));
+        }
+        if !(self.view_angle > 0.0 && self.view_angle <= 360.0) {
+            return Err(invalid(
+                "view_angle",
+                "must be more than 0 and at most 360 degrees",
+            ));
+        }
+        if !(self.bound_margin.is_finite() && self.bound_margin >= 0.0) {
+            return Err(invalid("bound_margin", "must not be negative"));
+        }
+        if !(self.predator.catch_radius.is_finite() && self.predator.catch_radius >= 0.0) {
+            return Err(invalid("predator.catch_radius", "must not be negative"));
+        }
+        for species in Species::ALL {
+            let behaviours = self.behaviours_of(species);
+            for (i, behaviour) in behaviours.iter().enumerate() {
+                if !(behaviour.weight.is_finite() && behaviour.weight >= 0.0) {
+                    return Err(invalid_behaviour(behaviour, "weight must not be negative"));
+                }
+                if !(0.0..=1.0).contains(&behaviour.probability) {
+                    return Err(invalid_behaviour(behaviour, "probability must be in 0..=1"));
+                }
+                if behaviours[..i].iter().any(|b| b.name == behaviour.name) {
+                    return Err(invalid_behaviour(behaviour, "is in the pipeline twice"));
+                }
+            }
+        }
+        for obstacle in &self.obstacles {
+            obstacle.validate().map_err(|e| invalid("obstacles", &e))?;
+        }
+        if self.bound_margin * 2.0 >= self.view_port_size.x.min(self.view_port_size.y) {
+            return Err(invalid(
+                "bound_margin",
+                "leaves no room inside view_port_size",
+            ));
+        }
+        Ok(())
+    }
+
+    /* Seconds one simulation step takes. */
+    pub fn time_step(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    pub fn view_port(&self) -> Region {
+        Region::new(Vector2::zero(), self.view_port_size)
+    }
+
+    /* Area inside which BoundBehaviour leaves boids alone. */
+    pub fn bound_region(&self) -> Region {
+        Region::new(
+            Vector2::new(self.bound_margin, self.bound_margin),
+            self.view_port_size - self.bound_margin,
+        )
+    }
+}
+
+fn invalid(key: &'static str, reason: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key,
+        reason: reason.to_string(),
+    }
+}
+
+fn invalid_behaviour(behaviour: &BehaviourConfig, reason: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: "behaviours",
+        reason: format!("{}: {}", behaviour.name, reason),
+    }
+}
+
+fn parse_value<T: FromStr>(key: &'static str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| ConfigError::InvalidValue {
+            key,
+            reason: format!("{} ({})", value, e),
+        })
+}
+
+fn parse_vector<T: FromStr + Copy>(
+    key: &'static str,
+    value: &str,
+) -> Result<Vector2<T>, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    match value.split_once(',') {
+        Some((x, y)) => Ok(Vector2 {
+            x: parse_value(key, x.trim())?,
+            y: parse_value(key, y.trim())?,
+        }),
+        None => Err(invalid(key, "expected x,y")),
+    }
+}
+
+#[test]
+fn default_config_is_valid() {
+    assert!(SimulationConfig::default().validate().is_ok());
+}
+
+#[test]
+fn set_overrides_values() {
+    let mut config = SimulationConfig::default();
+    config.set_from_str("max_boid_speed=7.5").unwrap();
+    config.set_from_str("tick_rate=30").unwrap();
+    config.set_from_str("view_port_size = 1024,768").unwrap();
+    config.set_from_str("parallel=false").unwrap();
+    config.set_from_str("spatial_index=kd_tree").unwrap();
+    config.set_from_str("view_angle=180").unwrap();
+    config.set_from_str("steering=prioritized").unwrap();
+    config.set_from_str("integrator=rk4").unwrap();
+    config.set_from_str("predator.mass=2.5").unwrap();
+    config
+        .set_from_str("behaviours.separation.weight=0.5")
+        .unwrap();
+    config
+        .set_from_str("behaviours.separation.kernel=gaussian")
+        .unwrap();
+    config
+        .set_from_str("behaviours.bound.enabled=false")
+        .unwrap();
+    config.set_from_str("predator.amount=3").unwrap();
+    config.set_from_str("predator.on_catch=remove").unwrap();
+    config
+        .set_from_str("predator.behaviours.pursue.weight=0.2")
+        .unwrap();
+    assert_eq!(config.max_boid_speed, 7.5);
+    assert_eq!(config.time_step(), 1.0 / 30.0);
+    assert!(!config.parallel);
+    assert_eq!(config.spatial_index, SpatialIndexKind::KdTree);
+    assert_eq!(config.view_angle, 180.0);
+    assert_eq!(config.steering, CombineMode::Prioritized);
+    assert_eq!(config.integrator, Integrator::Rk4);
+    assert_eq!(config.mass(Species::Predator), 2.5);
+    assert_eq!(config.mass(Species::Prey), BOID_MASS);
+    let separation = config.behaviour("separation").unwrap();
+    assert_eq!(separation.weight, 0.5);
+    assert_eq!(
+        separation.params.get("kernel", String::new()).unwrap(),
+        "gaussian"
+    );
+    assert!(!config.behaviour("bound").unwrap().enabled);
+    assert_eq!(config.predator.amount, 3);
+    assert_eq!(config.predator.on_catch, CatchAction::Remove);
+    assert_eq!(config.behaviours_of(Species::Predator)[0].weight, 0.2);
+    assert_eq!(config.behaviour("pursue"), None);
+    assert!(config.set_from_str("predator.on_catch=eat").is_err());
+    assert!(config.set_from_str("behaviours.flocking.weight=1").is_err());
+    assert!(config
+        .set_from_str("behaviours.align.weight=heavy")
+        .is_err());
+    assert_eq!(config.view_port_size, Vector2::new(1024.0, 768.0));
+    assert!(config.set_from_str("no_such_key=1").is_err());
+    assert!(config.set_from_str("integrator=leapfrog").is_err());
+    assert!(config.set_from_str("boids_amount=-3").is_err());
+}
+
+#[test]
+fn validation_rejects_nonsense() {
+    let invalid_configs = [
+        SimulationConfig {
+            max_boid_speed: -1.0,
+            ..Default::default()
+        },
+        SimulationConfig {
+            view_port_size: Vector2::new(0.0, 600.0),
+            ..Default::default()
+        },
+        SimulationConfig {
+            screen_size: Vector2::new(800, 0),
+            ..Default::default()
+        },
+        SimulationConfig {
+            view_distance: f32::NAN,
+            ..Default::default()
+        },
+        SimulationConfig {
+            view_angle: 0.0,
+            ..Default::default()
+        },
+        SimulationConfig {
+            view_angle: 400.0,
+            ..Default::default()
+        },
+        SimulationConfig {
+            tick_rate: 0.0,
+            ..Default::default()
+        },
+        SimulationConfig {
+            frame_rate: 500,
+            ..Default::default()
+        },
+        SimulationConfig {
+            mass: 0.0,
+            ..Default::default()
+        },
+        SimulationConfig {
+            behaviours: vec![BehaviourConfig::new("align", -1.0)],
+            ..Default::default()
+        },
+        SimulationConfig {
+            obstacles: vec![Obstacle::Circle {
+                center: Vector2::new(10.0, 10.0),
+                radius: -1.0,
+            }],
+            ..Default::default()
+        },
+        SimulationConfig {
+            predator: PredatorConfig {
+                max_speed: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        SimulationConfig {
+            predator: PredatorConfig {
+                behaviours: vec![
+                    BehaviourConfig::new("pursue", 1.0),
+                    BehaviourConfig::new("pursue", 2.0),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        SimulationConfig {
+            behaviours: vec![
+                BehaviourConfig::new("align", 1.0),
+                BehaviourConfig::new("align", 2.0),
+            ],
+            ..Default::default()
+        },
+    ];
+    for config in invalid_configs {
+        assert!(config.validate().is_err(), "{:?} should be invalid", config);
+    }
+}
+
+#[test]
+fn parse_toml_and_ron() {
+    let from_toml: SimulationConfig =
+        toml::from_str("max_boid_speed = 2.0\nview_port_size = { x = 400.0, y = 300.0 }\n")
+            .unwrap();
+    assert_eq!(from_toml.max_boid_speed, 2.0);
+    assert_eq!(from_toml.view_port_size, Vector2::new(400.0, 300.0));
+    assert_eq!(from_toml.boids_amount, BOIDS_AMOUNT);
+
+    let from_ron: SimulationConfig =
+        ron::from_str("(boids_amount: 12, view_distance: 40.0)").unwrap();
+    assert_eq!(from_ron.boids_amount, 12);
+    assert_eq!(from_ron.view_distance, 40.0);
+
+    assert!(toml::from_str::<SimulationConfig>("max_speed = 2.0").is_err());
+
+    let pipeline: SimulationConfig = toml::from_str(
+        "steering = \"dithered\"\n\
+         [[behaviours]]\nname = \"separation\"\nweight = 0.1\nprobability = 0.5\n\
+         params = { radius = 12, kernel = \"linear\" }\n",
+    )
+    .unwrap();
+    assert_eq!(pipeline.steering, CombineMode::Dithered);
+    assert_eq!(pipeline.behaviours.len(), 1);
+    assert_eq!(
+        pipeline.behaviours[0].params.get("radius", 0.0f32),
+        Ok(12.0)
+    );
+    assert!(pipeline.behaviours[0].enabled);
+
+    let obstacles: SimulationConfig = toml::from_str(
+        "[[obstacles]]\ncircle = { center = { x = 100.0, y = 100.0 }, radius = 20.0 }\n\
+         [[obstacles]]\npolygon = { vertices = [{ x = 0.0, y = 0.0 }, { x = 10.0, y = 0.0 }, \
+         { x = 0.0, y = 10.0 }] }\n",
+    )
+    .unwrap();
+    assert_eq!(obstacles.obstacles.len(), 2);
+    assert!(obstacles.validate().is_ok());
+}
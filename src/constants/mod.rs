@@ -1,9 +1,4 @@
-use std::{fmt, str::FromStr};
-
-use crate::{
-    logic::behaviour::traits::BorderBehaviourE,
-    math::vec::{V2f32, V2u32, Vector2},
-};
+use crate::math::vec::{V2f32, V2u32, Vector2};
 
 pub const SCREEN_SIZE: V2u32 = Vector2::new(800, 600);
 pub const MULTIP_VIEW: f32 = 1.0;
@@ -12,79 +7,43 @@ pub const VIEW_PORT_SIZE: V2f32 = Vector2::new(
     SCREEN_SIZE.y as f32 * MULTIP_VIEW,
 );
 pub const BOID_SIZE: i16 = 4;
-pub const VIEW_DISTANCE: f32 = BOID_SIZE as f32 * 20.0 as f32;
-
-use std::cell::RefCell;
-
-thread_local!(pub static BORDER_BEHAVIOUR: RefCell<BorderBehaviourE> = RefCell::new(BorderBehaviourE::GoThrough));
-
-pub const MAX_BOID_SPEED: f32 = 4.1;
-pub const MAX_BOID_FORCE: f32 = 0.201;
-pub const UPDATE_EVERY_TICK: u8 = 1;
+/* How close a boid's centre gets to an obstacle's outline. */
+pub const BOID_RADIUS: f32 = BOID_SIZE as f32 / 2.0;
+pub const VIEW_DISTANCE: f32 = BOID_SIZE as f32 * 20.0_f32;
+pub const VIEW_ANGLE: f32 = 270.0;
+
+/* Simulation steps per second, independent of how often the window is drawn. */
+pub const TICK_RATE: f32 = 60.0;
+pub const FRAME_RATE: u32 = 60;
+/* Speeds are in units per second and forces in units per second squared. */
+pub const MAX_BOID_SPEED: f32 = 4.1 * TICK_RATE;
+pub const MAX_BOID_FORCE: f32 = 0.201 * TICK_RATE * TICK_RATE;
+/* Behaviours ask for a change of velocity, which is made within this many seconds. */
+pub const STEERING_TIME: f32 = 1.0 / TICK_RATE;
+pub const SPAWN_SPEED: f32 = TICK_RATE;
+/* Steering forces are divided by the mass, heavier boids turn slower. */
+pub const BOID_MASS: f32 = 1.0;
 pub const BOIDS_AMOUNT: u64 = 30;
-pub const MAX_BOID_IN_AREA: usize = (BOIDS_AMOUNT as usize * 1) / 100 as usize + 1;
+pub const PREDATOR_MAX_SPEED: f32 = 4.6 * TICK_RATE;
+pub const PREDATOR_VIEW_DISTANCE: f32 = VIEW_DISTANCE * 1.5;
+pub const CATCH_RADIUS: f32 = BOID_SIZE as f32 * 2.0;
+pub const MAX_BOID_IN_AREA: usize = (BOIDS_AMOUNT as usize) / 100_usize + 1;
+pub const MAX_QUAD_TREE_DEPTH: u32 = 16;
 
 use bitflags::bitflags;
+#[cfg(feature = "render")]
 use sdl2::pixels::Color;
 
-bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    pub struct BehaviourEnabled: u32 {
-        const  ALL_DISABLED = 0b00000000;
-        const ALLIGN = 0b00000001;
-        const COHESION = 0b00000010;
-        const SEPERATE = 0b00000100;
-        const  BOUND= 0b00001000;
-        const ALL_ENABLED = 0b00001111;
-    }
-}
-pub struct BehaviourConsts;
-impl BehaviourConsts {
-    pub const ALLIGN_FACTOR: f32 = 0.03;
-    pub const COHESION_FACTOR: f32 = 0.002;
-    pub const SEPERATE_FACTOR: f32 = 0.03;
-    pub const BOUND_FACTOR: f32 = 0.3;
-}
-
-pub static mut BEHAVIOUR_ENABLED: BehaviourEnabled = BehaviourEnabled::ALL_ENABLED;
-
-impl fmt::Display for BehaviourEnabled {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.0, f)
-    }
-}
-
-impl FromStr for BehaviourEnabled {
-    type Err = bitflags::parser::ParseError;
-
-    fn from_str(behaviour_enabled: &str) -> Result<Self, Self::Err> {
-        Ok(Self(behaviour_enabled.parse()?))
-    }
-}
-
-pub struct IdIterator {
-    i: usize,
-}
-
-impl IdIterator {
-    const MAX_VALUE: usize = usize::MAX;
-    pub fn get_next(&mut self) -> usize {
-        if self.i + 1 == Self::MAX_VALUE {
-            self.i = 0;
-        }
-        let id = self.i;
-        self.i += 1;
-        return id;
-    }
-    pub fn new() -> Self {
-        Self { i: 0 }
-    }
-}
-thread_local!(pub static BOID_ID_ITERATOR: RefCell<IdIterator> = RefCell::new(IdIterator::new()));
-
-pub const BOID_COLOR: Color = Color::BLUE;
+/* Red, green and blue, kept in the Renderable component of every boid. */
+pub const BOID_COLOR: [u8; 3] = [0, 0, 255];
+pub const PREDATOR_COLOR: [u8; 3] = [255, 0, 255];
+#[cfg(feature = "render")]
+pub const OBSTACLE_COLOR: Color = Color::GREEN;
+#[cfg(feature = "render")]
 pub const REGION_COLOR: Color = Color::WHITE;
+#[cfg(feature = "render")]
 pub const VIEW_COLOR: Color = Color::RED;
+#[cfg(feature = "render")]
 pub const QUAD_TREE_COLOR: Color = Color::YELLOW;
 
 pub mod types;
@@ -99,4 +58,3 @@ bitflags! {
         const ALL_ENABLED = 0b111;
     }
 }
-thread_local!(pub static DRAW_PRIMITIVES: RefCell<DrawPrimitives> = RefCell::new(DrawPrimitives::ALL_DISABLED));
@@ -0,0 +1,91 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+};
+
+use super::{
+    component_type::{ComponentType, MAX_COMPONENTS},
+    entity::Entity,
+    sparse_set::{ComponentStorage, SparseSet},
+    EcsError,
+};
+
+/* Anything plain enough to be shared between the threads of a system. */
+pub trait Component: Send + Sync + 'static {}
+impl<T: Send + Sync + 'static> Component for T {}
+
+/* One SparseSet per registered component type, indexed by its ComponentType. */
+#[derive(Default)]
+pub struct ComponentManager {
+    types: HashMap<TypeId, ComponentType>,
+    storages: Vec<Box<dyn ComponentStorage>>,
+}
+
+impl ComponentManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Registering a type twice gives the same ComponentType. */
+    pub fn register<T: Component>(&mut self) -> Result<ComponentType, EcsError> {
+        if let Ok(component) = self.component_type::<T>() {
+            return Ok(component);
+        }
+        if self.storages.len() >= MAX_COMPONENTS as usize {
+            return Err(EcsError::TooManyComponents);
+        }
+        let component = self.storages.len() as ComponentType;
+        self.types.insert(TypeId::of::<T>(), component);
+        self.storages.push(Box::new(SparseSet::<T>::new()));
+        Ok(component)
+    }
+
+    pub fn component_type<T: Component>(&self) -> Result<ComponentType, EcsError> {
+        self.types
+            .get(&TypeId::of::<T>())
+            .copied()
+            .ok_or(EcsError::UnknownComponent(type_name::<T>()))
+    }
+
+    pub fn storage<T: Component>(&self) -> Result<&SparseSet<T>, EcsError> {
+        let component = self.component_type::<T>()?;
+        Ok(self.storages[component as usize]
+            .as_any()
+            .downcast_ref()
+            .expect("storage of another type"))
+    }
+
+    pub fn storage_mut<T: Component>(&mut self) -> Result<&mut SparseSet<T>, EcsError> {
+        let component = self.component_type::<T>()?;
+        Ok(self.storages[component as usize]
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storage of another type"))
+    }
+
+    /* Drops every component of `entity`. */
+    pub fn entity_destroyed(&mut self, entity: Entity) {
+        for storage in &mut self.storages {
+            storage.remove_entity(entity);
+        }
+    }
+}
+
+#[test]
+fn components_are_stored_by_type() {
+    let mut components = ComponentManager::new();
+    assert_eq!(components.register::<u32>(), Ok(0));
+    assert_eq!(components.register::<&str>(), Ok(1));
+    assert_eq!(components.register::<u32>(), Ok(0));
+    assert!(matches!(
+        components.component_type::<f32>(),
+        Err(EcsError::UnknownComponent(_))
+    ));
+
+    components.storage_mut::<u32>().unwrap().insert(4, 40);
+    components.storage_mut::<&str>().unwrap().insert(4, "four");
+    assert_eq!(components.storage::<u32>().unwrap().get(4), Some(&40));
+    components.entity_destroyed(4);
+    assert!(components.storage::<u32>().unwrap().is_empty());
+    assert!(components.storage::<&str>().unwrap().is_empty());
+}
@@ -1,2 +1,3 @@
+/* Bit of a component in a `Signature`, handed out in registration order. */
 pub type ComponentType = u8;
-pub const MAX_COMPONENTS: ComponentType = ComponentType::MAX;
+pub const MAX_COMPONENTS: ComponentType = 64;
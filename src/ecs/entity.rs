@@ -1,2 +1,3 @@
+/* Ids of destroyed entities are handed out again, oldest first. */
 pub type Entity = u32;
 pub const MAX_ENTITIES: Entity = Entity::MAX;
@@ -3,32 +3,79 @@ use std::collections::VecDeque;
 use super::{
     entity::{Entity, MAX_ENTITIES},
     signature::Signature,
+    EcsError,
 };
 
+/*
+ * Hands out entities and remembers their signatures. Storage grows with the
+ * entities actually created, ids of destroyed ones are reused oldest first so
+ * a just destroyed id isn't handed out again right away.
+ */
 pub struct EntityManager {
     avail_entities: VecDeque<Entity>,
-    signatures: [Signature; MAX_ENTITIES as usize],
+    /* None while the entity is destroyed. */
+    signatures: Vec<Option<Signature>>,
     living_count: usize,
 }
+impl Default for EntityManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EntityManager {
     pub fn new() -> Self {
-        let e: VecDeque<Entity> = (0..=MAX_ENTITIES)
-            .into_iter()
-            .map(|id| id as Entity)
-            .collect();
-        let signatures = [(); MAX_ENTITIES as usize].map(|_| Signature::empty());
         EntityManager {
             living_count: usize::MIN,
-            avail_entities: e,
-            signatures,
+            avail_entities: VecDeque::new(),
+            signatures: Vec::new(),
         }
     }
-    pub fn create_entity(&mut self) -> Result<Entity, &'static str> {
+    pub fn create_entity(&mut self) -> Result<Entity, EcsError> {
+        let id = match self.avail_entities.pop_front() {
+            Some(id) => id,
+            None if self.signatures.len() < MAX_ENTITIES as usize => {
+                self.signatures.push(None);
+                (self.signatures.len() - 1) as Entity
+            }
+            None => return Err(EcsError::TooManyEntities),
+        };
+        self.signatures[id as usize] = Some(Signature::empty());
         self.living_count += 1;
-        match self.avail_entities.pop_back() {
-            Some(id) => Ok(id),
-            None => Err("cannot create entity"),
-        }
+        Ok(id)
+    }
+    pub fn destroy_entity(&mut self, entity: Entity) -> Result<(), EcsError> {
+        self.signature(entity)?;
+        self.signatures[entity as usize] = None;
+        self.avail_entities.push_back(entity);
+        self.living_count -= 1;
+        Ok(())
+    }
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.signature(entity).is_ok()
+    }
+    pub fn signature(&self, entity: Entity) -> Result<Signature, EcsError> {
+        self.signatures
+            .get(entity as usize)
+            .copied()
+            .flatten()
+            .ok_or(EcsError::DeadEntity(entity))
+    }
+    pub fn set_signature(&mut self, entity: Entity, signature: Signature) -> Result<(), EcsError> {
+        self.signature(entity)?;
+        self.signatures[entity as usize] = Some(signature);
+        Ok(())
+    }
+    pub fn living_count(&self) -> usize {
+        self.living_count
+    }
+    /* Living entities in id order. */
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.signatures
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.is_some())
+            .map(|(id, _)| id as Entity)
     }
 }
 
@@ -36,5 +83,25 @@ impl EntityManager {
 fn create_entity() {
     let mut em = EntityManager::new();
     let i = em.create_entity().unwrap();
-    assert_eq!(i, MAX_ENTITIES as Entity - 1);
+    assert_eq!(i, 0);
+    assert_eq!(em.signature(i), Ok(Signature::empty()));
+}
+
+#[test]
+fn destroyed_entities_are_recycled_oldest_first() {
+    let mut em = EntityManager::new();
+    let entities: Vec<Entity> = (0..4).map(|_| em.create_entity().unwrap()).collect();
+    assert_eq!(entities, [0, 1, 2, 3]);
+    em.destroy_entity(2).unwrap();
+    em.destroy_entity(0).unwrap();
+    assert_eq!(em.destroy_entity(0), Err(EcsError::DeadEntity(0)));
+    assert_eq!(em.living_count(), 2);
+    assert!(!em.is_alive(2));
+    assert_eq!(em.entities().collect::<Vec<_>>(), [1, 3]);
+    assert!(em.set_signature(2, Signature::empty().with(1)).is_err());
+
+    assert_eq!(em.create_entity(), Ok(2));
+    assert_eq!(em.create_entity(), Ok(0));
+    assert_eq!(em.create_entity(), Ok(4));
+    assert_eq!(em.living_count(), 5);
 }
@@ -1,4 +1,39 @@
+use std::fmt;
+
+pub mod component_mgr;
 pub mod component_type;
 pub mod entity;
 pub mod entity_mgr;
 pub mod signature;
+pub mod sparse_set;
+pub mod system;
+pub mod world;
+
+use entity::Entity;
+
+#[derive(Debug, PartialEq)]
+pub enum EcsError {
+    TooManyEntities,
+    TooManyComponents,
+    DeadEntity(Entity),
+    UnknownComponent(&'static str),
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EcsError::TooManyEntities => write!(f, "no entity ids left"),
+            EcsError::TooManyComponents => {
+                write!(
+                    f,
+                    "no more than {} component types",
+                    component_type::MAX_COMPONENTS
+                )
+            }
+            EcsError::DeadEntity(entity) => write!(f, "entity {} is not alive", entity),
+            EcsError::UnknownComponent(name) => write!(f, "component {} is not registered", name),
+        }
+    }
+}
+
+impl std::error::Error for EcsError {}
@@ -1,16 +1,70 @@
-use super::component_type::MAX_COMPONENTS;
+use std::fmt;
 
+use super::component_type::{ComponentType, MAX_COMPONENTS};
+
+/* The component types an entity has, or a system needs, one bit each. */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct Signature {
-    v: [bool; MAX_COMPONENTS as usize],
+    v: u64,
 }
 
 impl Signature {
-    pub fn new(v: [bool; MAX_COMPONENTS as usize]) -> Self {
-        Self { v }
-    }
     pub fn empty() -> Self {
-        Self {
-            v: [false; MAX_COMPONENTS as usize],
-        }
+        Self { v: 0 }
+    }
+
+    pub fn with(mut self, component: ComponentType) -> Self {
+        self.set(component);
+        self
+    }
+
+    pub fn set(&mut self, component: ComponentType) {
+        debug_assert!(component < MAX_COMPONENTS);
+        self.v |= 1 << component;
+    }
+
+    pub fn reset(&mut self, component: ComponentType) {
+        self.v &= !(1 << component);
+    }
+
+    pub fn has(&self, component: ComponentType) -> bool {
+        self.v & (1 << component) != 0
+    }
+
+    /* Every component of `other` is in here too. */
+    pub fn contains(&self, other: Signature) -> bool {
+        self.v & other.v == other.v
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v == 0
+    }
+}
+
+impl FromIterator<ComponentType> for Signature {
+    fn from_iter<I: IntoIterator<Item = ComponentType>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::empty(), Self::with)
     }
 }
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Signature({:#b})", self.v)
+    }
+}
+
+#[test]
+fn signatures_match_their_subsets() {
+    let position_velocity: Signature = [0, 1].into_iter().collect();
+    let mut boid = position_velocity.with(5).with(63);
+    assert!(boid.has(63) && !boid.has(2));
+    assert!(boid.contains(position_velocity));
+    assert!(!position_velocity.contains(boid));
+    assert!(boid.contains(Signature::empty()));
+    boid.reset(1);
+    assert!(!boid.contains(position_velocity));
+    boid.reset(0);
+    boid.reset(5);
+    boid.reset(63);
+    assert!(boid.is_empty());
+}
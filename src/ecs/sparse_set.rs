@@ -0,0 +1,142 @@
+use std::any::Any;
+
+use super::entity::Entity;
+
+/*
+ * Components of one type. `data` is packed, so walking all of them is as
+ * fast as a Vec; `sparse` finds the place of an entity in it. Removing moves
+ * the last component into the hole, so the order changes.
+ */
+pub struct SparseSet<T> {
+    sparse: Vec<Option<u32>>,
+    entities: Vec<Entity>,
+    data: Vec<T>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn index(&self, entity: Entity) -> Option<usize> {
+        self.sparse
+            .get(entity as usize)
+            .copied()
+            .flatten()
+            .map(|i| i as usize)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.index(entity).is_some()
+    }
+
+    /* Returns the component `entity` had before. */
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(i) = self.index(entity) {
+            return Some(std::mem::replace(&mut self.data[i], component));
+        }
+        if self.sparse.len() <= entity as usize {
+            self.sparse.resize(entity as usize + 1, None);
+        }
+        self.sparse[entity as usize] = Some(self.data.len() as u32);
+        self.entities.push(entity);
+        self.data.push(component);
+        None
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let i = self.index(entity)?;
+        self.sparse[entity as usize] = None;
+        self.entities.swap_remove(i);
+        if let Some(&moved) = self.entities.get(i) {
+            self.sparse[moved as usize] = Some(i as u32);
+        }
+        Some(self.data.swap_remove(i))
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.index(entity).map(|i| &self.data[i])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.index(entity).map(|i| &mut self.data[i])
+    }
+
+    /* Entities in the same order as `components`. */
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn components(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn components_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(&self.data)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.entities.iter().copied().zip(&mut self.data)
+    }
+}
+
+/* A SparseSet of any type, so the ComponentManager can keep them side by side. */
+pub trait ComponentStorage: Send + Sync {
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Send + Sync + 'static> ComponentStorage for SparseSet<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[test]
+fn removing_keeps_the_set_packed() {
+    let mut set = SparseSet::new();
+    assert_eq!(set.insert(3, "a"), None);
+    set.insert(10, "b");
+    set.insert(0, "c");
+    assert_eq!(set.insert(10, "B"), Some("b"));
+    assert_eq!(set.len(), 3);
+
+    assert_eq!(set.remove(3), Some("a"));
+    assert_eq!(set.remove(3), None);
+    assert_eq!(set.entities(), [0, 10]);
+    assert_eq!(set.components(), ["c", "B"]);
+    assert_eq!(set.get(10), Some(&"B"));
+    assert_eq!(set.get(7), None);
+    *set.get_mut(0).unwrap() = "C";
+    assert_eq!(set.iter().collect::<Vec<_>>(), [(0, &"C"), (10, &"B")]);
+    assert!(!set.contains(3));
+}
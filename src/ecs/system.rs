@@ -0,0 +1,84 @@
+use std::collections::BTreeSet;
+
+use super::{entity::Entity, signature::Signature, world::World};
+
+/*
+ * Logic run over every entity that has all the components of the signature
+ * it was registered with. `entities` are in id order and don't change while
+ * the system runs, even if it adds or removes components.
+ */
+pub trait System: Send + Sync {
+    fn run(&mut self, entities: &[Entity], world: &mut World);
+}
+
+pub type SystemId = usize;
+
+struct SystemEntry {
+    signature: Signature,
+    entities: BTreeSet<Entity>,
+    /* Taken out while the system runs, it gets the world mutably. */
+    system: Option<Box<dyn System>>,
+}
+
+/* Keeps for every system the entities it runs on, in step with their signatures. */
+#[derive(Default)]
+pub struct SystemManager {
+    systems: Vec<SystemEntry>,
+}
+
+impl SystemManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    /* Systems run in the order they are registered. */
+    pub fn register(
+        &mut self,
+        system: Box<dyn System>,
+        signature: Signature,
+        entities: impl Iterator<Item = Entity>,
+    ) -> SystemId {
+        self.systems.push(SystemEntry {
+            signature,
+            entities: entities.collect(),
+            system: Some(system),
+        });
+        self.systems.len() - 1
+    }
+
+    pub fn entities(&self, system: SystemId) -> Vec<Entity> {
+        self.systems[system].entities.iter().copied().collect()
+    }
+
+    pub fn signature_changed(&mut self, entity: Entity, signature: Signature) {
+        for entry in &mut self.systems {
+            if signature.contains(entry.signature) {
+                entry.entities.insert(entity);
+            } else {
+                entry.entities.remove(&entity);
+            }
+        }
+    }
+
+    pub fn entity_destroyed(&mut self, entity: Entity) {
+        for entry in &mut self.systems {
+            entry.entities.remove(&entity);
+        }
+    }
+
+    pub(super) fn take(&mut self, system: SystemId) -> Option<Box<dyn System>> {
+        self.systems[system].system.take()
+    }
+
+    pub(super) fn put_back(&mut self, system: SystemId, taken: Box<dyn System>) {
+        self.systems[system].system = Some(taken);
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use super::{
+    component_mgr::{Component, ComponentManager},
+    component_type::ComponentType,
+    entity::Entity,
+    entity_mgr::EntityManager,
+    signature::Signature,
+    sparse_set::SparseSet,
+    system::{System, SystemId, SystemManager},
+    EcsError,
+};
+
+/*
+ * Entities, their components and the systems run over them. Every change of
+ * components goes through here, so the systems always know their entities.
+ * Resources are single values of a type the systems share, like settings.
+ */
+#[derive(Default)]
+pub struct World {
+    entities: EntityManager,
+    components: ComponentManager,
+    systems: SystemManager,
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_entity(&mut self) -> Result<Entity, EcsError> {
+        self.entities.create_entity()
+    }
+
+    /* Drops its components and takes it out of every system. */
+    pub fn destroy_entity(&mut self, entity: Entity) -> Result<(), EcsError> {
+        self.entities.destroy_entity(entity)?;
+        self.components.entity_destroyed(entity);
+        self.systems.entity_destroyed(entity);
+        Ok(())
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn living_count(&self) -> usize {
+        self.entities.living_count()
+    }
+
+    /* Only needed to build signatures up front, adding a component registers it too. */
+    pub fn register_component<T: Component>(&mut self) -> Result<ComponentType, EcsError> {
+        self.components.register::<T>()
+    }
+
+    pub fn component_type<T: Component>(&self) -> Result<ComponentType, EcsError> {
+        self.components.component_type::<T>()
+    }
+
+    /* Replaces the component of the same type `entity` already had. */
+    pub fn add_component<T: Component>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<(), EcsError> {
+        let mut signature = self.entities.signature(entity)?;
+        let component_type = self.components.register::<T>()?;
+        self.components
+            .storage_mut::<T>()?
+            .insert(entity, component);
+        signature.set(component_type);
+        self.set_signature(entity, signature)
+    }
+
+    pub fn remove_component<T: Component>(
+        &mut self,
+        entity: Entity,
+    ) -> Result<Option<T>, EcsError> {
+        let mut signature = self.entities.signature(entity)?;
+        let component_type = self.components.component_type::<T>()?;
+        let removed = self.components.storage_mut::<T>()?.remove(entity);
+        signature.reset(component_type);
+        self.set_signature(entity, signature)?;
+        Ok(removed)
+    }
+
+    fn set_signature(&mut self, entity: Entity, signature: Signature) -> Result<(), EcsError> {
+        self.entities.set_signature(entity, signature)?;
+        self.systems.signature_changed(entity, signature);
+        Ok(())
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.components.storage::<T>().ok()?.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.components.storage_mut::<T>().ok()?.get_mut(entity)
+    }
+
+    /* All components of a type, to walk them without looking up entities. */
+    pub fn storage<T: Component>(&self) -> Result<&SparseSet<T>, EcsError> {
+        self.components.storage::<T>()
+    }
+
+    pub fn storage_mut<T: Component>(&mut self) -> Result<&mut SparseSet<T>, EcsError> {
+        self.components.storage_mut::<T>()
+    }
+
+    pub fn signature(&self, entity: Entity) -> Result<Signature, EcsError> {
+        self.entities.signature(entity)
+    }
+
+    /* Living entities with every component of `signature`, in id order. */
+    pub fn query(&self, signature: Signature) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.entities().filter(move |&e| {
+            self.entities
+                .signature(e)
+                .is_ok_and(|s| s.contains(signature))
+        })
+    }
+
+    /* Returns the resource of the same type it replaces. */
+    pub fn insert_resource<T: Component>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn remove_resource<T: Component>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn resource<T: Component>(&self) -> Option<&T> {
+        self.resources.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn resource_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.resources.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    /*
+     * Lends the resource out while `f` gets the rest of the world, so a system
+     * can change it and the components at the same time. None without it.
+     */
+    pub fn resource_scope<T: Component, U>(
+        &mut self,
+        f: impl FnOnce(&mut World, &mut T) -> U,
+    ) -> Option<U> {
+        let mut resource = self.remove_resource::<T>()?;
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        Some(result)
+    }
+
+    /* Runs on the entities matching `signature`, now and as they change. */
+    pub fn register_system<S: System + 'static>(
+        &mut self,
+        system: S,
+        signature: Signature,
+    ) -> SystemId {
+        let entities: Vec<Entity> = self.query(signature).collect();
+        self.systems
+            .register(Box::new(system), signature, entities.into_iter())
+    }
+
+    pub fn system_entities(&self, system: SystemId) -> Vec<Entity> {
+        self.systems.entities(system)
+    }
+
+    pub fn run_system(&mut self, system: SystemId) {
+        let entities = self.systems.entities(system);
+        /* A system that runs itself from its own `run` is skipped. */
+        if let Some(mut taken) = self.systems.take(system) {
+            taken.run(&entities, self);
+            self.systems.put_back(system, taken);
+        }
+    }
+
+    /* Every system once, in registration order. */
+    pub fn run_systems(&mut self) {
+        for system in 0..self.systems.len() {
+            self.run_system(system);
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+struct Position(f32);
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+struct Velocity(f32);
+#[cfg(test)]
+struct Tag;
+
+/* Seconds per step, shared as a resource. */
+#[cfg(test)]
+struct TimeStep(f32);
+
+#[cfg(test)]
+struct Movement;
+#[cfg(test)]
+impl System for Movement {
+    fn run(&mut self, entities: &[Entity], world: &mut World) {
+        let dt = world.resource::<TimeStep>().map_or(1.0, |t| t.0);
+        for &e in entities {
+            let velocity = world.get::<Velocity>(e).unwrap().0;
+            world.get_mut::<Position>(e).unwrap().0 += velocity * dt;
+        }
+    }
+}
+
+/* Destroys every entity it sees, to check the world stays consistent meanwhile. */
+#[cfg(test)]
+struct Reaper;
+#[cfg(test)]
+impl System for Reaper {
+    fn run(&mut self, entities: &[Entity], world: &mut World) {
+        for &e in entities {
+            world.destroy_entity(e).unwrap();
+        }
+    }
+}
+
+#[test]
+fn systems_run_on_matching_entities() {
+    let mut world = World::new();
+    let signature: Signature = [
+        world.register_component::<Position>().unwrap(),
+        world.register_component::<Velocity>().unwrap(),
+    ]
+    .into_iter()
+    .collect();
+
+    let moving = world.create_entity().unwrap();
+    world.add_component(moving, Position(1.0)).unwrap();
+    world.add_component(moving, Velocity(2.0)).unwrap();
+    let still = world.create_entity().unwrap();
+    world.add_component(still, Position(5.0)).unwrap();
+    let movement = world.register_system(Movement, signature);
+    assert_eq!(world.system_entities(movement), [moving]);
+
+    /* Components added later count as well. */
+    let late = world.create_entity().unwrap();
+    world.add_component(late, Velocity(-1.0)).unwrap();
+    world.add_component(late, Tag).unwrap();
+    world.add_component(late, Position(0.0)).unwrap();
+    world.run_systems();
+    assert_eq!(world.get::<Position>(moving), Some(&Position(3.0)));
+    assert_eq!(world.get::<Position>(still), Some(&Position(5.0)));
+    assert_eq!(world.get::<Position>(late), Some(&Position(-1.0)));
+    assert_eq!(world.query(signature).collect::<Vec<_>>(), [moving, late]);
+
+    assert!(world.insert_resource(TimeStep(0.5)).is_none());
+    world.run_systems();
+    assert_eq!(world.get::<Position>(moving), Some(&Position(4.0)));
+    world.resource_mut::<TimeStep>().unwrap().0 = 2.0;
+    assert_eq!(world.insert_resource(TimeStep(1.0)).map(|t| t.0), Some(2.0));
+    world.resource_scope(|world, step: &mut TimeStep| {
+        step.0 += world.get::<Position>(moving).unwrap().0;
+    });
+    assert_eq!(world.resource::<TimeStep>().map(|t| t.0), Some(5.0));
+    assert_eq!(world.remove_resource::<TimeStep>().map(|t| t.0), Some(5.0));
+    assert!(world.resource::<TimeStep>().is_none());
+
+    assert_eq!(
+        world.remove_component::<Velocity>(moving),
+        Ok(Some(Velocity(2.0)))
+    );
+    assert_eq!(world.system_entities(movement), [late]);
+    assert!(!world.signature(moving).unwrap().contains(signature));
+}
+
+#[test]
+fn destroyed_entities_leave_no_trace() {
+    let mut world = World::new();
+    let tag: Signature = [world.register_component::<Tag>().unwrap()]
+        .into_iter()
+        .collect();
+    let reaper = world.register_system(Reaper, tag);
+    let entities: Vec<Entity> = (0..3).map(|_| world.create_entity().unwrap()).collect();
+    for &e in &entities {
+        world.add_component(e, Position(e as f32)).unwrap();
+    }
+    world.add_component(entities[1], Tag).unwrap();
+    world.run_systems();
+
+    assert!(!world.is_alive(entities[1]));
+    assert_eq!(world.living_count(), 2);
+    assert_eq!(world.get::<Position>(entities[1]), None);
+    assert_eq!(world.storage::<Position>().unwrap().len(), 2);
+    assert!(world.system_entities(reaper).is_empty());
+    assert_eq!(
+        world.add_component(entities[1], Tag),
+        Err(EcsError::DeadEntity(entities[1]))
+    );
+
+    /* The id comes back without the components of the destroyed entity. */
+    let reused = world.create_entity().unwrap();
+    assert_eq!(reused, entities[1]);
+    assert_eq!(world.signature(reused), Ok(Signature::empty()));
+    assert_eq!(world.get::<Position>(reused), None);
+}
+
+#[test]
+fn worlds_scale_to_many_entities() {
+    let mut world = World::new();
+    for i in 0..100_000 {
+        let e = world.create_entity().unwrap();
+        world.add_component(e, Position(i as f32)).unwrap();
+    }
+    for e in (0..100_000).step_by(2) {
+        world.destroy_entity(e).unwrap();
+    }
+    assert_eq!(world.living_count(), 50_000);
+    assert_eq!(world.storage::<Position>().unwrap().len(), 50_000);
+    assert_eq!(world.get::<Position>(99_999), Some(&Position(99_999.0)));
+}
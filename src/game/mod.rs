@@ -1,25 +1,182 @@
-use log::LevelFilter;
-use log4rs::{
-    append::file::FileAppender,
-    config::{Appender, Root},
-    encode::pattern::PatternEncoder,
-    Config,
-};
+pub mod timestep;
 
-use crate::{
-    constants::SCREEN_SIZE,
-    graphics::renderer::{GfxSubsystem, RendererManager},
+#[cfg(feature = "render")]
+use {
+    crate::{
+        camera::Camera,
+        constants::DrawPrimitives,
+        graphics::renderer::{GfxSubsystem, RendererManager},
+        logic::boid::{boid_mgr::BoidManager, species::Species, traits::Updatable},
+        recording::replay::Replay,
+    },
+    log::LevelFilter,
+    log4rs::{
+        append::file::FileAppender,
+        config::{Appender, Root},
+        encode::pattern::PatternEncoder,
+        Config,
+    },
+    sdl2::{event::Event, gfx::framerate::FPSManager, keyboard::Keycode},
+    std::{path::PathBuf, time::Instant},
+    timestep::FixedTimestep,
 };
 
-pub struct Game {}
-pub struct GameBuilder {}
-impl Game {}
+/*
+ * The window: simulates `config.tick_rate` steps per second whatever the frame
+ * rate, and draws up to `config.frame_rate` frames per second in between,
+ * blending the boids between the last two steps.
+ */
+#[cfg(feature = "render")]
+pub struct Game {
+    boid_manager: BoidManager,
+    /* A replay only copies recorded boids into an empty manager, it never updates it. */
+    replay: Option<Replay>,
+}
+#[cfg(feature = "render")]
+pub struct GameBuilder {
+    boid_manager: BoidManager,
+    replay: Option<Replay>,
+}
+#[cfg(feature = "render")]
+impl Game {
+    /* Runs until the window is closed, the manager is handed back for saving. */
+    pub fn run(mut self) -> Result<BoidManager, String> {
+        let config = &self.boid_manager.context().config;
+        let screen_size = config.screen_size;
+        let mut timestep = FixedTimestep::new(config.tick_rate);
+        let mut fps_manager = FPSManager::new();
+        fps_manager.set_framerate(config.frame_rate)?;
+
+        let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
+        let gss = GfxSubsystem::new(&ttf_context);
+
+        let video_subsystem = gss.sdl_context.video()?;
+        let window = video_subsystem
+            .window("Boids", screen_size.x, screen_size.y)
+            .position_centered()
+            .opengl()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let mut event_pump = gss.sdl_context.event_pump()?;
+        let mut renderer = RendererManager::new(window, gss);
+
+        let mut camera = Camera::new(self.boid_manager.context().config.view_port().left_up);
+        log::info!("camera position {:?}", camera);
+
+        let mut last_frame = Instant::now();
+        /* Frame of the replay the manager holds, set_boids is too slow to run on every render. */
+        let mut applied_frame = None;
+        'running: loop {
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } if self
+                        .replay
+                        .as_mut()
+                        .is_some_and(|replay| replay_key(replay, keycode)) => {}
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } if behaviour_key(&mut self.boid_manager, keycode) => {}
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => match keycode {
+                        Keycode::W => {
+                            self.boid_manager.add_boid(1);
+                        }
+                        Keycode::Q => {
+                            self.boid_manager.add_species(Species::Predator, 1);
+                        }
+                        Keycode::S => {
+                            let path = PathBuf::from(format!(
+                                "snapshot-{}.json",
+                                self.boid_manager.tick()
+                            ));
+                            match self.boid_manager.save_snapshot(&path) {
+                                Ok(()) => log::info!("saved snapshot {}", path.display()),
+                                Err(e) => log::error!("{}", e),
+                            }
+                        }
+                        Keycode::R => {
+                            let context = self.boid_manager.context_mut();
+                            context.border_behaviour = context.border_behaviour.toggled();
+                        }
+                        Keycode::Num5 => {
+                            renderer.draw_primitives ^= DrawPrimitives::QUAD_TREE;
+                        }
+                        Keycode::Num6 => {
+                            renderer.draw_primitives ^= DrawPrimitives::BOID_VIEW;
+                        }
+                        Keycode::Num7 => {
+                            renderer.draw_primitives ^= DrawPrimitives::BOUND_VIEW;
+                        }
+                        Keycode::Num8 => {
+                            let next = self.boid_manager.context().config.spatial_index.next();
+                            self.boid_manager.set_spatial_index(next);
+                        }
+                        Keycode::Left => {
+                            camera.pos.x -= 20.0;
+                        }
+                        Keycode::Right => {
+                            camera.pos.x += 20.0;
+                        }
+                        Keycode::Down => {
+                            camera.pos.y += 20.0;
+                        }
+                        Keycode::Up => {
+                            camera.pos.y -= 20.0;
+                        }
+                        Keycode::Escape => break 'running,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+
+            let now = Instant::now();
+            for _ in 0..timestep.advance(now - last_frame) {
+                match self.replay.as_mut() {
+                    Some(replay) => replay.advance(),
+                    None => self.boid_manager.update(),
+                }
+            }
+            last_frame = now;
+            if let Some(replay) = &self.replay {
+                if applied_frame != Some(replay.frame_index()) {
+                    replay.apply(&mut self.boid_manager);
+                    applied_frame = Some(replay.frame_index());
+                }
+            }
+            renderer.draw(&mut self.boid_manager, &camera, timestep.alpha());
+            fps_manager.delay();
+        }
+        Ok(self.boid_manager)
+    }
+}
+#[cfg(feature = "render")]
 impl GameBuilder {
-    pub fn build() {}
-    fn init_sdl(&self) -> Result<(), String> {
-        Ok(())
+    pub fn new(boid_manager: BoidManager) -> Self {
+        Self {
+            boid_manager,
+            replay: None,
+        }
+    }
+    pub fn with_replay(mut self, replay: Replay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+    pub fn build(self) -> Game {
+        Game {
+            boid_manager: self.boid_manager,
+            replay: self.replay,
+        }
     }
-    fn init_logger() {
+    pub fn init_logger() {
         /*Init Logger*/
         let logfile = FileAppender::builder()
             .encoder(Box::new(PatternEncoder::new("{d}: {l} - {m}\n")))
@@ -36,3 +193,53 @@ impl GameBuilder {
         let _ = log4rs::init_config(config.unwrap());
     }
 }
+
+/* Toggles every behaviour whose configured `key` was pressed, false if there is none. */
+#[cfg(feature = "render")]
+fn behaviour_key(boid_manager: &mut BoidManager, keycode: Keycode) -> bool {
+    let mut toggled = false;
+    for species in Species::ALL {
+        let names: Vec<String> = boid_manager
+            .context()
+            .config
+            .behaviours_of(species)
+            .iter()
+            .filter(|b| b.key.as_deref().and_then(Keycode::from_name) == Some(keycode))
+            .map(|b| b.name.clone())
+            .collect();
+        for name in names {
+            toggled |= boid_manager.toggle_behaviour(species, &name).is_some();
+        }
+    }
+    toggled
+}
+
+/*
+ * Space pauses, `.` and `,` step one tick, PageUp/PageDown jump 100 ticks,
+ * Home/End go to the ends and +/- double or halve the speed.
+ * Returns false for keys the replay doesn't use, so the camera still moves.
+ */
+#[cfg(feature = "render")]
+fn replay_key(replay: &mut Replay, keycode: Keycode) -> bool {
+    match keycode {
+        Keycode::Space => replay.toggle_pause(),
+        Keycode::Period => replay.step(1),
+        Keycode::Comma => replay.step(-1),
+        Keycode::PageUp => replay.scrub_to(replay.frame_index() as i64 + 100),
+        Keycode::PageDown => replay.scrub_to(replay.frame_index() as i64 - 100),
+        Keycode::Home => replay.scrub_to(0),
+        Keycode::End => replay.scrub_to(i64::MAX),
+        Keycode::Equals | Keycode::KpPlus => replay.set_speed(replay.speed() * 2.0),
+        Keycode::Minus | Keycode::KpMinus => replay.set_speed(replay.speed() / 2.0),
+        _ => return false,
+    }
+    log::info!(
+        "replay frame {}/{} tick {:?} speed {} paused {}",
+        replay.frame_index(),
+        replay.len(),
+        replay.frame().map(|f| f.tick),
+        replay.speed(),
+        replay.is_paused()
+    );
+    true
+}
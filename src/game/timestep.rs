@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+#[cfg(test)]
+use crate::{
+    config::SimulationConfig,
+    logic::boid::{boid_mgr::BoidManager, traits::Updatable},
+};
+
+/*
+ * Turns the real time between frames into a whole number of simulation steps
+ * of `step`. What is left carries over to the next frame and tells how far the
+ * display is between the last two steps, see `alpha`. This keeps the
+ * simulation the same no matter how fast the window is drawn.
+ */
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    /* Catching up is capped, so one slow frame can't make the next ones slower. */
+    pub const MAX_STEPS_PER_FRAME: u32 = 8;
+
+    pub fn new(tick_rate: f32) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1.0 / tick_rate as f64),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /* Adds the time the last frame took, returns how many steps to simulate now. */
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+        let due = self.accumulator.as_nanos() / self.step.as_nanos();
+        if due > Self::MAX_STEPS_PER_FRAME as u128 {
+            log::warn!("{} steps behind, skipping ahead", due);
+            self.accumulator = Duration::ZERO;
+            return Self::MAX_STEPS_PER_FRAME;
+        }
+        self.accumulator -= self.step * due as u32;
+        due as u32
+    }
+
+    /* How far past the last step the current time is, in 0..1 steps. */
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+fn frame_time(frame_rate: u32) -> Duration {
+    Duration::from_nanos(1_000_000_000 / frame_rate as u64)
+}
+
+#[test]
+fn steps_follow_real_time_at_any_frame_rate() {
+    for frame_rate in [30, 60, 144] {
+        let mut timestep = FixedTimestep::new(60.0);
+        let mut steps = 0;
+        for _ in 0..frame_rate * 3 {
+            steps += timestep.advance(frame_time(frame_rate));
+            assert!((0.0..1.0).contains(&timestep.alpha()));
+        }
+        assert!((179..=180).contains(&steps), "{} {}", frame_rate, steps);
+    }
+
+    let mut timestep = FixedTimestep::new(60.0);
+    assert_eq!(timestep.advance(Duration::from_secs(2)), 8);
+    assert_eq!(timestep.alpha(), 0.0);
+    assert_eq!(timestep.advance(timestep.step() / 2), 0);
+    assert!((timestep.alpha() - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn the_flock_is_the_same_at_any_frame_rate() {
+    let run = |frame_rate: u32| {
+        let mut manager = BoidManager::new(SimulationConfig {
+            seed: Some(21),
+            ..Default::default()
+        });
+        manager.spawn_boid(40);
+        let mut timestep = FixedTimestep::new(manager.context().config.tick_rate);
+        'frames: loop {
+            for _ in 0..timestep.advance(frame_time(frame_rate)) {
+                if manager.tick() == 120 {
+                    break 'frames;
+                }
+                manager.update();
+            }
+        }
+        manager.snapshot()
+    };
+    let at_60 = run(60);
+    assert_eq!(run(30), at_60);
+    assert_eq!(run(144), at_60);
+}
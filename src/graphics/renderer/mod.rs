@@ -1,5 +1,5 @@
 use crate::camera::Camera;
-use crate::constants::{BEHAVIOUR_ENABLED, SCREEN_SIZE};
+use crate::constants::{DrawPrimitives, SCREEN_SIZE};
 use crate::logic::boid::boid_mgr::BoidManager;
 
 use sdl2::pixels::Color;
@@ -15,7 +15,7 @@ macro_rules! rect(
 );
 
 pub trait Renderable {
-    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera);
+    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera, primitives: DrawPrimitives);
 }
 
 pub struct Writer<'ttf, 'b> {
@@ -49,6 +49,7 @@ impl<'ttf, 'b> GfxSubsystem<'ttf, 'b> {
 pub struct RendererManager<'ttf, 'b> {
     canvas: WindowCanvas,
     gfx: GfxSubsystem<'ttf, 'b>,
+    pub draw_primitives: DrawPrimitives,
 }
 impl<'ttf, 'b> RendererManager<'ttf, 'b> {
     pub fn new(window: Window, gfx: GfxSubsystem<'ttf, 'b>) -> RendererManager<'ttf, 'b> {
@@ -57,22 +58,33 @@ impl<'ttf, 'b> RendererManager<'ttf, 'b> {
             .build()
             .map_err(|e| e.to_string())
             .unwrap();
-        RendererManager { canvas, gfx }
+        RendererManager {
+            canvas,
+            gfx,
+            draw_primitives: DrawPrimitives::ALL_DISABLED,
+        }
     }
     //MenosGrandes why this isn't render?
-    pub fn draw(&mut self, boid_manager: &mut BoidManager, camera: &Camera) {
+    /* `alpha` is how far the frame is between the last two simulation steps. */
+    pub fn draw(&mut self, boid_manager: &mut BoidManager, camera: &Camera, alpha: f32) {
         self.canvas.set_draw_color(Color::BLACK);
         self.canvas.clear();
 
-        boid_manager.render(&mut self.canvas, camera);
-
-        unsafe {
-            if !BEHAVIOUR_ENABLED.is_empty() {
-                self.draw_string(BEHAVIOUR_ENABLED.to_string());
-            } else {
-                self.draw_string((&"NONE").to_string());
-            }
-        }
+        boid_manager.render_interpolated(&mut self.canvas, camera, self.draw_primitives, alpha);
+
+        let enabled: Vec<&str> = boid_manager
+            .context()
+            .config
+            .behaviours
+            .iter()
+            .filter(|b| b.enabled)
+            .map(|b| b.name.as_str())
+            .collect();
+        let behaviours = match enabled.is_empty() {
+            true => "NONE".to_string(),
+            false => enabled.join(" "),
+        };
+        self.draw_string(format!("{} | {}", behaviours, boid_manager.population()));
 
         //let view_port =
         self.canvas.present();
@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+use crate::logic::boid::traits::Updatable;
+
+/*
+ * Drives any Updatable (usually BoidManager) without touching SDL.
+ * Nothing in here may depend on the `render` feature, so it can be used
+ * on machines without a display.
+ */
+pub struct HeadlessRunner<T: Updatable> {
+    simulation: T,
+    ticks: u64,
+    elapsed: Duration,
+}
+
+impl<T: Updatable> HeadlessRunner<T> {
+    pub fn new(simulation: T) -> Self {
+        Self {
+            simulation,
+            ticks: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn step(&mut self) {
+        let start = Instant::now();
+        self.simulation.update();
+        self.elapsed += start.elapsed();
+        self.ticks += 1;
+    }
+
+    pub fn run(&mut self, steps: u64) {
+        log::info!("headless run of {} steps", steps);
+        for _ in 0..steps {
+            self.step();
+        }
+        log::info!(
+            "headless run done, {} ticks in {:?}",
+            self.ticks,
+            self.elapsed
+        );
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    pub fn simulation(&self) -> &T {
+        &self.simulation
+    }
+    pub fn simulation_mut(&mut self) -> &mut T {
+        &mut self.simulation
+    }
+    pub fn into_inner(self) -> T {
+        self.simulation
+    }
+}
+
+#[test]
+fn run_counts_ticks() {
+    struct Counter(u64);
+    impl Updatable for Counter {
+        fn update(&mut self) {
+            self.0 += 1;
+        }
+    }
+    let mut runner = HeadlessRunner::new(Counter(0));
+    runner.run(5);
+    runner.step();
+    assert_eq!(runner.ticks(), 6);
+    assert_eq!(runner.into_inner().0, 6);
+}
+
+#[test]
+fn run_boid_manager() {
+    use crate::logic::boid::boid_mgr::BoidManager;
+
+    let mut boid_manager = BoidManager::default();
+    boid_manager.spawn_boid(50);
+    let mut runner = HeadlessRunner::new(boid_manager);
+    runner.run(100);
+    assert_eq!(runner.ticks(), 100);
+    assert_eq!(runner.simulation().boids().len(), 50);
+}
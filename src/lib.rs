@@ -0,0 +1,16 @@
+pub mod camera;
+pub mod config;
+pub mod constants;
+pub mod ecs;
+pub mod game;
+#[cfg(feature = "render")]
+pub mod graphics;
+pub mod headless;
+pub mod logic;
+pub mod math;
+pub mod recording;
+
+extern crate approx;
+extern crate crossbeam;
+#[cfg(feature = "render")]
+extern crate sdl2;
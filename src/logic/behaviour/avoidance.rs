@@ -0,0 +1,137 @@
+#[cfg(test)]
+use crate::logic::obstacle::Obstacle;
+use crate::{
+    constants::VIEW_DISTANCE,
+    logic::{boid::boid_impl::Boid, context::SimulationContext, obstacle::RayHit},
+    math::vec::{DotProduct, Magnitude, V2f32, Vector2},
+};
+
+use super::{pipeline::BehaviourParams, traits::Behaviour};
+
+/*
+ * Steers around `config.obstacles` before hitting them. Three feelers are cast
+ * ahead of a moving boid: one along the heading, `look_ahead` long, and two
+ * half as long at `feeler_angle` to either side. Each hit pushes away along the
+ * outline's normal and sideways, harder the closer the hit is.
+ */
+pub struct AvoidObstaclesBehaviour {
+    pub look_ahead: f32,
+    /* Radians between the middle feeler and each side feeler. */
+    pub feeler_angle: f32,
+}
+impl AvoidObstaclesBehaviour {
+    pub const DEFAULT_LOOK_AHEAD: f32 = VIEW_DISTANCE;
+    pub const DEFAULT_FEELER_ANGLE: f32 = 30.0;
+    pub const PARAMS: &'static [&'static str] = &["look_ahead", "feeler_angle"];
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        let look_ahead = params.get("look_ahead", Self::DEFAULT_LOOK_AHEAD)?;
+        if !(look_ahead.is_finite() && look_ahead > 0.0) {
+            return Err(format!(
+                "look_ahead {} must be a positive number",
+                look_ahead
+            ));
+        }
+        let feeler_angle: f32 = params.get("feeler_angle", Self::DEFAULT_FEELER_ANGLE)?;
+        if !(0.0..=90.0).contains(&feeler_angle) {
+            return Err(format!("feeler_angle {} must be in 0..=90", feeler_angle));
+        }
+        Ok(Box::new(Self {
+            look_ahead,
+            feeler_angle: feeler_angle.to_radians(),
+        }))
+    }
+}
+impl Default for AvoidObstaclesBehaviour {
+    fn default() -> Self {
+        Self {
+            look_ahead: Self::DEFAULT_LOOK_AHEAD,
+            feeler_angle: Self::DEFAULT_FEELER_ANGLE.to_radians(),
+        }
+    }
+}
+impl Behaviour for AvoidObstaclesBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let obstacles = &ctx.config.obstacles;
+        let heading = match self_boid.heading() {
+            Some(heading) if !obstacles.is_empty() => heading,
+            _ => return V2f32::zero(),
+        };
+        let left = Vector2::new(heading.y, -heading.x);
+        let (sin, cos) = self.feeler_angle.sin_cos();
+        let feelers = [
+            (heading, self.look_ahead),
+            (heading * cos + left * sin, self.look_ahead / 2.0),
+            (heading * cos - left * sin, self.look_ahead / 2.0),
+        ];
+        let mut away = V2f32::zero();
+        for (direction, length) in feelers {
+            let nearest = obstacles
+                .iter()
+                .filter_map(|o| o.ray_hit(self_boid.position, direction, length))
+                .min_by(|a, b| a.distance.total_cmp(&b.distance));
+            if let Some(RayHit { distance, normal }) = nearest {
+                /* Head-on the normal only brakes, so turn left unless it already leans a side. */
+                let mut sideways = normal - heading * normal.dot(heading);
+                if sideways.calc_magnitude() < 1e-3 {
+                    sideways = left;
+                }
+                sideways.set_magnitude(1.0);
+                away += (normal + sideways) * (1.0 - distance / length);
+            }
+        }
+        away * ctx.config.max_speed(self_boid.species)
+    }
+}
+
+#[cfg(test)]
+fn context_with(obstacles: Vec<Obstacle>) -> SimulationContext {
+    let mut ctx = SimulationContext::default();
+    ctx.config.obstacles = obstacles;
+    ctx
+}
+
+#[test]
+fn steers_around_what_is_ahead() {
+    let avoid = AvoidObstaclesBehaviour::default();
+    let wall = Obstacle::Rect {
+        left_up: Vector2::new(100.0, -50.0),
+        right_down: Vector2::new(120.0, 50.0),
+    };
+    let ctx = context_with(vec![wall]);
+
+    /* Heading straight at the wall it brakes and turns to a side. */
+    let boid = Boid::new(0, Vector2::new(60.0, 0.0), Vector2::new(2.0, 0.0));
+    let steering = avoid.calculate(&boid, &[], &ctx);
+    assert!(steering.x < 0.0 && steering.y != 0.0, "{}", steering);
+    /* Closer means harder. */
+    let far = Boid::new(0, Vector2::new(30.0, 10.0), Vector2::new(2.0, 0.0));
+    let close = Boid::new(0, Vector2::new(90.0, 10.0), Vector2::new(2.0, 0.0));
+    assert!(
+        avoid.calculate(&far, &[], &ctx).x > avoid.calculate(&close, &[], &ctx).x,
+        "closer hits push harder"
+    );
+
+    /* Moving away, standing still or nothing around: no steering. */
+    let away = Boid::new(0, Vector2::new(60.0, 0.0), Vector2::new(-2.0, 0.0));
+    assert_eq!(avoid.calculate(&away, &[], &ctx), V2f32::zero());
+    let still = Boid::new(0, Vector2::new(60.0, 0.0), Vector2::zero());
+    assert_eq!(avoid.calculate(&still, &[], &ctx), V2f32::zero());
+    assert_eq!(
+        avoid.calculate(&boid, &[], &context_with(vec![])),
+        V2f32::zero()
+    );
+}
+
+#[test]
+fn side_feelers_turn_away_from_a_pillar() {
+    let avoid = AvoidObstaclesBehaviour::default();
+    /* A pillar ahead and to the right (down on screen) of the path. */
+    let ctx = context_with(vec![Obstacle::Circle {
+        center: Vector2::new(50.0, 20.0),
+        radius: 10.0,
+    }]);
+    let boid = Boid::new(0, Vector2::new(20.0, 0.0), Vector2::new(2.0, 0.0));
+    let steering = avoid.calculate(&boid, &[], &ctx);
+    assert!(steering.y < 0.0, "{}", steering);
+}
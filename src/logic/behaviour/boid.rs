@@ -1,33 +1,35 @@
+use crate::config::SimulationConfig;
 use crate::logic::boid::boid_impl::Boid;
 use crate::{constants::*, math::vec::*};
 
 use super::traits::{BorderBehaviour, BorderBehaviourE};
 
 impl BorderBehaviour for Boid {
-    fn border(&mut self, e: &BorderBehaviourE) {
+    fn border(&mut self, e: &BorderBehaviourE, config: &SimulationConfig) {
+        let view_port_size = config.view_port_size;
         match e {
             BorderBehaviourE::Reflect => {
-                if self.position.x > VIEW_PORT_SIZE.x as f32 - (BOID_SIZE as f32) * 3.0 {
+                if self.position.x > view_port_size.x - (BOID_SIZE as f32) * 3.0 {
                     self.velocity = self.velocity.reflect(Vector2::new(-1.0, 0.0));
                 } else if self.position.x < BOID_SIZE as f32 * 3.0 {
                     self.velocity = self.velocity.reflect(Vector2::new(1.0, 0.0));
                 }
-                if self.position.y > VIEW_PORT_SIZE.y as f32 - (BOID_SIZE as f32) * 3.0 {
+                if self.position.y > view_port_size.y - (BOID_SIZE as f32) * 3.0 {
                     self.velocity = self.velocity.reflect(Vector2::new(0.0, 1.0));
                 } else if self.position.y < BOID_SIZE as f32 * 3.0 {
                     self.velocity = self.velocity.reflect(Vector2::new(0.0, -1.0));
                 }
             }
             BorderBehaviourE::GoThrough => {
-                if self.position.x > VIEW_PORT_SIZE.x - 10.0 {
+                if self.position.x > view_port_size.x - 10.0 {
                     self.position.x = 10.0;
                 } else if self.position.x < 10.0 {
-                    self.position.x = VIEW_PORT_SIZE.x as f32 - 10.0;
+                    self.position.x = view_port_size.x - 10.0;
                 }
-                if self.position.y > VIEW_PORT_SIZE.y - 10.0 {
+                if self.position.y > view_port_size.y - 10.0 {
                     self.position.y = 10.0;
                 } else if self.position.y < 10.0 {
-                    self.position.y = VIEW_PORT_SIZE.y - 10.0;
+                    self.position.y = view_port_size.y - 10.0;
                 }
             }
         }
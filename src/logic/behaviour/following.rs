@@ -0,0 +1,208 @@
+use std::path::Path;
+
+#[cfg(test)]
+use crate::math::vec::Vector2;
+use crate::{
+    constants::VIEW_PORT_SIZE,
+    logic::{boid::boid_impl::Boid, context::SimulationContext},
+    math::{
+        flow_field::FlowField,
+        path::Polyline,
+        vec::{Distance, Magnitude, V2f32},
+    },
+};
+
+use super::{
+    pipeline::BehaviourParams,
+    steering::{desired_velocity, positive, seek},
+    traits::Behaviour,
+};
+
+/*
+ * Keeps boids inside a corridor `radius` wide around `path` and moving along
+ * it, like a migration route. The boid looks `look_ahead` seconds ahead: if it
+ * leaves the corridor there, it seeks the point `advance` further along the
+ * path, otherwise it heads the way the path goes. The end of an open path is
+ * arrived at, a closed one is walked forever.
+ */
+pub struct PathFollowingBehaviour {
+    pub path: Polyline,
+    pub radius: f32,
+    pub look_ahead: f32,
+    pub advance: f32,
+}
+impl PathFollowingBehaviour {
+    pub const DEFAULT_RADIUS: f32 = 20.0;
+    pub const DEFAULT_LOOK_AHEAD: f32 = 0.2;
+    pub const DEFAULT_ADVANCE: f32 = 30.0;
+    pub const PARAMS: &'static [&'static str] =
+        &["path", "closed", "radius", "look_ahead", "advance"];
+
+    pub fn new(path: Polyline) -> Self {
+        Self {
+            path,
+            radius: Self::DEFAULT_RADIUS,
+            look_ahead: Self::DEFAULT_LOOK_AHEAD,
+            advance: Self::DEFAULT_ADVANCE,
+        }
+    }
+
+    /* `path` is required, as points like `100,100 300,100 300,400`. */
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        let mut path: Polyline = params
+            .get_optional("path")?
+            .ok_or_else(|| "path is required".to_string())?;
+        path.closed = params.get("closed", false)?;
+        Ok(Box::new(Self {
+            path,
+            radius: positive(params, "radius", Self::DEFAULT_RADIUS)?,
+            look_ahead: positive(params, "look_ahead", Self::DEFAULT_LOOK_AHEAD)?,
+            advance: positive(params, "advance", Self::DEFAULT_ADVANCE)?,
+        }))
+    }
+}
+impl Behaviour for PathFollowingBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let max_speed = ctx.config.max_speed(self_boid.species);
+        let predicted = self_boid.position + self_boid.velocity * self.look_ahead;
+        let (closest, along) = self.path.closest(predicted);
+        let ahead = along + self.advance;
+        let (target, direction) = self.path.at(ahead);
+        if !self.path.closed && ahead >= self.path.length() {
+            let distance = V2f32::distance(self_boid.position, target);
+            let speed = max_speed * (distance / self.advance).min(1.0);
+            return desired_velocity(self_boid.position, target, speed) - self_boid.velocity;
+        }
+        if V2f32::distance(predicted, closest) > self.radius {
+            seek(self_boid, target, max_speed)
+        } else {
+            direction * max_speed - self_boid.velocity
+        }
+    }
+}
+
+/*
+ * Lets boids drift with a flow field, like wind or a current: they steer to
+ * the field's vector at their position times their max speed. Vectors longer
+ * than 1 count as 1, outside the field nothing happens.
+ */
+pub struct FlowFieldFollowingBehaviour {
+    pub field: FlowField,
+}
+impl FlowFieldFollowingBehaviour {
+    pub const DEFAULT_CELL_SIZE: f32 = 20.0;
+    pub const DEFAULT_FREQUENCY: f32 = 0.1;
+    pub const PARAMS: &'static [&'static str] =
+        &["file", "seed", "cell_size", "columns", "rows", "frequency"];
+
+    /*
+     * The field is read from `file`, or made from Perlin noise with `seed`
+     * covering the default view port unless `columns` and `rows` say otherwise.
+     */
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        if let Some(file) = params.get_optional::<String>("file")? {
+            if params.keys().any(|key| key != "file") {
+                return Err("file can't be combined with the Perlin noise params".to_string());
+            }
+            let field = FlowField::from_file(Path::new(&file)).map_err(|e| e.to_string())?;
+            return Ok(Box::new(Self { field }));
+        }
+        let cell_size = positive(params, "cell_size", Self::DEFAULT_CELL_SIZE)?;
+        let cells = |size: f32| (size / cell_size).ceil() as usize;
+        let columns = params.get("columns", cells(VIEW_PORT_SIZE.x))?;
+        let rows = params.get("rows", cells(VIEW_PORT_SIZE.y))?;
+        let field = FlowField::from_perlin(
+            params.get("seed", 0)?,
+            cell_size,
+            columns,
+            rows,
+            params.get("frequency", Self::DEFAULT_FREQUENCY)?,
+        );
+        field.validate()?;
+        Ok(Box::new(Self { field }))
+    }
+}
+impl Behaviour for FlowFieldFollowingBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        match self.field.sample(self_boid.position) {
+            Some(mut flow) => {
+                flow.limit(1.0);
+                flow * ctx.config.max_speed(self_boid.species) - self_boid.velocity
+            }
+            None => V2f32::zero(),
+        }
+    }
+}
+
+#[test]
+fn paths_pull_boids_back_and_along() {
+    let ctx = SimulationContext::default();
+    let max_speed = ctx.config.max_boid_speed;
+    let follow = PathFollowingBehaviour::new("0,0 200,0".parse().unwrap());
+
+    /* In the corridor and going the right way: nothing to do. */
+    let on_path = Boid::new(0, Vector2::new(50.0, 5.0), Vector2::new(max_speed, 0.0));
+    assert_eq!(follow.calculate(&on_path, &[], &ctx), V2f32::zero());
+    /* Standing still it gets going along the path. */
+    let still = Boid::new(0, Vector2::new(50.0, 5.0), V2f32::zero());
+    assert_eq!(
+        follow.calculate(&still, &[], &ctx),
+        Vector2::new(max_speed, 0.0)
+    );
+    /* Drifting out of the corridor it turns back towards the path ahead. */
+    let leaving = Boid::new(0, Vector2::new(50.0, 15.0), Vector2::new(60.0, 60.0));
+    let steering = follow.calculate(&leaving, &[], &ctx);
+    assert!(steering.y < 0.0 && steering.x > 0.0, "{}", steering);
+    /* Near the end of an open path it slows down towards the last point. */
+    let ending = Boid::new(0, Vector2::new(195.0, 0.0), Vector2::new(max_speed, 0.0));
+    assert!(follow.calculate(&ending, &[], &ctx).x < 0.0);
+
+    /* A closed path goes on around the corner. */
+    let mut square = PathFollowingBehaviour::new("0,0 100,0 100,100 0,100".parse().unwrap());
+    square.path.closed = true;
+    let corner = Boid::new(0, Vector2::new(10.0, 100.0), Vector2::new(-max_speed, 0.0));
+    assert!(square.calculate(&corner, &[], &ctx).y < 0.0);
+}
+
+#[test]
+fn path_params_are_checked() {
+    let mut params = BehaviourParams::default();
+    assert!(PathFollowingBehaviour::from_params(&params).is_err());
+    params.set("path", "0,0 100,0");
+    assert!(PathFollowingBehaviour::from_params(&params).is_ok());
+    params.set("radius", -1);
+    assert!(PathFollowingBehaviour::from_params(&params).is_err());
+    params.set("path", "0,0");
+    params.set("radius", 1);
+    assert!(PathFollowingBehaviour::from_params(&params).is_err());
+}
+
+#[test]
+fn boids_drift_with_the_flow() {
+    let ctx = SimulationContext::default();
+    let max_speed = ctx.config.max_boid_speed;
+    let follow = FlowFieldFollowingBehaviour {
+        field: FlowField {
+            cell_size: 10.0,
+            columns: 1,
+            rows: 1,
+            vectors: vec![Vector2::new(0.0, 3.0)],
+        },
+    };
+    let boid = Boid::new(0, Vector2::new(5.0, 5.0), Vector2::new(1.0, 0.0));
+    assert_eq!(
+        follow.calculate(&boid, &[], &ctx),
+        Vector2::new(-1.0, max_speed)
+    );
+    let outside = Boid::new(0, Vector2::new(15.0, 5.0), Vector2::new(1.0, 0.0));
+    assert_eq!(follow.calculate(&outside, &[], &ctx), V2f32::zero());
+
+    let mut params = BehaviourParams::default();
+    params.set("seed", 3);
+    params.set("cell_size", 50);
+    assert!(FlowFieldFollowingBehaviour::from_params(&params).is_ok());
+    params.set("columns", 0);
+    assert!(FlowFieldFollowingBehaviour::from_params(&params).is_err());
+    params.set("file", "wind.toml");
+    assert!(FlowFieldFollowingBehaviour::from_params(&params).is_err());
+}
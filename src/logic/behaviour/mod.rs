@@ -1,2 +1,8 @@
+pub mod avoidance;
 pub mod boid;
+pub mod following;
+pub mod pipeline;
+pub mod registry;
+pub mod separation;
+pub mod steering;
 pub mod traits;
@@ -0,0 +1,510 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(test)]
+use crate::math::vec::Vector2;
+use crate::{
+    config::{ConfigError, SimulationConfig},
+    constants::{types::BoidId, STEERING_TIME},
+    logic::{
+        boid::{boid_impl::Boid, species::Species},
+        context::SimulationContext,
+    },
+    math::{
+        noise::{mix, unit},
+        vec::{Finite, Magnitude, V2f32},
+    },
+};
+
+use super::{registry::BehaviourRegistry, traits::Behaviour};
+
+/*
+ * A behaviour parameter. Always kept as text and parsed by the behaviour, so the
+ * config can hold numbers, words and flags side by side; in TOML/RON/JSON it is
+ * written as whatever scalar it looks like, in binary snapshots as a string.
+ * Integers stay integers so a large seed keeps every digit, the ones TOML
+ * can't hold and floats like inf or NaN are written as strings.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamValue(pub String);
+
+impl Serialize for ParamValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            if let Ok(integer) = self.0.parse::<i64>() {
+                return serializer.serialize_i64(integer);
+            }
+            if self.0.parse::<u64>().is_ok() {
+                return serializer.serialize_str(&self.0);
+            }
+            if let Some(number) = self.0.parse::<f64>().ok().filter(|n| n.is_finite()) {
+                return serializer.serialize_f64(number);
+            }
+            if let Ok(flag) = self.0.parse::<bool>() {
+                return serializer.serialize_bool(flag);
+            }
+        }
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ParamValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Scalar;
+        impl de::Visitor<'_> for Scalar {
+            type Value = ParamValue;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number, a boolean or a string")
+            }
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<ParamValue, E> {
+                Ok(ParamValue(v.to_string()))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ParamValue, E> {
+                Ok(ParamValue(v.to_string()))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ParamValue, E> {
+                Ok(ParamValue(v.to_string()))
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<ParamValue, E> {
+                Ok(ParamValue(v.to_string()))
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ParamValue, E> {
+                Ok(ParamValue(v.to_string()))
+            }
+        }
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Scalar)
+        } else {
+            deserializer.deserialize_string(Scalar)
+        }
+    }
+}
+
+/* Named parameters of one behaviour, see BehaviourRegistry for which names a behaviour takes. */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BehaviourParams(pub BTreeMap<String, ParamValue>);
+
+impl BehaviourParams {
+    /* The parsed value of `key`, `default` when it isn't set. */
+    pub fn get<T: FromStr>(&self, key: &str, default: T) -> Result<T, String>
+    where
+        T::Err: fmt::Display,
+    {
+        Ok(self.get_optional(key)?.unwrap_or(default))
+    }
+
+    /* Like `get`, for params without a default. */
+    pub fn get_optional<T: FromStr>(&self, key: &str) -> Result<Option<T>, String>
+    where
+        T::Err: fmt::Display,
+    {
+        self.0
+            .get(key)
+            .map(|ParamValue(value)| {
+                value
+                    .parse()
+                    .map_err(|e: T::Err| format!("{} = {} ({})", key, value, e))
+            })
+            .transpose()
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.0
+            .insert(key.to_string(), ParamValue(value.to_string()));
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+/* One step of the steering pipeline as written in the config. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BehaviourConfig {
+    /* Name the behaviour is registered under. */
+    pub name: String,
+    pub weight: f32,
+    pub enabled: bool,
+    /* Chance to be considered at all, only used by `dithered`. */
+    pub probability: f32,
+    /* SDL key name that toggles the behaviour in the window, e.g. "1" or "F2". */
+    pub key: Option<String>,
+    pub params: BehaviourParams,
+}
+
+impl Default for BehaviourConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            weight: 1.0,
+            enabled: true,
+            probability: 1.0,
+            key: None,
+            params: BehaviourParams::default(),
+        }
+    }
+}
+
+impl BehaviourConfig {
+    pub fn new(name: &str, weight: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            weight,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    pub fn with_param(mut self, key: &str, value: impl ToString) -> Self {
+        self.params.set(key, value);
+        self
+    }
+}
+
+/* How the weighted results of the enabled behaviours become one steering force. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CombineMode {
+    /* Everything added up. */
+    #[default]
+    WeightedSum,
+    /*
+     * Added up in order until `max_boid_force` is used up, the behaviour that
+     * crosses it is truncated and the rest is skipped.
+     */
+    Prioritized,
+    /*
+     * In order, each behaviour is considered with its `probability` and the first
+     * one that steers at all wins. Cheap, since usually one behaviour runs per boid.
+     */
+    Dithered,
+}
+
+impl CombineMode {
+    pub const ALL: [CombineMode; 3] = [
+        CombineMode::WeightedSum,
+        CombineMode::Prioritized,
+        CombineMode::Dithered,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            CombineMode::WeightedSum => "weighted_sum",
+            CombineMode::Prioritized => "prioritized",
+            CombineMode::Dithered => "dithered",
+        }
+    }
+}
+
+impl fmt::Display for CombineMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CombineMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| "expected one of weighted_sum, prioritized, dithered".to_string())
+    }
+}
+
+/*
+ * The behaviours built from `config.behaviours_of(species)`, in the same order
+ * and with their names. Weights, enabled flags and the combine mode are read
+ * from the context on every call, so they can change at runtime; changed
+ * params need a new pipeline, as does a changed list, see `matches`.
+ * Behaviours with a `seed` param that isn't set get `config.seed`, so they
+ * follow the seed of the simulation.
+ */
+#[derive(Default)]
+pub struct SteeringPipeline {
+    pub species: Species,
+    behaviours: Vec<(String, Box<dyn Behaviour>)>,
+}
+
+impl SteeringPipeline {
+    /* Steers nowhere. */
+    pub fn empty(species: Species) -> Self {
+        Self {
+            species,
+            behaviours: Vec::new(),
+        }
+    }
+
+    pub fn build(
+        registry: &BehaviourRegistry,
+        config: &SimulationConfig,
+        species: Species,
+    ) -> Result<Self, ConfigError> {
+        let behaviours = config
+            .behaviours_of(species)
+            .iter()
+            .map(|behaviour| {
+                match config.seed {
+                    Some(seed)
+                        if !behaviour.params.0.contains_key("seed")
+                            && registry
+                                .params(&behaviour.name)
+                                .is_some_and(|params| params.contains(&"seed")) =>
+                    {
+                        registry.create(&behaviour.clone().with_param("seed", seed))
+                    }
+                    _ => registry.create(behaviour),
+                }
+                .map(|built| (behaviour.name.clone(), built))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            species,
+            behaviours,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviours.is_empty()
+    }
+
+    /* True if the pipeline was built from `behaviours`, as far as their names tell. */
+    pub fn matches(&self, behaviours: &[BehaviourConfig]) -> bool {
+        self.behaviours.len() == behaviours.len()
+            && self
+                .behaviours
+                .iter()
+                .zip(behaviours)
+                .all(|((name, _), config)| *name == config.name)
+    }
+
+    /*
+     * Steering force of `boid`, in units per second squared times mass:
+     * the change of velocity a behaviour asks for is made within STEERING_TIME.
+     * `dither` only matters for CombineMode::Dithered and should change every
+     * tick, see `tick_seed`. A behaviour that returns NaN or infinity is logged
+     * and counts as not steering, so does one whose config isn't at its place
+     * in the context any more.
+     */
+    pub fn steer(
+        &self,
+        boid: &Boid,
+        neighbours: &[&Boid],
+        ctx: &SimulationContext,
+        dither: u64,
+    ) -> V2f32 {
+        let steps = ctx
+            .config
+            .behaviours_of(self.species)
+            .iter()
+            .zip(&self.behaviours)
+            .enumerate()
+            .filter(|(_, (config, (name, _)))| config.enabled && config.name == *name);
+        let force = |config: &BehaviourConfig, behaviour: &dyn Behaviour| {
+            let steering = behaviour.calculate(boid, neighbours, ctx);
+            if steering.is_finite() {
+                steering * config.weight / STEERING_TIME
+            } else {
+                log::warn!(
+                    "{} gave {} for boid {}, ignored",
+                    config.name,
+                    steering,
+                    boid.id
+                );
+                V2f32::zero()
+            }
+        };
+        match ctx.config.steering {
+            CombineMode::WeightedSum => steps
+                .map(|(_, (config, (_, behaviour)))| force(config, behaviour.as_ref()))
+                .sum(),
+            CombineMode::Prioritized => {
+                let mut total = V2f32::zero();
+                let mut budget = ctx.config.max_boid_force;
+                for (_, (config, (_, behaviour))) in steps {
+                    let steering = force(config, behaviour.as_ref());
+                    let magnitude = steering.calc_magnitude();
+                    if magnitude >= budget {
+                        total += steering * (budget / magnitude);
+                        break;
+                    }
+                    total += steering;
+                    budget -= magnitude;
+                }
+                total
+            }
+            CombineMode::Dithered => steps
+                .filter(|(i, (config, _))| dither_chance(dither, boid.id, *i) < config.probability)
+                .map(|(_, (config, (_, behaviour)))| force(config, behaviour.as_ref()))
+                .find(|steering| *steering != V2f32::zero())
+                .unwrap_or_else(V2f32::zero),
+        }
+    }
+}
+
+/* Per tick seed for `steer`, so dithering is random but reproducible. */
+pub fn tick_seed(seed: u64, tick: u64) -> u64 {
+    mix(seed ^ mix(tick))
+}
+
+/* Uniform in [0, 1), the same for the same seed, boid and pipeline step. */
+fn dither_chance(seed: u64, boid: BoidId, step: usize) -> f32 {
+    unit(mix(seed
+        ^ mix(boid as u64)
+        ^ mix(step as u64).rotate_left(17)))
+}
+
+#[cfg(test)]
+struct Constant(V2f32);
+#[cfg(test)]
+impl Behaviour for Constant {
+    /* Scaled so `steer` gives back exactly the given force. */
+    fn calculate(&self, _: &Boid, _: &[&Boid], _: &SimulationContext) -> V2f32 {
+        self.0 * STEERING_TIME
+    }
+}
+
+#[cfg(test)]
+fn constant_pipeline(
+    mode: CombineMode,
+    forces: &[(f32, f32)],
+) -> (SteeringPipeline, SimulationContext) {
+    let mut ctx = SimulationContext::default();
+    ctx.config.steering = mode;
+    ctx.config.max_boid_force = 1.0;
+    ctx.config.behaviours = forces
+        .iter()
+        .enumerate()
+        .map(|(i, _)| BehaviourConfig::new(&format!("constant{}", i), 1.0))
+        .collect();
+    let pipeline = SteeringPipeline {
+        species: Species::Prey,
+        behaviours: forces
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| {
+                let behaviour = Box::new(Constant(Vector2::new(x, y))) as Box<dyn Behaviour>;
+                (format!("constant{}", i), behaviour)
+            })
+            .collect(),
+    };
+    (pipeline, ctx)
+}
+
+#[test]
+fn weighted_sum_uses_weights_and_enabled() {
+    let (pipeline, mut ctx) = constant_pipeline(
+        CombineMode::WeightedSum,
+        &[(1.0, 0.0), (0.0, 1.0), (1.0, 1.0)],
+    );
+    ctx.config.behaviours[0].weight = 2.0;
+    ctx.config.behaviours[2].enabled = false;
+    let boid = Boid::new(0, Vector2::zero(), Vector2::zero());
+    assert_eq!(pipeline.steer(&boid, &[], &ctx, 0), Vector2::new(2.0, 1.0));
+}
+
+#[test]
+fn prioritized_truncates_at_max_force() {
+    let (pipeline, mut ctx) = constant_pipeline(
+        CombineMode::Prioritized,
+        &[(0.6, 0.0), (0.0, 0.8), (5.0, 5.0)],
+    );
+    let boid = Boid::new(0, Vector2::zero(), Vector2::zero());
+    let force = pipeline.steer(&boid, &[], &ctx, 0);
+    approx::assert_relative_eq!(force.x, 0.6);
+    approx::assert_relative_eq!(force.y, 0.4);
+
+    /* The first two use up the whole budget, nothing is left for the third. */
+    ctx.config.max_boid_force = 1.4;
+    let force = pipeline.steer(&boid, &[], &ctx, 0);
+    approx::assert_relative_eq!(force.x, 0.6, epsilon = 1e-5);
+    approx::assert_relative_eq!(force.y, 0.8, epsilon = 1e-5);
+}
+
+#[test]
+fn behaviours_only_get_their_own_config() {
+    let (pipeline, mut ctx) =
+        constant_pipeline(CombineMode::WeightedSum, &[(1.0, 0.0), (0.0, 1.0)]);
+    assert!(pipeline.matches(&ctx.config.behaviours));
+    ctx.config.behaviours.swap(0, 1);
+    ctx.config.behaviours[0].weight = 5.0;
+    assert!(!pipeline.matches(&ctx.config.behaviours));
+    let boid = Boid::new(0, Vector2::zero(), Vector2::zero());
+    assert_eq!(pipeline.steer(&boid, &[], &ctx, 0), V2f32::zero());
+}
+
+#[test]
+fn dithered_picks_the_first_chosen_behaviour() {
+    let (pipeline, mut ctx) =
+        constant_pipeline(CombineMode::Dithered, &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+    let boid = Boid::new(0, Vector2::zero(), Vector2::zero());
+    /* A step that doesn't steer never wins. */
+    assert_eq!(pipeline.steer(&boid, &[], &ctx, 0), Vector2::new(1.0, 0.0));
+
+    ctx.config.behaviours[1].probability = 0.25;
+    let mut first = 0;
+    for tick in 0..1000 {
+        let force = pipeline.steer(&boid, &[], &ctx, tick_seed(7, tick));
+        assert_eq!(force, pipeline.steer(&boid, &[], &ctx, tick_seed(7, tick)));
+        if force == Vector2::new(1.0, 0.0) {
+            first += 1;
+        } else {
+            assert_eq!(force, Vector2::new(0.0, 1.0));
+        }
+    }
+    assert!((200..300).contains(&first), "{}", first);
+}
+
+#[test]
+fn params_round_trip_through_every_format() {
+    let behaviour = BehaviourConfig::new("separation", 0.5)
+        .with_key("3")
+        .with_param("radius", 25.5)
+        .with_param("kernel", "gaussian")
+        .with_param("wrap", true);
+    let toml = toml::to_string(&behaviour).unwrap();
+    assert!(toml.contains("radius = 25.5"), "{}", toml);
+    assert_eq!(toml::from_str::<BehaviourConfig>(&toml).unwrap(), behaviour);
+    let ron = ron::to_string(&behaviour).unwrap();
+    assert_eq!(ron::from_str::<BehaviourConfig>(&ron).unwrap(), behaviour);
+    let binary = bincode::serialize(&behaviour).unwrap();
+    assert_eq!(
+        bincode::deserialize::<BehaviourConfig>(&binary).unwrap(),
+        behaviour
+    );
+
+    assert_eq!(behaviour.params.get("radius", 1.0f32), Ok(25.5));
+    assert_eq!(behaviour.params.get("missing", 1.0f32), Ok(1.0));
+    assert!(behaviour.params.get::<f32>("kernel", 1.0).is_err());
+}
+
+#[test]
+fn large_seeds_and_odd_floats_round_trip() {
+    let behaviour = BehaviourConfig::new("wander", 1.0)
+        .with_param("seed", u64::MAX)
+        .with_param("offset", -(1i64 << 60))
+        .with_param("limit", f32::INFINITY)
+        .with_param("scale", f32::NAN);
+    let toml = toml::to_string(&behaviour).unwrap();
+    assert_eq!(toml::from_str::<BehaviourConfig>(&toml).unwrap(), behaviour);
+    let ron = ron::to_string(&behaviour).unwrap();
+    assert_eq!(ron::from_str::<BehaviourConfig>(&ron).unwrap(), behaviour);
+    let json = serde_json::to_string(&behaviour).unwrap();
+    assert_eq!(
+        serde_json::from_str::<BehaviourConfig>(&json).unwrap(),
+        behaviour
+    );
+    assert_eq!(behaviour.params.get("seed", 0u64), Ok(u64::MAX));
+}
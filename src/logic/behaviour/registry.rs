@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use crate::{config::ConfigError, logic::boid::species::Species};
+
+use super::{
+    avoidance::AvoidObstaclesBehaviour,
+    following::{FlowFieldFollowingBehaviour, PathFollowingBehaviour},
+    pipeline::{BehaviourConfig, BehaviourParams},
+    steering::{
+        ArriveBehaviour, EvadeBehaviour, FleeBehaviour, PursueBehaviour, SeekBehaviour,
+        WanderBehaviour,
+    },
+    traits::{AlignBehaviour, Behaviour, BoundBehaviour, CohesionBehaviour, SeperateBehaviour},
+};
+
+pub type BehaviourFactory = fn(&BehaviourParams) -> Result<Box<dyn Behaviour>, String>;
+
+struct Registration {
+    params: &'static [&'static str],
+    factory: BehaviourFactory,
+}
+
+/*
+ * Behaviours by the name the config refers to them with. A new behaviour only
+ * has to be registered here (or on a manager's registry) with the params it reads.
+ */
+pub struct BehaviourRegistry {
+    behaviours: BTreeMap<String, Registration>,
+}
+
+impl BehaviourRegistry {
+    pub fn empty() -> Self {
+        Self {
+            behaviours: BTreeMap::new(),
+        }
+    }
+
+    /* Registering a name again replaces the earlier behaviour. */
+    pub fn register(
+        &mut self,
+        name: &str,
+        params: &'static [&'static str],
+        factory: BehaviourFactory,
+    ) {
+        self.behaviours
+            .insert(name.to_string(), Registration { params, factory });
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.behaviours.keys().map(String::as_str)
+    }
+
+    /* The params `name` reads, None if it isn't registered. */
+    pub fn params(&self, name: &str) -> Option<&'static [&'static str]> {
+        self.behaviours.get(name).map(|r| r.params)
+    }
+
+    pub fn create(&self, config: &BehaviourConfig) -> Result<Box<dyn Behaviour>, ConfigError> {
+        let registration = self
+            .behaviours
+            .get(&config.name)
+            .ok_or_else(|| ConfigError::UnknownBehaviour(config.name.clone()))?;
+        let invalid = |reason: String| ConfigError::InvalidValue {
+            key: "behaviours",
+            reason: format!("{}: {}", config.name, reason),
+        };
+        if let Some(unknown) = config
+            .params
+            .keys()
+            .find(|key| !registration.params.contains(key))
+        {
+            return Err(invalid(format!(
+                "unknown param {}, expected one of {:?}",
+                unknown, registration.params
+            )));
+        }
+        (registration.factory)(&config.params).map_err(invalid)
+    }
+}
+
+impl Default for BehaviourRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("align", &[], |_| Ok(Box::new(AlignBehaviour)));
+        registry.register("cohesion", &[], |_| Ok(Box::new(CohesionBehaviour)));
+        registry.register(
+            "separation",
+            SeperateBehaviour::PARAMS,
+            SeperateBehaviour::from_params,
+        );
+        registry.register("bound", &[], |_| Ok(Box::new(BoundBehaviour)));
+        registry.register("seek", SeekBehaviour::PARAMS, SeekBehaviour::from_params);
+        registry.register("flee", FleeBehaviour::PARAMS, FleeBehaviour::from_params);
+        registry.register(
+            "arrive",
+            ArriveBehaviour::PARAMS,
+            ArriveBehaviour::from_params,
+        );
+        registry.register(
+            "pursue",
+            PursueBehaviour::PARAMS,
+            PursueBehaviour::from_params,
+        );
+        registry.register("evade", EvadeBehaviour::PARAMS, EvadeBehaviour::from_params);
+        registry.register(
+            "wander",
+            WanderBehaviour::PARAMS,
+            WanderBehaviour::from_params,
+        );
+        registry.register(
+            "avoid_obstacles",
+            AvoidObstaclesBehaviour::PARAMS,
+            AvoidObstaclesBehaviour::from_params,
+        );
+        registry.register(
+            "path_following",
+            PathFollowingBehaviour::PARAMS,
+            PathFollowingBehaviour::from_params,
+        );
+        registry.register(
+            "flow_field_following",
+            FlowFieldFollowingBehaviour::PARAMS,
+            FlowFieldFollowingBehaviour::from_params,
+        );
+        registry
+    }
+}
+
+/*
+ * The pipeline an empty config gets: the classic flocking rules with the keys
+ * the window always used for them, running from predators in sight and
+ * steering around obstacles.
+ */
+pub fn default_behaviours() -> Vec<BehaviourConfig> {
+    vec![
+        BehaviourConfig::new("align", 0.03).with_key("2"),
+        BehaviourConfig::new("separation", 0.03).with_key("3"),
+        BehaviourConfig::new("cohesion", 0.002).with_key("1"),
+        BehaviourConfig::new("bound", 0.07).with_key("4"),
+        BehaviourConfig::new("evade", 0.05).with_param("species", Species::Predator),
+        BehaviourConfig::new("avoid_obstacles", 0.1),
+    ]
+}
+
+/* Predators hunt the closest prey they see and keep some room between each other. */
+pub fn default_predator_behaviours() -> Vec<BehaviourConfig> {
+    vec![
+        BehaviourConfig::new("pursue", 0.05).with_param("species", Species::Prey),
+        BehaviourConfig::new("separation", 0.03),
+        BehaviourConfig::new("bound", 0.07),
+        BehaviourConfig::new("avoid_obstacles", 0.1),
+    ]
+}
+
+#[test]
+fn create_checks_names_and_params() {
+    let registry = BehaviourRegistry::default();
+    assert_eq!(
+        registry.names().collect::<Vec<_>>(),
+        vec![
+            "align",
+            "arrive",
+            "avoid_obstacles",
+            "bound",
+            "cohesion",
+            "evade",
+            "flee",
+            "flow_field_following",
+            "path_following",
+            "pursue",
+            "seek",
+            "separation",
+            "wander"
+        ]
+    );
+    assert!(registry
+        .create(&BehaviourConfig::new("separation", 1.0).with_param("radius", 10))
+        .is_ok());
+    assert!(matches!(
+        registry.create(&BehaviourConfig::new("flocking", 1.0)),
+        Err(ConfigError::UnknownBehaviour(name)) if name == "flocking"
+    ));
+    assert!(registry
+        .create(&BehaviourConfig::new("align", 1.0).with_param("radius", 10))
+        .is_err());
+    assert!(registry
+        .create(&BehaviourConfig::new("separation", 1.0).with_param("kernel", "cubic"))
+        .is_err());
+    for behaviour in default_behaviours()
+        .into_iter()
+        .chain(default_predator_behaviours())
+    {
+        assert!(registry.create(&behaviour).is_ok(), "{}", behaviour.name);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::types::BoidId, math::vec::V2f32};
+
+/* Below this two boids count as being on the same spot. */
+pub const MIN_SEPARATION_DISTANCE: f32 = 1e-3;
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/*
+ * How strongly a neighbour at `distance` pushes a boid away, for neighbours
+ * inside the desired separation `radius`. Every kernel is 1 or more close up
+ * and falls off towards the radius; all of them stay finite at distance 0.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeparationKernel {
+    /* 1 at distance 0 down to 0 at the radius. */
+    Linear,
+    /* (radius / distance)² - 1, the old behaviour without the singularity. */
+    #[default]
+    InverseSquare,
+    /*
+     * Bell curve with a standard deviation of half the radius, shifted and
+     * scaled to go from 1 at distance 0 down to 0 at the radius.
+     */
+    Gaussian,
+}
+
+impl SeparationKernel {
+    pub const ALL: [SeparationKernel; 3] = [
+        SeparationKernel::Linear,
+        SeparationKernel::InverseSquare,
+        SeparationKernel::Gaussian,
+    ];
+
+    pub fn weight(self, distance: f32, radius: f32) -> f32 {
+        if distance.is_nan() || distance >= radius {
+            return 0.0;
+        }
+        match self {
+            SeparationKernel::Linear => 1.0 - distance / radius,
+            SeparationKernel::InverseSquare => {
+                (radius / distance.max(MIN_SEPARATION_DISTANCE)).powi(2) - 1.0
+            }
+            SeparationKernel::Gaussian => {
+                let sigma = radius / 2.0;
+                let bell = |d: f32| (-(d * d) / (2.0 * sigma * sigma)).exp();
+                let edge = bell(radius);
+                (bell(distance) - edge) / (1.0 - edge)
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SeparationKernel::Linear => "linear",
+            SeparationKernel::InverseSquare => "inverse_square",
+            SeparationKernel::Gaussian => "gaussian",
+        }
+    }
+}
+
+impl fmt::Display for SeparationKernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SeparationKernel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| "expected one of linear, inverse_square, gaussian".to_string())
+    }
+}
+
+/*
+ * Direction `id` moves away from `other` when both are on the same spot. Only
+ * depends on the pair, so the two boids always get opposite directions.
+ */
+pub fn escape_direction(id: BoidId, other: BoidId) -> V2f32 {
+    let angle = (id.min(other).wrapping_add(id.max(other)) % 1024) as f32 * GOLDEN_ANGLE;
+    let direction = V2f32::new(angle.cos(), angle.sin());
+    if id < other {
+        direction
+    } else {
+        direction * -1.0
+    }
+}
+
+#[test]
+fn kernels_fall_off_towards_the_radius() {
+    for kernel in SeparationKernel::ALL {
+        assert_eq!(kernel.to_string().parse::<SeparationKernel>(), Ok(kernel));
+        let weights: Vec<f32> = [0.0, 0.5, 5.0, 10.0, 19.9]
+            .into_iter()
+            .map(|d| kernel.weight(d, 20.0))
+            .collect();
+        assert!(
+            weights.iter().all(|w| w.is_finite() && *w >= 0.0),
+            "{}",
+            kernel
+        );
+        assert!(weights[0] >= 1.0, "{}", kernel);
+        assert!(
+            weights.windows(2).all(|w| w[0] > w[1]),
+            "{} {:?}",
+            kernel,
+            weights
+        );
+        assert_eq!(kernel.weight(20.0, 20.0), 0.0);
+        /* No jump in the force when a neighbour crosses the radius. */
+        assert!(kernel.weight(19.99, 20.0) < 0.01, "{}", kernel);
+        assert_eq!(kernel.weight(f32::NAN, 20.0), 0.0);
+    }
+    assert!("cubic".parse::<SeparationKernel>().is_err());
+}
+
+#[test]
+fn escape_directions_are_opposite() {
+    for (a, b) in [(0, 1), (7, 3), (100, 100_000)] {
+        let (there, back) = (escape_direction(a, b), escape_direction(b, a));
+        assert_eq!(there + back, V2f32::zero());
+        approx::assert_relative_eq!(there.x * there.x + there.y * there.y, 1.0, epsilon = 1e-5);
+    }
+}
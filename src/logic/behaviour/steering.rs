@@ -0,0 +1,516 @@
+use std::{f32::consts::PI, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::math::vec::DotProduct;
+use crate::{
+    constants::{types::BoidId, VIEW_DISTANCE},
+    logic::{
+        boid::{boid_impl::Boid, species::Species},
+        context::SimulationContext,
+    },
+    math::{
+        noise::{mix, unit, Perlin},
+        spatial::distance_squared,
+        vec::{Distance, Magnitude, V2f32, Vector2},
+    },
+};
+
+use super::{pipeline::BehaviourParams, traits::Behaviour};
+
+/*
+ * The classic steering behaviours from Reynolds' "Steering Behaviors For
+ * Autonomous Characters". Each one picks a desired velocity and steers by the
+ * difference to the current velocity, the pipeline limits the force.
+ */
+
+/* Velocity towards `target` at `speed`, zero on the target itself. */
+pub(super) fn desired_velocity(from: V2f32, target: V2f32, speed: f32) -> V2f32 {
+    let mut offset = target - from;
+    if offset == V2f32::zero() || !offset.calc_magnitude().is_normal() {
+        return V2f32::zero();
+    }
+    offset.set_magnitude(speed);
+    offset
+}
+
+pub fn seek(boid: &Boid, target: V2f32, max_speed: f32) -> V2f32 {
+    desired_velocity(boid.position, target, max_speed) - boid.velocity
+}
+
+pub fn flee(boid: &Boid, target: V2f32, max_speed: f32) -> V2f32 {
+    desired_velocity(target, boid.position, max_speed) - boid.velocity
+}
+
+/* The `x` and `y` params of behaviours that steer relative to a fixed point. */
+fn point(params: &BehaviourParams) -> Result<V2f32, String> {
+    match (params.get_optional("x")?, params.get_optional("y")?) {
+        (Some(x), Some(y)) => Ok(Vector2::new(x, y)),
+        _ => Err("x and y are required".to_string()),
+    }
+}
+
+pub(super) fn positive(params: &BehaviourParams, key: &str, default: f32) -> Result<f32, String> {
+    let value: f32 = params.get(key, default)?;
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{} {} must be a positive number", key, value))
+    }
+}
+
+/* Straight towards `target` at full speed. */
+pub struct SeekBehaviour {
+    pub target: V2f32,
+}
+impl SeekBehaviour {
+    pub const PARAMS: &'static [&'static str] = &["x", "y"];
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        Ok(Box::new(Self {
+            target: point(params)?,
+        }))
+    }
+}
+impl Behaviour for SeekBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        seek(
+            self_boid,
+            self.target,
+            ctx.config.max_speed(self_boid.species),
+        )
+    }
+}
+
+/* Straight away from `target` while it is closer than `radius`. */
+pub struct FleeBehaviour {
+    pub target: V2f32,
+    pub radius: f32,
+}
+impl FleeBehaviour {
+    pub const PARAMS: &'static [&'static str] = &["x", "y", "radius"];
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        Ok(Box::new(Self {
+            target: point(params)?,
+            radius: positive(params, "radius", f32::MAX)?,
+        }))
+    }
+}
+impl Behaviour for FleeBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        if V2f32::distance(self_boid.position, self.target) >= self.radius {
+            return V2f32::zero();
+        }
+        flee(
+            self_boid,
+            self.target,
+            ctx.config.max_speed(self_boid.species),
+        )
+    }
+}
+
+/* Like seek, but slows down linearly inside `slowing_radius` and stops on the target. */
+pub struct ArriveBehaviour {
+    pub target: V2f32,
+    pub slowing_radius: f32,
+}
+impl ArriveBehaviour {
+    pub const DEFAULT_SLOWING_RADIUS: f32 = VIEW_DISTANCE;
+    pub const PARAMS: &'static [&'static str] = &["x", "y", "slowing_radius"];
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        Ok(Box::new(Self {
+            target: point(params)?,
+            slowing_radius: positive(params, "slowing_radius", Self::DEFAULT_SLOWING_RADIUS)?,
+        }))
+    }
+}
+impl Behaviour for ArriveBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let distance = V2f32::distance(self_boid.position, self.target);
+        let speed =
+            ctx.config.max_speed(self_boid.species) * (distance / self.slowing_radius).min(1.0);
+        desired_velocity(self_boid.position, self.target, speed) - self_boid.velocity
+    }
+}
+
+/* Which neighbour pursue and evade react to. */
+pub struct QuarryFilter {
+    /* Only this boid, if it is among the neighbours. */
+    pub target: Option<BoidId>,
+    /* Only boids of this species. */
+    pub species: Option<Species>,
+    /* Only boids closer than this. */
+    pub radius: f32,
+}
+
+impl QuarryFilter {
+    fn from_params(params: &BehaviourParams) -> Result<Self, String> {
+        Ok(Self {
+            target: params.get_optional("target")?,
+            species: params.get_optional("species")?,
+            radius: positive(params, "radius", f32::MAX)?,
+        })
+    }
+}
+
+/* The boid the filter picks, the closest one if more than one passes. */
+fn quarry<'a>(
+    self_boid: &Boid,
+    other_boids: &[&'a Boid],
+    filter: &QuarryFilter,
+) -> Option<&'a Boid> {
+    let radius_squared = filter.radius * filter.radius;
+    let mut others = other_boids.iter().copied().filter(|b| {
+        b.id != self_boid.id
+            && filter.species.is_none_or(|species| b.species == species)
+            && distance_squared(b.position, self_boid.position) < radius_squared
+    });
+    match filter.target {
+        Some(id) => others.find(|b| b.id == id),
+        None => others.min_by(|a, b| {
+            distance_squared(a.position, self_boid.position)
+                .total_cmp(&distance_squared(b.position, self_boid.position))
+        }),
+    }
+}
+
+/*
+ * Where `quarry` will be when the boid could get there, assuming it keeps its
+ * velocity. Looks at most `max_prediction` seconds ahead.
+ */
+fn predicted_position(
+    self_boid: &Boid,
+    quarry: &Boid,
+    max_speed: f32,
+    max_prediction: f32,
+) -> V2f32 {
+    let distance = V2f32::distance(self_boid.position, quarry.position);
+    let seconds = if max_speed > 0.0 {
+        (distance / max_speed).min(max_prediction)
+    } else {
+        max_prediction
+    };
+    quarry.position + quarry.velocity * seconds
+}
+
+/* Seeks where the target boid is heading. */
+pub struct PursueBehaviour {
+    pub quarry: QuarryFilter,
+    pub max_prediction: f32,
+}
+/* Flees from where the target boid is heading. */
+pub struct EvadeBehaviour {
+    pub quarry: QuarryFilter,
+    pub max_prediction: f32,
+}
+
+macro_rules! pursuit_params {
+    ($t:ty) => {
+        impl $t {
+            pub const DEFAULT_MAX_PREDICTION: f32 = 0.5;
+            pub const PARAMS: &'static [&'static str] =
+                &["target", "species", "radius", "max_prediction"];
+
+            pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+                Ok(Box::new(Self {
+                    quarry: QuarryFilter::from_params(params)?,
+                    max_prediction: positive(
+                        params,
+                        "max_prediction",
+                        Self::DEFAULT_MAX_PREDICTION,
+                    )?,
+                }))
+            }
+        }
+    };
+}
+pursuit_params!(PursueBehaviour);
+pursuit_params!(EvadeBehaviour);
+
+impl Behaviour for PursueBehaviour {
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let max_speed = ctx.config.max_speed(self_boid.species);
+        match quarry(self_boid, other_boids, &self.quarry) {
+            Some(quarry) => {
+                let target = predicted_position(self_boid, quarry, max_speed, self.max_prediction);
+                seek(self_boid, target, max_speed)
+            }
+            None => V2f32::zero(),
+        }
+    }
+}
+impl Behaviour for EvadeBehaviour {
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let max_speed = ctx.config.max_speed(self_boid.species);
+        match quarry(self_boid, other_boids, &self.quarry) {
+            Some(quarry) => {
+                let target = predicted_position(self_boid, quarry, max_speed, self.max_prediction);
+                flee(self_boid, target, max_speed)
+            }
+            None => V2f32::zero(),
+        }
+    }
+}
+
+/* Where on the wander circle a boid heads to. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WanderMode {
+    /* Perlin noise over the position, the path bends smoothly. */
+    #[default]
+    Perlin,
+    /* A new random point every tick, the path trembles. */
+    Jitter,
+}
+
+impl WanderMode {
+    pub const ALL: [WanderMode; 2] = [WanderMode::Perlin, WanderMode::Jitter];
+
+    fn name(self) -> &'static str {
+        match self {
+            WanderMode::Perlin => "perlin",
+            WanderMode::Jitter => "jitter",
+        }
+    }
+}
+
+impl fmt::Display for WanderMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for WanderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| "expected one of perlin, jitter".to_string())
+    }
+}
+
+/*
+ * Seeks a point on a circle of `radius` that is `distance` ahead of the boid.
+ * Behaviours can't keep state between ticks, so the point on the circle is a
+ * function of the boid and its position, reproducible for the same `seed`.
+ */
+pub struct WanderBehaviour {
+    pub mode: WanderMode,
+    pub distance: f32,
+    pub radius: f32,
+    /* Noise cycles per unit travelled, only used by Perlin. */
+    pub frequency: f32,
+    pub seed: u64,
+    noise: Perlin,
+}
+impl WanderBehaviour {
+    pub const DEFAULT_DISTANCE: f32 = 30.0;
+    pub const DEFAULT_RADIUS: f32 = 15.0;
+    pub const DEFAULT_FREQUENCY: f32 = 0.01;
+    pub const PARAMS: &'static [&'static str] =
+        &["mode", "distance", "radius", "frequency", "seed"];
+
+    pub fn new(mode: WanderMode, seed: u64) -> Self {
+        Self {
+            mode,
+            distance: Self::DEFAULT_DISTANCE,
+            radius: Self::DEFAULT_RADIUS,
+            frequency: Self::DEFAULT_FREQUENCY,
+            seed,
+            noise: Perlin::new(seed),
+        }
+    }
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        let mut wander = Self::new(
+            params.get("mode", WanderMode::default())?,
+            params.get("seed", 0)?,
+        );
+        wander.distance = params.get("distance", Self::DEFAULT_DISTANCE)?;
+        if !(wander.distance.is_finite() && wander.distance >= 0.0) {
+            return Err(format!("distance {} must not be negative", wander.distance));
+        }
+        wander.radius = positive(params, "radius", Self::DEFAULT_RADIUS)?;
+        wander.frequency = positive(params, "frequency", Self::DEFAULT_FREQUENCY)?;
+        Ok(Box::new(wander))
+    }
+
+    /* Angle on the circle relative to the heading, in about -PI..=PI. */
+    fn angle(&self, boid: &Boid) -> f32 {
+        let position = boid.position;
+        match self.mode {
+            WanderMode::Perlin => {
+                /* Every boid samples its own stretch of the noise. */
+                let offset = unit(mix(boid.id as u64)) * 256.0;
+                let n = self.noise.get(
+                    position.x * self.frequency + offset,
+                    position.y * self.frequency,
+                );
+                n * PI
+            }
+            WanderMode::Jitter => {
+                let bits = (position.x.to_bits() as u64) << 32 | position.y.to_bits() as u64;
+                let hash = mix(self.seed ^ mix(boid.id as u64) ^ mix(bits));
+                (unit(hash) * 2.0 - 1.0) * PI
+            }
+        }
+    }
+}
+impl Behaviour for WanderBehaviour {
+    fn calculate(&self, self_boid: &Boid, _: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let heading = self_boid.heading().unwrap_or(Vector2::new(1.0, 0.0));
+        let angle = heading.y.atan2(heading.x) + self.angle(self_boid);
+        let target = self_boid.position
+            + heading * self.distance
+            + Vector2::new(angle.cos(), angle.sin()) * self.radius;
+        seek(self_boid, target, ctx.config.max_speed(self_boid.species))
+    }
+}
+
+#[cfg(test)]
+fn params(pairs: &[(&str, &str)]) -> BehaviourParams {
+    let mut params = BehaviourParams::default();
+    for (key, value) in pairs {
+        params.set(key, value);
+    }
+    params
+}
+
+#[test]
+fn seek_and_flee_point_at_and_away_from_the_target() {
+    let ctx = SimulationContext::default();
+    let boid = Boid::new(0, Vector2::new(100.0, 100.0), Vector2::zero());
+    let target = params(&[("x", "200"), ("y", "100")]);
+
+    let towards = SeekBehaviour::from_params(&target)
+        .unwrap()
+        .calculate(&boid, &[], &ctx);
+    assert_eq!(towards, Vector2::new(ctx.config.max_boid_speed, 0.0));
+    let away = FleeBehaviour::from_params(&target)
+        .unwrap()
+        .calculate(&boid, &[], &ctx);
+    assert_eq!(away, Vector2::new(-ctx.config.max_boid_speed, 0.0));
+
+    /* Flee ignores targets outside of its radius. */
+    let far = params(&[("x", "200"), ("y", "100"), ("radius", "50")]);
+    let away = FleeBehaviour::from_params(&far)
+        .unwrap()
+        .calculate(&boid, &[], &ctx);
+    assert_eq!(away, V2f32::zero());
+
+    /* Moving sideways, seeking turns the velocity towards the target. */
+    let moving = Boid::new(0, Vector2::new(100.0, 100.0), Vector2::new(0.0, 1.0));
+    let steering = SeekBehaviour {
+        target: Vector2::new(200.0, 100.0),
+    }
+    .calculate(&moving, &[], &ctx);
+    assert!(steering.x > 0.0 && steering.y < 0.0);
+
+    let on_target = Boid::new(0, Vector2::new(200.0, 100.0), Vector2::zero());
+    assert_eq!(seek(&on_target, on_target.position, 2.0), V2f32::zero());
+    assert!(SeekBehaviour::from_params(&params(&[("x", "1")])).is_err());
+}
+
+#[test]
+fn arrive_slows_down_inside_the_slowing_radius() {
+    let ctx = SimulationContext::default();
+    let max_speed = ctx.config.max_boid_speed;
+    let arrive = ArriveBehaviour {
+        target: Vector2::new(100.0, 0.0),
+        slowing_radius: 50.0,
+    };
+    /* Far away it behaves like seek. */
+    let far = Boid::new(0, Vector2::new(-100.0, 0.0), Vector2::zero());
+    assert_eq!(
+        arrive.calculate(&far, &[], &ctx),
+        Vector2::new(max_speed, 0.0)
+    );
+    /* Halfway into the radius at full speed it brakes to half speed. */
+    let close = Boid::new(0, Vector2::new(75.0, 0.0), Vector2::new(max_speed, 0.0));
+    let steering = arrive.calculate(&close, &[], &ctx);
+    approx::assert_relative_eq!(steering.x, -max_speed / 2.0, epsilon = 1e-5);
+    /* On the target it only cancels the velocity. */
+    let there = Boid::new(0, Vector2::new(100.0, 0.0), Vector2::new(1.0, 1.0));
+    assert_eq!(
+        arrive.calculate(&there, &[], &ctx),
+        Vector2::new(-1.0, -1.0)
+    );
+}
+
+#[test]
+fn pursue_and_evade_lead_the_target() {
+    let ctx = SimulationContext::default();
+    let hunter = Boid::new(0, Vector2::new(0.0, 0.0), Vector2::zero());
+    /* Straight ahead, running up. */
+    let runner = Boid::new(1, Vector2::new(100.0, 0.0), Vector2::new(0.0, -1.0));
+    let bystander = Boid::new(2, Vector2::new(0.0, 30.0), Vector2::zero());
+
+    let pursue = PursueBehaviour::from_params(&params(&[("target", "1")])).unwrap();
+    let steering = pursue.calculate(&hunter, &[&hunter, &runner, &bystander], &ctx);
+    assert!(steering.x > 0.0 && steering.y < 0.0, "{}", steering);
+    let evade = EvadeBehaviour::from_params(&params(&[("target", "1")])).unwrap();
+    let steering = evade.calculate(&hunter, &[&runner, &bystander], &ctx);
+    assert!(steering.x < 0.0 && steering.y > 0.0, "{}", steering);
+
+    /* Without a target the closest neighbour is chased. */
+    let pursue = PursueBehaviour::from_params(&params(&[])).unwrap();
+    let steering = pursue.calculate(&hunter, &[&runner, &bystander], &ctx);
+    assert_eq!(steering, Vector2::new(0.0, ctx.config.max_boid_speed));
+    /* A target that can't be seen is ignored. */
+    let pursue = PursueBehaviour::from_params(&params(&[("target", "7")])).unwrap();
+    assert_eq!(pursue.calculate(&hunter, &[&runner], &ctx), V2f32::zero());
+    assert!(PursueBehaviour::from_params(&params(&[("target", "first")])).is_err());
+
+    /* Species and radius narrow down who is reacted to. */
+    let predator = runner.with_species(Species::Predator);
+    let evade = EvadeBehaviour::from_params(&params(&[("species", "predator")])).unwrap();
+    let steering = evade.calculate(&hunter, &[&predator, &bystander], &ctx);
+    assert!(steering.x < 0.0, "{}", steering);
+    let close = params(&[("species", "predator"), ("radius", "50")]);
+    let evade = EvadeBehaviour::from_params(&close).unwrap();
+    assert_eq!(
+        evade.calculate(&hunter, &[&predator, &bystander], &ctx),
+        V2f32::zero()
+    );
+    assert!(EvadeBehaviour::from_params(&params(&[("species", "wolf")])).is_err());
+}
+
+#[test]
+fn wander_heads_somewhere_ahead() {
+    let ctx = SimulationContext::default();
+    for mode in WanderMode::ALL {
+        assert_eq!(mode.to_string().parse::<WanderMode>(), Ok(mode));
+        let wander = WanderBehaviour::new(mode, 3);
+        let (mut left, mut right) = (false, false);
+        for i in 0..100 {
+            let boid = Boid::new(
+                i % 7,
+                Vector2::new(i as f32 * 13.7, 300.0 - i as f32 * 5.3),
+                Vector2::new(0.1, 0.0),
+            );
+            let steering = wander.calculate(&boid, &[], &ctx);
+            assert!(steering.x > 0.0, "{} {}", mode, steering);
+            assert_eq!(steering, wander.calculate(&boid, &[], &ctx));
+            left |= steering.y < 0.0;
+            right |= steering.y > 0.0;
+        }
+        assert!(left && right, "{}", mode);
+    }
+
+    /* Perlin wander turns smoothly along the path. */
+    let wander = WanderBehaviour::new(WanderMode::Perlin, 3);
+    let at = |x: f32| {
+        let boid = Boid::new(0, Vector2::new(x, 100.0), Vector2::new(1.0, 0.0));
+        let mut steering = wander.calculate(&boid, &[], &ctx) + boid.velocity;
+        steering.set_magnitude(1.0);
+        steering
+    };
+    assert!(at(100.0).dot(at(101.0)) > 0.99);
+    assert!("spiral".parse::<WanderMode>().is_err());
+    assert!(WanderBehaviour::from_params(&params(&[("radius", "0")])).is_err());
+}
@@ -1,128 +1,171 @@
-use crate::constants::{
-    BehaviourConsts, BehaviourEnabled, BEHAVIOUR_ENABLED, MAX_BOID_SPEED, SCREEN_SIZE,
-    VIEW_PORT_SIZE,
+use serde::{Deserialize, Serialize};
+
+use crate::config::SimulationConfig;
+use crate::constants::VIEW_DISTANCE;
+use crate::logic::behaviour::pipeline::BehaviourParams;
+use crate::logic::behaviour::separation::{
+    escape_direction, SeparationKernel, MIN_SEPARATION_DISTANCE,
 };
 use crate::logic::boid::boid_impl::Boid;
+use crate::logic::context::SimulationContext;
 use crate::math::quadtree::region::Region;
+use crate::math::vec::Vector2;
 use crate::math::vec::{Distance, Magnitude, V2f32};
-use crate::math::vec::{Normalize, Vector2};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BorderBehaviourE {
     GoThrough,
     Reflect,
 }
+impl BorderBehaviourE {
+    pub fn toggled(self) -> Self {
+        match self {
+            BorderBehaviourE::GoThrough => BorderBehaviourE::Reflect,
+            BorderBehaviourE::Reflect => BorderBehaviourE::GoThrough,
+        }
+    }
+}
 
 pub trait BorderBehaviour {
-    fn border(&mut self, e: &BorderBehaviourE);
+    fn border(&mut self, e: &BorderBehaviourE, config: &SimulationConfig);
 }
+/*
+ * Steering of `self_boid` from the neighbours it perceives. BoidManager only passes
+ * boids inside the field of view, but `self_boid` itself is skipped if it is given.
+ * The result is scaled by the weight of the pipeline step, see SteeringPipeline.
+ */
 pub trait Behaviour: Send + Sync {
-    fn calculate(&self, self_boid: &Boid, other_boids: &[Boid]) -> V2f32;
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32;
 }
 
+/* Align and cohesion only flock with boids of the same species. */
 pub struct AlignBehaviour;
 impl Behaviour for AlignBehaviour {
-    fn calculate(&self, self_boid: &Boid, other_boids: &[Boid]) -> V2f32 {
-        if !unsafe { BEHAVIOUR_ENABLED.contains(BehaviourEnabled::ALLIGN) } {
-            return V2f32::zero();
-        }
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32 {
         log::info!("Other boids : {:?}", other_boids);
-        let mut avarage_velocity: V2f32 = other_boids
+        let (mut avarage_velocity, other) = other_boids
             .iter()
-            .filter_map(|boid| match boid.id == self_boid.id {
-                true => None,
-                false => Some(boid.velocity),
-            })
-            .sum::<V2f32>();
+            .filter(|boid| boid.id != self_boid.id && boid.species == self_boid.species)
+            .fold((V2f32::zero(), 0), |(sum, other), boid| {
+                (sum + boid.velocity, other + 1)
+            });
         log::info!("avarage_velocity in Align{ } ", avarage_velocity);
 
         if avarage_velocity != Vector2::zero() {
-            avarage_velocity /= (other_boids.len() - 1) as f32;
-            avarage_velocity.set_magnitude(MAX_BOID_SPEED);
+            avarage_velocity /= other as f32;
+            avarage_velocity.set_magnitude(ctx.config.max_speed(self_boid.species));
             avarage_velocity -= self_boid.velocity;
-            avarage_velocity *= BehaviourConsts::ALLIGN_FACTOR;
         }
         avarage_velocity
     }
 }
 pub struct CohesionBehaviour;
 impl Behaviour for CohesionBehaviour {
-    fn calculate(&self, self_boid: &Boid, other_boids: &[Boid]) -> V2f32 {
-        if !unsafe { BEHAVIOUR_ENABLED.contains(BehaviourEnabled::COHESION) } {
-            return V2f32::zero();
-        }
-        let mut avarage_position: V2f32 = other_boids
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let (mut avarage_position, other) = other_boids
             .iter()
-            .filter_map(|boid| match boid.id == self_boid.id {
-                true => None,
-                false => Some(boid.position),
-            })
-            .sum::<V2f32>();
+            .filter(|boid| boid.id != self_boid.id && boid.species == self_boid.species)
+            .fold((V2f32::zero(), 0), |(sum, other), boid| {
+                (sum + boid.position, other + 1)
+            });
 
-        if avarage_position != V2f32::zero() {
-            avarage_position /= (other_boids.len() - 1) as f32;
+        if other > 0 {
+            avarage_position /= other as f32;
             avarage_position -= self_boid.position;
-            avarage_position.set_magnitude(MAX_BOID_SPEED);
+        }
+        /* Zero when there is nobody else or the centre is right where the boid is. */
+        if avarage_position != V2f32::zero() {
+            avarage_position.set_magnitude(ctx.config.max_speed(self_boid.species));
             avarage_position -= self_boid.velocity;
-            avarage_position *= BehaviourConsts::COHESION_FACTOR;
         }
         avarage_position
     }
 }
-pub struct SeperateBehaviour;
-impl Behaviour for SeperateBehaviour {
-    fn calculate(&self, self_boid: &Boid, other_boids: &[Boid]) -> V2f32 {
-        if !unsafe { BEHAVIOUR_ENABLED.contains(BehaviourEnabled::SEPERATE) } {
-            return V2f32::zero();
+/*
+ * Pushes away from every neighbour inside `radius`, weighted by `kernel`.
+ * Boids on the same spot get opposite escape directions.
+ */
+pub struct SeperateBehaviour {
+    pub radius: f32,
+    pub kernel: SeparationKernel,
+}
+impl SeperateBehaviour {
+    pub const DEFAULT_RADIUS: f32 = VIEW_DISTANCE / 2.0;
+    pub const PARAMS: &'static [&'static str] = &["radius", "kernel"];
+
+    pub fn from_params(params: &BehaviourParams) -> Result<Box<dyn Behaviour>, String> {
+        let radius = params.get("radius", Self::DEFAULT_RADIUS)?;
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(format!("radius {} must be a positive number", radius));
         }
-        let mut avarage_position = V2f32::zero();
-        let mut other = 0;
+        Ok(Box::new(Self {
+            radius,
+            kernel: params.get("kernel", SeparationKernel::default())?,
+        }))
+    }
+}
+impl Default for SeperateBehaviour {
+    fn default() -> Self {
+        Self {
+            radius: Self::DEFAULT_RADIUS,
+            kernel: SeparationKernel::default(),
+        }
+    }
+}
+impl Behaviour for SeperateBehaviour {
+    fn calculate(&self, self_boid: &Boid, other_boids: &[&Boid], ctx: &SimulationContext) -> V2f32 {
+        let radius = self.radius;
+        let mut away = V2f32::zero();
         for b in other_boids {
-            if b.id != self_boid.id {
-                let distance = V2f32::distance(self_boid.position, b.position);
-                let mut diff = self_boid.position - b.position;
-                diff /= (distance * distance);
-                avarage_position += diff;
-                other += 1;
+            if b.id == self_boid.id {
+                continue;
             }
+            let distance = V2f32::distance(self_boid.position, b.position);
+            let weight = self.kernel.weight(distance, radius);
+            if weight == 0.0 {
+                continue;
+            }
+            let direction = if distance > MIN_SEPARATION_DISTANCE {
+                (self_boid.position - b.position) / distance
+            } else {
+                escape_direction(self_boid.id, b.id)
+            };
+            away += direction * weight;
         }
-        if other > 0 {
-            avarage_position.set_magnitude(MAX_BOID_SPEED);
-            avarage_position -= self_boid.velocity;
-            avarage_position *= BehaviourConsts::SEPERATE_FACTOR;
+        /* Zero as well when pushes from opposite sides cancel out. */
+        if away != V2f32::zero() {
+            away.set_magnitude(ctx.config.max_speed(self_boid.species));
+            away -= self_boid.velocity;
         }
-        avarage_position
+        away
     }
 }
 
+/* Pushes boids outside `bound_region` back in at their max speed. */
 pub struct BoundBehaviour;
 impl Behaviour for BoundBehaviour {
-    fn calculate(&self, self_boid: &Boid, _other_boids: &[Boid]) -> V2f32 {
-        if !unsafe { BEHAVIOUR_ENABLED.contains(BehaviourEnabled::BOUND) } {
-            return V2f32::zero();
-        }
-
-        let r: Region = Region::new(
-            Vector2::new(100.0, 100.0),
-            Vector2::new(
-                (VIEW_PORT_SIZE.x - 100.0) as f32,
-                (VIEW_PORT_SIZE.y - 100.0) as f32,
-            ),
-        );
+    fn calculate(
+        &self,
+        self_boid: &Boid,
+        _other_boids: &[&Boid],
+        ctx: &SimulationContext,
+    ) -> V2f32 {
+        let r: Region = ctx.config.bound_region();
         let x = if self_boid.position.x < r.left_up.x {
-            BehaviourConsts::BOUND_FACTOR
+            1.0
         } else if self_boid.position.x > r.right_down.x {
-            -BehaviourConsts::BOUND_FACTOR
+            -1.0
         } else {
             0.0
         };
 
         let y = if self_boid.position.y < r.left_up.y {
-            BehaviourConsts::BOUND_FACTOR
+            1.0
         } else if self_boid.position.y > r.right_down.y {
-            -BehaviourConsts::BOUND_FACTOR
+            -1.0
         } else {
             0.0
         };
-        Vector2::new(x, y)
+        Vector2::new(x, y) * ctx.config.max_speed(self_boid.species)
     }
 }
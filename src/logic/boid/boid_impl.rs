@@ -1,30 +1,31 @@
-use sdl2::{
-    gfx::primitives::DrawRenderer,
-    pixels::Color,
-    rect::{Point, Rect},
-    render::WindowCanvas,
-};
+use serde::{Deserialize, Serialize};
 
-use super::traits::*;
+use super::{species::Species, traits::*};
 use crate::{
-    camera::Camera,
-    constants::{
-        types::BoidId, DrawPrimitives, BOID_COLOR, BOID_ID_ITERATOR, BOID_SIZE, BORDER_BEHAVIOUR,
-        DRAW_PRIMITIVES, MAX_BOID_FORCE, MAX_BOID_SPEED, VIEW_COLOR,
-    },
-    graphics::renderer::Renderable,
-    logic::behaviour::traits::BorderBehaviour,
-    math::{
-        quadtree::region::Region,
-        vec::{Magnitude, V2f32},
-    },
+    config::SimulationConfig,
+    constants::{types::BoidId, BOID_MASS},
+    logic::context::SimulationContext,
+    math::vec::{Finite, Magnitude, V2f32},
 };
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+/*
+ * A boid by value, gathered from the components of its entity: what
+ * behaviours, spatial indices, recordings and snapshots work with.
+ */
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Boid {
     pub position: V2f32,
     pub velocity: V2f32,
     pub id: BoidId,
+    #[serde(default)]
+    pub species: Species,
+    /* Steering forces are divided by it. */
+    #[serde(default = "default_mass")]
+    pub mass: f32,
+}
+
+fn default_mass() -> f32 {
+    BOID_MASS
 }
 /*
 impl std::fmt::Debug for Boid {
@@ -36,46 +37,69 @@ impl std::fmt::Debug for Boid {
     }
 }*/
 impl Boid {
-    pub fn new(position: V2f32, velocity: V2f32) -> Self {
+    pub fn new(id: BoidId, position: V2f32, velocity: V2f32) -> Self {
         Self {
             position,
             velocity,
-            id: BOID_ID_ITERATOR.with(|id| id.borrow_mut().get_next()),
+            id,
+            species: Species::default(),
+            mass: BOID_MASS,
         }
     }
-}
 
-impl Renderable for Boid {
-    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera) {
-        canvas.set_draw_color(BOID_COLOR);
-        DRAW_PRIMITIVES.with(|value| {
-            if value.borrow().contains(DrawPrimitives::BOID_VIEW) {
-                canvas.set_draw_color(VIEW_COLOR);
-                let r = Region::rect_from_center(camera.calc_pos_v2f32(self.position));
-                let _ = canvas.draw_rect(Rect::from(r));
-            }
-        });
-        canvas.set_draw_color(BOID_COLOR);
-        let r = Region::rect_from_center_with_distance(
-            camera.calc_pos_v2f32(self.position),
-            BOID_SIZE as f32,
-        );
-        let _ = canvas.rectangle(
-            r.left_up.x as i16,
-            r.left_up.y as i16,
-            r.right_down.x as i16,
-            r.right_down.y as i16,
-            Color::BLUE,
-        );
+    pub fn with_species(mut self, species: Species) -> Self {
+        self.species = species;
+        self
+    }
+
+    pub fn with_mass(mut self, mass: f32) -> Self {
+        self.mass = mass;
+        self
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.position.is_finite() && self.velocity.is_finite()
+    }
+
+    /*
+     * Puts a boid that ended up with a NaN or infinite position or velocity back
+     * into a usable state: the last finite value, or the view port centre and no
+     * velocity when `previous` wasn't finite either.
+     */
+    pub fn recover(&mut self, previous: &Boid, config: &SimulationConfig) {
+        if !self.position.is_finite() {
+            self.position = match previous.position.is_finite() {
+                true => previous.position,
+                false => config.view_port_size / 2.0,
+            };
+        }
+        if !self.velocity.is_finite() {
+            self.velocity = match previous.velocity.is_finite() {
+                true => previous.velocity,
+                false => V2f32::zero(),
+            };
+        }
     }
 }
+
+/*
+ * One simulation step of `config.time_step()` seconds with `config.integrator`.
+ * The force, in units per second squared times mass, is clamped to
+ * `max_boid_force` before it is divided by the mass. The border is left to
+ * the BorderSystem.
+ */
 impl UpdatableAcceleration for Boid {
-    fn update(&mut self, acceleration: V2f32) {
-        log::info!("UpdateAcceleration acceleration {:?}", acceleration);
-        BORDER_BEHAVIOUR.with(|beh| self.border(&beh.borrow()));
-        self.position += self.velocity;
-        self.velocity += acceleration; // * MAX_BOID_FORCE;
-        self.velocity.limit(MAX_BOID_SPEED);
+    fn update<F: Fn(&Self) -> V2f32>(&mut self, force: F, ctx: &SimulationContext) {
+        let config = &ctx.config;
+        let acceleration = |state: &Boid| {
+            let mut force = force(state);
+            force.limit(config.max_boid_force);
+            force / state.mass
+        };
+        *self = config
+            .integrator
+            .step(self, config.time_step(), acceleration);
+        self.velocity.limit(config.max_speed(self.species));
         log::info!("update {:?}", self);
     }
 }
@@ -1,158 +1,1360 @@
-use rayon::prelude::*;
-use sdl2::render::WindowCanvas;
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    camera::Camera,
-    constants::{
-        types::BoidId, DrawPrimitives, DRAW_PRIMITIVES, MAX_BOID_IN_AREA, SCREEN_SIZE,
-        VIEW_PORT_SIZE,
-    },
-    graphics::renderer::Renderable,
-    logic::behaviour::traits::{
-        AlignBehaviour, Behaviour, BoundBehaviour, CohesionBehaviour, SeperateBehaviour,
+    config::ConfigError,
+    config::SimulationConfig,
+    constants::{types::BoidId, BOID_RADIUS, SPAWN_SPEED},
+    ecs::{component_mgr::Component, entity::Entity, world::World, EcsError},
+    logic::{
+        behaviour::{
+            pipeline::{tick_seed, BehaviourConfig, SteeringPipeline},
+            registry::BehaviourRegistry,
+        },
+        context::SimulationContext,
+        obstacle::resolve_collisions,
     },
     math::{
-        quadtree::{quadt::QuadTree, region::Region},
+        spatial::{distance_squared, SpatialIndex, SpatialIndexKind},
         vec::{Magnitude, V2f32, Vector2},
     },
+    recording::{create_writer, RecordingError, TrajectoryWriter},
+};
+#[cfg(feature = "render")]
+use {
+    super::systems::RenderSystem,
+    crate::{
+        camera::Camera, constants::DrawPrimitives, graphics::renderer::Renderable,
+        math::quadtree::region::Region,
+    },
+    sdl2::render::WindowCanvas,
 };
 
 use super::{
     boid_impl::Boid,
-    traits::{Updatable, UpdatableAcceleration},
+    components::{self, Mass, Position, PreviousPosition, Velocity},
+    handle::{BoidHandle, BoidHandles},
+    perception::{Occluder, Perception},
+    snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION},
+    species::{CatchAction, Population, Species},
+    systems::{self, gather_boid, register_boid_systems, Flock, Steering},
+    traits::Updatable,
 };
 
+/*
+ * Every boid is an entity of `world` with the components in `components`,
+ * moved by the systems in `systems`. The Flock, Steering and SimulationContext
+ * resources of those systems live in `world` as well.
+ */
 pub struct BoidManager {
-    pub boids: Vec<Boid>,
-    pub behaviours: Vec<Box<dyn Behaviour>>,
-    pub quad_tree: QuadTree,
-    update_tick: u8,
+    pub registry: BehaviourRegistry,
+    world: World,
+    handles: BoidHandles,
+    rng: ChaCha8Rng,
+    seed: u64,
+    tick: u64,
+    /* Prey caught by predators since the start. */
+    caught: u64,
+    /* The Perception components are set from the config before the next update. */
+    perceptions_dirty: bool,
+    recorder: Option<Box<dyn TrajectoryWriter>>,
 }
 impl BoidManager {
-    pub fn new(starting_region: Region) -> Self {
+    pub fn new(mut config: SimulationConfig) -> Self {
+        let seed = *config.seed.get_or_insert_with(|| rand::thread_rng().gen());
+        log::info!("simulation seed {}", seed);
+        let registry = BehaviourRegistry::default();
+        let pipelines = build_pipelines(&registry, &config);
+        let mut world = World::new();
+        register_boid_systems(&mut world).expect("boid components fit in a signature");
+        world.insert_resource(Flock::new(config.spatial_index.create(&config)));
+        world.insert_resource(Steering {
+            pipelines,
+            occluders: Vec::new(),
+            dither: 0,
+        });
+        world.insert_resource(SimulationContext::new(config));
         Self {
-            boids: Vec::new(),
-            behaviours: vec![
-                Box::new(AlignBehaviour {}),
-                Box::new(SeperateBehaviour {}),
-                Box::new(CohesionBehaviour {}),
-                Box::new(BoundBehaviour {}),
-            ],
-            quad_tree: QuadTree::new(starting_region),
-            update_tick: 0,
+            registry,
+            world,
+            handles: BoidHandles::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+            tick: 0,
+            caught: 0,
+            perceptions_dirty: false,
+            recorder: None,
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            tick: self.tick,
+            config: self.context().config.clone(),
+            border_behaviour: self.context().border_behaviour,
+            rng: self.rng.clone(),
+            handles: self.handles.clone(),
+            boids: self.boids().to_vec(),
+            caught: self.caught,
+        }
+    }
+
+    /* Components attached with `add_component` are not part of a snapshot. */
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let mut boid_manager = Self::new(snapshot.config);
+        boid_manager.context_mut().border_behaviour = snapshot.border_behaviour;
+        boid_manager.rng = snapshot.rng;
+        boid_manager.handles = snapshot.handles;
+        boid_manager.tick = snapshot.tick;
+        boid_manager.caught = snapshot.caught;
+        for boid in snapshot.boids {
+            let Some(handle) = boid_manager.handles.handle(boid.id) else {
+                log::error!("boid {} has no handle in the snapshot, dropped", boid.id);
+                continue;
+            };
+            let entity = boid_manager.create_entity();
+            boid_manager.handles.moved(boid.id, entity);
+            boid_manager.attach(entity, handle, boid);
+        }
+        boid_manager.refresh();
+        boid_manager
+    }
+
+    /* Format is picked from the extension, see `SnapshotFormat::from_path`. */
+    pub fn save_snapshot(&self, path: &Path) -> Result<(), SnapshotError> {
+        self.snapshot().save(path)
+    }
+
+    pub fn load_snapshot(path: &Path) -> Result<Self, SnapshotError> {
+        Snapshot::load(path).map(Self::from_snapshot)
+    }
+
+    /*
+     * Streams the boids to `path` after every update, starting with the current state.
+     * Format is picked from the extension, see `RecordingFormat::from_path`.
+     */
+    pub fn record_to(&mut self, path: &Path) -> Result<(), RecordingError> {
+        self.set_recorder(create_writer(path)?)
+    }
+    pub fn set_recorder(
+        &mut self,
+        mut recorder: Box<dyn TrajectoryWriter>,
+    ) -> Result<(), RecordingError> {
+        self.stop_recording()?;
+        recorder.write_frame(self.tick, self.boids())?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+    pub fn stop_recording(&mut self) -> Result<(), RecordingError> {
+        match self.recorder.take() {
+            Some(mut recorder) => recorder.finish(),
+            None => Ok(()),
         }
     }
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /* Same flock for the same seed and config, use it to reproduce a run. */
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.reseed(seed);
+        self
+    }
+    pub fn reseed(&mut self, seed: u64) {
+        log::info!("simulation seed {}", seed);
+        self.seed = seed;
+        self.context_mut().config.seed = Some(seed);
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        /* Behaviours without a seed of their own take the new one. */
+        let pipelines = build_pipelines(&self.registry, &self.context().config);
+        self.resource_mut::<Steering>().pipelines = pipelines;
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-    pub fn add_boid(&mut self, amount: u64) {
+    /* Resources put in by `new`, see `world_mut`. */
+    fn resource<T: Component>(&self) -> &T {
+        self.world
+            .resource()
+            .expect("boid resources stay in the world")
+    }
+    fn resource_mut<T: Component>(&mut self) -> &mut T {
+        self.world
+            .resource_mut()
+            .expect("boid resources stay in the world")
+    }
+
+    pub fn context(&self) -> &SimulationContext {
+        self.resource()
+    }
+
+    /* The boids see with the new config from the next update on. */
+    pub fn context_mut(&mut self) -> &mut SimulationContext {
+        self.perceptions_dirty = true;
+        self.resource_mut()
+    }
+
+    /*
+     * Every boid by value. The order changes when boids are removed, keep a
+     * `BoidHandle` to find one again.
+     */
+    pub fn boids(&self) -> &[Boid] {
+        &self.resource::<Flock>().boids
+    }
+
+    pub fn spatial_index(&self) -> &dyn SpatialIndex {
+        self.resource::<Flock>().index.as_ref()
+    }
+
+    /* Checked for line of sight when `config.occlusion` is on. */
+    pub fn occluders_mut(&mut self) -> &mut Vec<Box<dyn Occluder>> {
+        &mut self.resource_mut::<Steering>().occluders
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /*
+     * To register systems of your own, they run after the boid systems.
+     * Components of boids changed here show up in `boids` after the next
+     * update. The Flock, Steering and SimulationContext resources must stay.
+     */
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn population(&self) -> Population {
+        Population::count(self.boids(), self.caught)
+    }
+
+    /* Gathers `boids` again after the components were changed from here. */
+    fn refresh(&mut self) {
+        self.world
+            .resource_scope(|world, flock: &mut Flock| flock.refresh(world));
+    }
+
+    fn create_entity(&mut self) -> Entity {
+        self.world.create_entity().expect("entity ids ran out")
+    }
+
+    /* Gives `entity` every component of a boid, with the id of `handle`. */
+    fn attach(&mut self, entity: Entity, handle: BoidHandle, boid: Boid) {
+        let perception = Perception::from_config(&self.context().config, boid.species);
+        let world = &mut self.world;
+        let attached = (|| -> Result<(), EcsError> {
+            world.add_component(entity, handle)?;
+            world.add_component(entity, Position(boid.position))?;
+            world.add_component(entity, PreviousPosition(boid.position))?;
+            world.add_component(entity, Velocity(boid.velocity))?;
+            world.add_component(entity, boid.species)?;
+            world.add_component(entity, Mass(boid.mass))?;
+            world.add_component(entity, perception)?;
+            world.add_component(entity, components::Renderable::of(boid.species))
+        })();
+        if let Err(e) = attached {
+            log::error!("{}, boid {} is incomplete", e, handle);
+        }
+    }
+
+    /* A new entity for `boid`, with the id of the boid if it is still free. */
+    fn insert(&mut self, boid: Boid, id: Option<BoidId>) -> BoidHandle {
+        let entity = self.create_entity();
+        let handle = match id.and_then(|id| self.handles.insert_at(id, entity)) {
+            Some(handle) => handle,
+            None => self.handles.insert(entity),
+        };
+        self.attach(
+            entity,
+            handle,
+            Boid {
+                id: handle.id,
+                ..boid
+            },
+        );
+        handle
+    }
+
+    /*
+     * Somewhere in the view port outside the obstacles, moving at SPAWN_SPEED
+     * in a random direction.
+     */
+    fn random_placement(&mut self) -> (V2f32, V2f32) {
+        let view_port_size = self.context().config.view_port_size;
+        let mut c = Vector2::random(-0.5, 0.5, &mut self.rng);
+        c.set_magnitude(SPAWN_SPEED);
+        let rand_pos = Vector2::random_from_vec(
+            Vector2::new(0.0, view_port_size.x),
+            Vector2::new(0.0, view_port_size.y),
+            &mut self.rng,
+        );
+        let mut boid = Boid::new(0, rand_pos, c);
+        resolve_collisions(&mut boid, &self.context().config.obstacles, BOID_RADIUS);
+        (boid.position, c)
+    }
+
+    pub fn add_boid(&mut self, amount: u64) -> Vec<BoidHandle> {
+        self.add_species(Species::Prey, amount)
+    }
+    pub fn add_species(&mut self, species: Species, amount: u64) -> Vec<BoidHandle> {
+        let mut added = Vec::with_capacity(amount as usize);
+        let mass = self.context().config.mass(species);
         for _i in 0..amount {
-            let mut c = Vector2::random(-0.5, 0.5);
-            c.set_magnitude(1.0);
-            let rand_pos = Vector2::random_from_vec(
-                Vector2::new(0.0, VIEW_PORT_SIZE.x as f32),
-                Vector2::new(0.0, VIEW_PORT_SIZE.y as f32),
-            );
-            println!("{}", rand_pos);
-            self.boids.push(Boid::new(rand_pos, c));
-        }
-        log::info!("SPAWN");
-        self.boids.iter().for_each(|boid| log::info!("{:?}", boid));
-        log::info!("END SPAWN");
-    }
-    pub fn spawn_boid(&mut self, amount: u64) {
-        self.boids = Vec::with_capacity(amount as usize);
-        self.add_boid(amount);
+            let (rand_pos, c) = self.random_placement();
+            log::debug!("spawn {} at {}", species, rand_pos);
+            let boid = Boid::new(0, rand_pos, c)
+                .with_species(species)
+                .with_mass(mass);
+            added.push(self.insert(boid, None));
+        }
+        self.refresh();
+        added
+    }
+    pub fn spawn_boid(&mut self, amount: u64) -> Vec<BoidHandle> {
+        self.remove_all_boids();
+        self.add_boid(amount)
+    }
+
+    /*
+     * Replaces every boid, like loading a frame of a recording. Ids are kept
+     * unless two boids share one, every old handle becomes stale.
+     */
+    pub fn set_boids(&mut self, boids: Vec<Boid>) -> Vec<BoidHandle> {
+        self.remove_all_boids();
+        let handles = boids
+            .into_iter()
+            .map(|boid| self.insert(boid, Some(boid.id)))
+            .collect();
+        self.refresh();
+        handles
+    }
+
+    /* The boid of `handle`, None once it was removed. */
+    pub fn get(&self, handle: BoidHandle) -> Option<Boid> {
+        gather_boid(&self.world, self.handles.entity(handle)?)
+    }
+
+    /* Handle of the live boid with `id`, for boids found by walking `boids`. */
+    pub fn handle(&self, id: BoidId) -> Option<BoidHandle> {
+        self.handles.handle(id)
+    }
+
+    /*
+     * Changes the boid of `handle` through a copy that is written back to its
+     * components, the id stays. False if it was removed.
+     */
+    pub fn modify<F: FnOnce(&mut Boid)>(&mut self, handle: BoidHandle, f: F) -> bool {
+        let written = self.write_boid(handle, f);
+        self.refresh();
+        written
+    }
+
+    /* `modify` without the refresh, for changing many boids at once. */
+    fn write_boid<F: FnOnce(&mut Boid)>(&mut self, handle: BoidHandle, f: F) -> bool {
+        let (Some(entity), Some(mut boid)) = (self.handles.entity(handle), self.get(handle)) else {
+            return false;
+        };
+        let species = boid.species;
+        f(&mut boid);
+        let perception = Perception::from_config(&self.context().config, boid.species);
+        let world = &mut self.world;
+        let written = (|| -> Result<(), EcsError> {
+            world.add_component(entity, Position(boid.position))?;
+            world.add_component(entity, Velocity(boid.velocity))?;
+            world.add_component(entity, Mass(boid.mass))?;
+            if boid.species != species {
+                world.add_component(entity, boid.species)?;
+                world.add_component(entity, perception)?;
+                world.add_component(entity, components::Renderable::of(boid.species))?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = &written {
+            log::error!("{}, boid {} not changed", e, handle);
+        }
+        written.is_ok()
+    }
+
+    /*
+     * Takes the boid of `handle` out, the last boid moves into its place.
+     * None if it was already removed.
+     */
+    pub fn remove_boid(&mut self, handle: BoidHandle) -> Option<Boid> {
+        let boid = self.take_boid(handle);
+        self.refresh();
+        boid
+    }
+
+    /* `remove_boid` without the refresh, for removing many boids at once. */
+    fn take_boid(&mut self, handle: BoidHandle) -> Option<Boid> {
+        let entity = self.handles.remove(handle)?;
+        let boid = gather_boid(&self.world, entity);
+        let _ = self.world.destroy_entity(entity);
+        boid
     }
     pub fn remove_all_boids(&mut self) {
-        self.boids = Vec::new();
+        let entities = self
+            .world
+            .storage::<BoidHandle>()
+            .map(|s| s.entities().to_vec())
+            .unwrap_or_default();
+        for entity in entities {
+            let _ = self.world.destroy_entity(entity);
+        }
+        self.handles.clear();
+        self.refresh();
+    }
+
+    /*
+     * Attaches any component to the boid of `handle`, for systems registered
+     * on `world_mut` to work with. False if the boid was removed.
+     */
+    pub fn add_component<T: Component>(&mut self, handle: BoidHandle, component: T) -> bool {
+        let Some(entity) = self.handles.entity(handle) else {
+            return false;
+        };
+        let added = self.world.add_component(entity, component);
+        if let Err(e) = &added {
+            log::error!("{}", e);
+        }
+        self.refresh();
+        added.is_ok()
     }
 
-    fn update_boids_in_quad_tree_from_too(&mut self, boid_id: BoidId) {
-        let mut other_visible_boids: Vec<Boid> = Vec::with_capacity(MAX_BOID_IN_AREA);
-        let region: Region = Region::rect_from_center(self.boids[boid_id].position);
+    pub fn component<T: Component>(&self, handle: BoidHandle) -> Option<&T> {
+        self.world.get(self.handles.entity(handle)?)
+    }
 
-        self.quad_tree
-            .get_all_boids_in_boundry(&region, &mut other_visible_boids);
+    /* Changes to the components of `Boid` show up in `boids` after the next update. */
+    pub fn component_mut<T: Component>(&mut self, handle: BoidHandle) -> Option<&mut T> {
+        self.world.get_mut(self.handles.entity(handle)?)
+    }
+
+    /* A boid without one of the components of `Boid` is left out of `boids`. */
+    pub fn remove_component<T: Component>(&mut self, handle: BoidHandle) -> Option<T> {
+        let entity = self.handles.entity(handle)?;
+        let removed = self.world.remove_component(entity).ok().flatten();
+        self.refresh();
+        removed
+    }
+
+    /* Where to draw boid `index` when `alpha` of the way from the previous step to the current one. */
+    pub fn interpolated_position(&self, index: usize, alpha: f32) -> V2f32 {
+        let flock = self.resource::<Flock>();
+        systems::interpolated_position(&self.world, flock.entities[index], alpha)
+            .unwrap_or(flock.boids[index].position)
+    }
+
+    pub fn pipeline(&self, species: Species) -> &SteeringPipeline {
+        &self.resource::<Steering>().pipelines[species.index()]
+    }
+
+    /*
+     * Replaces the steering pipeline of a species. On error nothing changes, so
+     * behaviours registered on `registry` after `new` can be configured here.
+     */
+    pub fn set_behaviours(
+        &mut self,
+        species: Species,
+        behaviours: Vec<BehaviourConfig>,
+    ) -> Result<(), ConfigError> {
+        let mut config = self.context().config.clone();
+        *config.behaviours_of_mut(species) = behaviours;
+        config.validate()?;
+        let pipeline = SteeringPipeline::build(&self.registry, &config, species)?;
+        self.resource_mut::<Steering>().pipelines[species.index()] = pipeline;
+        self.context_mut().config = config;
+        Ok(())
+    }
+
+    /* Flips the enabled flag of a behaviour, returns the new state or None if there is none. */
+    pub fn toggle_behaviour(&mut self, species: Species, name: &str) -> Option<bool> {
+        let behaviour = self
+            .context_mut()
+            .config
+            .behaviours_of_mut(species)
+            .iter_mut()
+            .find(|b| b.name == name)?;
+        behaviour.enabled = !behaviour.enabled;
+        log::info!("{} {} enabled {}", species, name, behaviour.enabled);
+        Some(behaviour.enabled)
+    }
+
+    /* Swaps the neighbour search backend, it is built on the next update. */
+    pub fn set_spatial_index(&mut self, kind: SpatialIndexKind) {
+        log::info!("spatial index {}", kind);
+        self.context_mut().config.spatial_index = kind;
+        let config = &self.context().config;
+        let mut index = kind.create(config);
+        index.build(config.view_port(), &[]);
+        let flock = self.resource_mut::<Flock>();
+        flock.index = index;
+        flock.dirty = true;
+    }
+
+    /* Sets the Perception of every boid from the config again. */
+    fn refresh_perceptions(&mut self) {
+        self.world
+            .resource_scope(|world, ctx: &mut SimulationContext| {
+                let entities = world
+                    .storage::<BoidHandle>()
+                    .map(|s| s.entities().to_vec())
+                    .unwrap_or_default();
+                for entity in entities {
+                    let Some(&species) = world.get::<Species>(entity) else {
+                        continue;
+                    };
+                    if let Some(perception) = world.get_mut::<Perception>(entity) {
+                        *perception = Perception::from_config(&ctx.config, species);
+                    }
+                }
+            });
+    }
+
+    /* Rebuilds the pipelines whose behaviours were changed through `context_mut`. */
+    fn sync_pipelines(&mut self) {
+        for species in Species::ALL {
+            let config = &self.context().config;
+            if self
+                .pipeline(species)
+                .matches(config.behaviours_of(species))
+            {
+                continue;
+            }
+            log::info!("{} behaviours changed, rebuilding their pipeline", species);
+            let pipeline = build_pipeline(&self.registry, config, species);
+            self.resource_mut::<Steering>().pipelines[species.index()] = pipeline;
+        }
+    }
+
+    /* A failing recorder is dropped, the simulation itself keeps running. */
+    fn record_frame(&mut self) {
+        let flock = self.world.resource::<Flock>();
+        if let (Some(recorder), Some(flock)) = (self.recorder.as_mut(), flock) {
+            if let Err(err) = recorder.write_frame(self.tick, &flock.boids) {
+                log::error!("{}, recording stopped", err);
+                self.recorder = None;
+            }
+        }
+    }
 
-        for b in &other_visible_boids {
-            let acceleration: V2f32 = self
-                .behaviours
+    /*
+     * Every predator catches the closest prey inside its catch radius, at most
+     * one per tick. Predators are few, so this just walks all boids.
+     */
+    fn catch_prey(&mut self) {
+        let radius_squared = self.context().config.predator.catch_radius.powi(2);
+        let boids = self.boids();
+        let mut caught: Vec<usize> = Vec::new();
+        for predator in boids.iter().filter(|b| b.species == Species::Predator) {
+            let closest = boids
                 .iter()
-                .map(|behaviour| behaviour.calculate(&b, &other_visible_boids))
-                .sum();
-            self.boids[b.id].update(acceleration);
+                .enumerate()
+                .filter(|(i, b)| b.species == Species::Prey && !caught.contains(i))
+                .map(|(i, b)| (distance_squared(b.position, predator.position), i))
+                .filter(|(distance, _)| *distance <= radius_squared)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((_, i)) = closest {
+                log::debug!("boid {} caught by {}", boids[i].id, predator.id);
+                caught.push(i);
+            }
+        }
+        if caught.is_empty() {
+            return;
+        }
+        self.caught += caught.len() as u64;
+        /* The flock is refreshed once at the end, so the indices stay valid until then. */
+        match self.context().config.predator.on_catch {
+            CatchAction::Remove => {
+                let caught: Vec<BoidHandle> = caught
+                    .into_iter()
+                    .filter_map(|i| self.handle(self.boids()[i].id))
+                    .collect();
+                for handle in caught {
+                    self.take_boid(handle);
+                }
+            }
+            /* The boid keeps its handle, it is just somewhere else. */
+            CatchAction::Respawn => {
+                for i in caught {
+                    let Some(handle) = self.handle(self.boids()[i].id) else {
+                        continue;
+                    };
+                    let (position, velocity) = self.random_placement();
+                    self.write_boid(handle, |boid| {
+                        boid.position = position;
+                        boid.velocity = velocity;
+                    });
+                    if let Some(previous) = self.component_mut::<PreviousPosition>(handle) {
+                        previous.0 = position;
+                    }
+                }
+            }
         }
+        self.refresh();
     }
+}
+/* One pipeline per species. Configs from the command line are checked before, this only catches code. */
+fn build_pipelines(
+    registry: &BehaviourRegistry,
+    config: &SimulationConfig,
+) -> Vec<SteeringPipeline> {
+    Species::ALL
+        .into_iter()
+        .map(|species| build_pipeline(registry, config, species))
+        .collect()
+}
 
-    fn update_boids_in_quad_tree(&mut self) {
-        (0..self.boids.len()).into_iter().for_each(|boid_id| {
-            self.update_boids_in_quad_tree_from_too(boid_id);
-        });
+fn build_pipeline(
+    registry: &BehaviourRegistry,
+    config: &SimulationConfig,
+    species: Species,
+) -> SteeringPipeline {
+    SteeringPipeline::build(registry, config, species).unwrap_or_else(|e| {
+        log::error!("{}, the {} will not steer", e, species);
+        SteeringPipeline::empty(species)
+    })
+}
+
+#[cfg(feature = "render")]
+impl BoidManager {
+    /* Like `render`, with the boids drawn `alpha` of the way between the last two steps. */
+    pub fn render_interpolated(
+        &mut self,
+        canvas: &mut WindowCanvas,
+        camera: &Camera,
+        primitives: DrawPrimitives,
+        alpha: f32,
+    ) {
+        for o in self
+            .resource_mut::<SimulationContext>()
+            .config
+            .obstacles
+            .iter_mut()
+        {
+            o.render(canvas, camera, primitives);
+        }
+        RenderSystem::draw(&self.world, canvas, camera, primitives, alpha);
+
+        if primitives.contains(DrawPrimitives::QUAD_TREE) {
+            let flock = self.resource_mut::<Flock>();
+            flock.index.render_debug(canvas, camera, &flock.boids);
+        }
+
+        if primitives.contains(DrawPrimitives::BOUND_VIEW) {
+            let bound = self.context().config.bound_region();
+            let mut r: Region =
+                Region::new(bound.left_up - camera.pos, bound.right_down - camera.pos);
+            r.render(canvas, camera, primitives);
+        }
     }
 }
+
+#[cfg(feature = "render")]
 impl Renderable for BoidManager {
-    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera) {
-        for b in self.boids.iter_mut() {
-            b.render(canvas, camera);
+    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera, primitives: DrawPrimitives) {
+        self.render_interpolated(canvas, camera, primitives, 1.0);
+    }
+}
+
+impl Default for BoidManager {
+    fn default() -> Self {
+        Self::new(SimulationConfig::default())
+    }
+}
+
+/* One step: the systems of `world` in turn, then predators catch their prey. */
+impl Updatable for BoidManager {
+    fn update(&mut self) {
+        if std::mem::take(&mut self.perceptions_dirty) {
+            self.refresh_perceptions();
         }
+        self.sync_pipelines();
+        let dither = tick_seed(self.seed, self.tick);
+        self.resource_mut::<Steering>().dither = dither;
+        self.world.run_systems();
+        self.catch_prey();
+        self.tick += 1;
+        self.record_frame();
+    }
+}
+/* Changes the boid at `index` of `boids`, to set up a situation. */
+#[cfg(test)]
+fn modify_at<F: FnOnce(&mut Boid)>(manager: &mut BoidManager, index: usize, f: F) {
+    let handle = manager.handle(manager.boids()[index].id).unwrap();
+    assert!(manager.modify(handle, f));
+}
 
-        DRAW_PRIMITIVES.with(|value| {
-            if value.borrow().contains(DrawPrimitives::QUAD_TREE) {
-                self.quad_tree.render(canvas, camera);
+#[cfg(test)]
+fn index_dirty(manager: &BoidManager) -> bool {
+    manager.world().resource::<Flock>().unwrap().dirty
+}
+
+#[test]
+fn get_all_boids_in_boundry() {}
+
+#[test]
+fn update_boids_in_quad_tree() {
+    use crate::logic::behaviour::traits::BorderBehaviour;
+
+    let mut manager = BoidManager::default();
+    manager.set_behaviours(Species::Prey, vec![]).unwrap();
+    manager.spawn_boid(40);
+    let before = manager.boids().to_vec();
+    manager.update();
+    for (old, new) in before.iter().zip(manager.boids()) {
+        let mut expected = *old;
+        expected.border(
+            &manager.context().border_behaviour,
+            &manager.context().config,
+        );
+        let dt = manager.context().config.time_step();
+        assert_eq!(new.position, expected.position + expected.velocity * dt);
+    }
+}
+
+#[test]
+fn update_does_not_depend_on_boid_order() {
+    let mut manager = BoidManager::default();
+    manager.spawn_boid(60);
+    let mut reversed = BoidManager::default();
+    reversed.set_boids(manager.boids().iter().rev().copied().collect());
+
+    for _ in 0..5 {
+        manager.update();
+        reversed.update();
+    }
+    let mut sorted = reversed.boids().to_vec();
+    sorted.sort_by_key(|b| b.id);
+    for (a, b) in manager.boids().iter().zip(&sorted) {
+        approx::assert_relative_eq!(a.position.x, b.position.x, epsilon = 1e-3);
+        approx::assert_relative_eq!(a.position.y, b.position.y, epsilon = 1e-3);
+        approx::assert_relative_eq!(a.velocity.x, b.velocity.x, epsilon = 1e-3);
+        approx::assert_relative_eq!(a.velocity.y, b.velocity.y, epsilon = 1e-3);
+    }
+}
+
+#[test]
+fn managers_have_independent_context() {
+    use crate::logic::behaviour::traits::BorderBehaviourE;
+
+    let mut first = BoidManager::default();
+    let mut second = BoidManager::default();
+    second.toggle_behaviour(Species::Prey, "align");
+    second.context_mut().border_behaviour = BorderBehaviourE::Reflect;
+    first.spawn_boid(10);
+    second.spawn_boid(5);
+
+    assert!(first.boids().iter().enumerate().all(|(i, b)| b.id == i));
+    assert!(second.boids().iter().enumerate().all(|(i, b)| b.id == i));
+    assert!(first.context().config.behaviour("align").unwrap().enabled);
+    assert_eq!(
+        first.context().border_behaviour,
+        BorderBehaviourE::GoThrough
+    );
+}
+
+#[test]
+fn behaviours_give_same_result_on_worker_threads() {
+    use crate::logic::behaviour::traits::BorderBehaviourE;
+    use rayon::prelude::*;
+
+    let mut manager = BoidManager::default();
+    manager
+        .set_behaviours(
+            Species::Prey,
+            vec![
+                BehaviourConfig::new("cohesion", 0.002),
+                BehaviourConfig::new("separation", 0.03),
+            ],
+        )
+        .unwrap();
+    manager.context_mut().border_behaviour = BorderBehaviourE::Reflect;
+    manager.spawn_boid(50);
+
+    let all: Vec<&Boid> = manager.boids().iter().collect();
+    let acceleration = |b: &Boid| -> V2f32 {
+        manager
+            .pipeline(Species::Prey)
+            .steer(b, &all, manager.context(), 0)
+    };
+    let serial: Vec<V2f32> = manager.boids().iter().map(acceleration).collect();
+    let parallel: Vec<V2f32> = manager.boids().par_iter().map(acceleration).collect();
+    assert_eq!(serial, parallel);
+}
+
+#[test]
+fn parallel_update_matches_serial() {
+    let mut parallel = BoidManager::default();
+    parallel.spawn_boid(200);
+    let mut serial = BoidManager::default();
+    serial.set_boids(parallel.boids().to_vec());
+    serial.context_mut().config.parallel = false;
+
+    for _ in 0..10 {
+        parallel.update();
+        serial.update();
+    }
+    assert_eq!(parallel.boids(), serial.boids());
+}
+
+#[test]
+fn same_seed_gives_same_trajectories() {
+    let run = |seed: u64| {
+        let mut manager = BoidManager::default().with_seed(seed);
+        manager.spawn_boid(100);
+        for _ in 0..50 {
+            manager.update();
+        }
+        manager.boids().to_vec()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+}
+
+#[test]
+fn seed_from_config() {
+    let config = SimulationConfig {
+        seed: Some(1234),
+        ..Default::default()
+    };
+    let mut first = BoidManager::new(config.clone());
+    let mut second = BoidManager::new(config);
+    first.spawn_boid(10);
+    second.spawn_boid(10);
+    assert_eq!(first.seed(), 1234);
+    assert_eq!(first.boids(), second.boids());
+}
+
+#[test]
+fn snapshot_resumes_identically() {
+    let mut manager = BoidManager::default().with_seed(99);
+    manager.spawn_boid(80);
+    manager.toggle_behaviour(Species::Prey, "cohesion");
+    manager.toggle_behaviour(Species::Prey, "separation");
+    for _ in 0..20 {
+        manager.update();
+    }
+
+    let dir = std::env::temp_dir();
+    let json = dir.join(format!("boids-snapshot-{}.json", std::process::id()));
+    let binary = dir.join(format!("boids-snapshot-{}.bin", std::process::id()));
+    manager.save_snapshot(&json).unwrap();
+    manager.save_snapshot(&binary).unwrap();
+    let mut from_json = BoidManager::load_snapshot(&json).unwrap();
+    let mut from_binary = BoidManager::load_snapshot(&binary).unwrap();
+    let _ = std::fs::remove_file(json);
+    let _ = std::fs::remove_file(binary);
+
+    assert_eq!(from_json.snapshot(), manager.snapshot());
+    assert_eq!(from_binary.snapshot(), manager.snapshot());
+    for _ in 0..20 {
+        manager.update();
+        from_json.update();
+        from_binary.update();
+    }
+    manager.add_boid(3);
+    from_json.add_boid(3);
+    from_binary.add_boid(3);
+    assert_eq!(from_json.tick(), 40);
+    assert_eq!(from_json.boids(), manager.boids());
+    assert_eq!(from_binary.boids(), manager.boids());
+}
+
+#[test]
+fn boids_outside_view_port_are_still_simulated() {
+    use crate::logic::behaviour::traits::BorderBehaviourE;
+
+    let mut manager = BoidManager::default();
+    manager.context_mut().border_behaviour = BorderBehaviourE::Reflect;
+    manager.set_boids(vec![
+        Boid::new(0, Vector2::new(-30.0, 100.0), Vector2::new(-1.0, 0.0)),
+        Boid::new(1, Vector2::new(-40.0, 100.0), Vector2::new(-1.0, 0.0)),
+        Boid::new(2, Vector2::new(800.0, 600.0), Vector2::new(1.0, 1.0)),
+        Boid::new(3, Vector2::new(400.0, 300.0), Vector2::new(1.0, 1.0)),
+    ]);
+    for _ in 0..10 {
+        manager.update();
+    }
+    assert_eq!(manager.spatial_index().len(), 4);
+    assert!(manager.boids().iter().all(|b| b.position.x.is_finite()));
+}
+
+#[test]
+fn every_spatial_index_gives_the_same_flock() {
+    let run = |kind: SpatialIndexKind| {
+        let mut manager = BoidManager::default().with_seed(17);
+        manager.set_spatial_index(kind);
+        manager.spawn_boid(150);
+        for _ in 0..5 {
+            manager.update();
+        }
+        assert_eq!(manager.context().config.spatial_index, kind);
+        manager.boids().to_vec()
+    };
+    let reference = run(SpatialIndexKind::BruteForce);
+    for kind in SpatialIndexKind::ALL {
+        for (a, b) in run(kind).iter().zip(&reference) {
+            approx::assert_relative_eq!(a.position.x, b.position.x, epsilon = 1e-3);
+            approx::assert_relative_eq!(a.position.y, b.position.y, epsilon = 1e-3);
+        }
+    }
+}
+
+#[test]
+fn quad_tree_follows_the_flock_without_rebuilds() {
+    use crate::math::quadtree::quadt::QuadTree;
+
+    let mut manager = BoidManager::default().with_seed(23);
+    manager.spawn_boid(100);
+    manager.update();
+    assert!(!index_dirty(&manager));
+    for _ in 0..20 {
+        manager.update();
+        assert!(!index_dirty(&manager));
+    }
+    let mut expected = QuadTree::new(manager.context().config.view_port());
+    expected.rebuild(manager.context().config.view_port(), manager.boids());
+    for b in manager.boids() {
+        let (mut found, mut reference) = (vec![], vec![]);
+        manager
+            .spatial_index()
+            .query_radius(manager.boids(), b.position, 50.0, &mut found);
+        expected.query_radius(manager.boids(), b.position, 50.0, &mut reference);
+        found.sort();
+        reference.sort();
+        assert_eq!(found, reference);
+    }
+
+    /* The grid can't follow single moves and is built again every update. */
+    manager.set_spatial_index(SpatialIndexKind::Grid);
+    manager.update();
+    assert!(index_dirty(&manager));
+}
+
+#[test]
+fn boids_in_the_blind_spot_are_ignored() {
+    let run = |view_angle: f32| {
+        let mut manager = BoidManager::default();
+        manager
+            .set_behaviours(Species::Prey, vec![BehaviourConfig::new("cohesion", 0.002)])
+            .unwrap();
+        manager.context_mut().config.view_angle = view_angle;
+        manager.set_boids(vec![
+            Boid::new(0, Vector2::new(400.0, 300.0), Vector2::new(1.0, 0.0)),
+            /* Right behind boid 0, which in turn is ahead of this one. */
+            Boid::new(1, Vector2::new(370.0, 300.0), Vector2::new(1.0, 0.0)),
+        ]);
+        manager.update();
+        (manager.boids()[0].velocity, manager.boids()[1].velocity)
+    };
+    let (leader, follower) = run(270.0);
+    assert_eq!(leader, Vector2::new(1.0, 0.0));
+    assert!(follower.x > 1.0);
+    let (leader, _) = run(360.0);
+    assert!(leader.x < 1.0);
+}
+
+#[test]
+fn overlapping_boids_separate_with_every_kernel() {
+    use crate::{logic::behaviour::separation::SeparationKernel, math::vec::Distance};
+
+    for kernel in SeparationKernel::ALL {
+        let mut manager = BoidManager::default();
+        manager
+            .set_behaviours(
+                Species::Prey,
+                vec![BehaviourConfig::new("separation", 0.03)
+                    .with_param("kernel", kernel.to_string())],
+            )
+            .unwrap();
+        let spot = Vector2::new(400.0, 300.0);
+        manager.set_boids(
+            (0..3)
+                .map(|i| Boid::new(i, spot, Vector2::zero()))
+                .collect(),
+        );
+        for _ in 0..3 {
+            manager.update();
+        }
+        assert!(manager.boids().iter().all(|b| b.is_finite()), "{}", kernel);
+        for (i, a) in manager.boids().iter().enumerate() {
+            for b in &manager.boids()[i + 1..] {
+                assert!(V2f32::distance(a.position, b.position) > 0.1, "{}", kernel);
+            }
+        }
+    }
+}
+
+#[test]
+fn non_finite_steering_and_boids_are_recovered() {
+    use crate::logic::behaviour::traits::Behaviour;
+
+    struct Broken;
+    impl Behaviour for Broken {
+        fn calculate(&self, boid: &Boid, _: &[&Boid], _: &SimulationContext) -> V2f32 {
+            match boid.id {
+                0 => Vector2::new(f32::NAN, 0.0),
+                _ => Vector2::new(0.0, f32::INFINITY),
             }
+        }
+    }
+    let mut manager = BoidManager::default().with_seed(3);
+    manager.spawn_boid(20);
+    manager
+        .registry
+        .register("broken", &[], |_| Ok(Box::new(Broken)));
+    let mut behaviours = manager.context().config.behaviours.clone();
+    behaviours.push(BehaviourConfig::new("broken", 1.0));
+    manager.set_behaviours(Species::Prey, behaviours).unwrap();
+    modify_at(&mut manager, 5, |b| {
+        b.position = Vector2::new(f32::NAN, 10.0)
+    });
+    modify_at(&mut manager, 6, |b| {
+        b.velocity = Vector2::new(f32::INFINITY, 0.0)
+    });
+    manager.update();
+    assert_eq!(manager.boids()[5].position, Vector2::new(400.0, 300.0));
+    for _ in 0..5 {
+        assert!(manager.boids().iter().all(|b| b.is_finite()));
+        manager.update();
+    }
+}
+
+#[test]
+fn behaviour_settings_survive_snapshots() {
+    use crate::logic::behaviour::pipeline::CombineMode;
+
+    let mut manager = BoidManager::default().with_seed(8);
+    manager.spawn_boid(30);
+    assert_eq!(
+        manager.toggle_behaviour(Species::Prey, "bound"),
+        Some(false)
+    );
+    assert_eq!(manager.toggle_behaviour(Species::Prey, "flocking"), None);
+    assert!(manager
+        .set_behaviours(Species::Prey, vec![BehaviourConfig::new("flocking", 1.0)])
+        .is_err());
+    assert_eq!(manager.pipeline(Species::Prey).len(), 6);
+
+    let restored = BoidManager::from_snapshot(manager.snapshot());
+    assert!(
+        !restored
+            .context()
+            .config
+            .behaviour("bound")
+            .unwrap()
+            .enabled
+    );
+    assert_eq!(restored.pipeline(Species::Prey).len(), 6);
+
+    for mode in [CombineMode::Prioritized, CombineMode::Dithered] {
+        let mut manager = BoidManager::default().with_seed(8);
+        manager.context_mut().config.steering = mode;
+        manager.spawn_boid(30);
+        for _ in 0..5 {
+            manager.update();
+        }
+        assert!(manager.boids().iter().all(|b| b.is_finite()), "{:?}", mode);
+    }
+}
+
+#[test]
+fn prey_flees_and_predators_chase() {
+    let mut manager = BoidManager::default();
+    manager.set_boids(vec![
+        Boid::new(0, Vector2::new(400.0, 300.0), Vector2::zero()),
+        Boid::new(1, Vector2::new(440.0, 300.0), Vector2::zero()).with_species(Species::Predator),
+    ]);
+    manager.update();
+    assert!(manager.boids()[0].velocity.x < 0.0);
+    assert!(manager.boids()[1].velocity.x < 0.0);
+    assert_eq!(
+        manager.population(),
+        Population {
+            prey: 1,
+            predators: 1,
+            caught: 0
+        }
+    );
+}
+
+#[test]
+fn predators_catch_prey() {
+    for action in CatchAction::ALL {
+        let mut manager = BoidManager::default().with_seed(4);
+        manager.context_mut().config.predator.on_catch = action;
+        manager.spawn_boid(10);
+        manager.add_species(Species::Predator, 1);
+        /* Two prey within reach, only the closer one is caught. */
+        let spot = manager.boids()[10].position;
+        modify_at(&mut manager, 3, |b| {
+            b.position = spot + Vector2::new(3.0, 0.0)
+        });
+        modify_at(&mut manager, 7, |b| {
+            b.position = spot + Vector2::new(0.0, 1.0)
         });
+        modify_at(&mut manager, 10, |b| b.velocity = Vector2::zero());
+        manager.set_behaviours(Species::Prey, vec![]).unwrap();
+        manager.set_behaviours(Species::Predator, vec![]).unwrap();
+        manager.update();
 
-        DRAW_PRIMITIVES.with(|value| {
-            if value.borrow().contains(DrawPrimitives::BOUND_VIEW) {
-                let mut r: Region = Region::new(
-                    Vector2::new(100.0, 100.0) - camera.pos,
-                    Vector2::new(
-                        (VIEW_PORT_SIZE.x - 100.0) as f32,
-                        (VIEW_PORT_SIZE.y - 100.0) as f32,
-                    ) - camera.pos,
+        let population = manager.population();
+        assert_eq!(population.caught, 1, "{}", action);
+        assert_eq!(population.predators, 1);
+        let caught = manager.boids().iter().find(|b| b.id == 7);
+        match action {
+            CatchAction::Remove => {
+                assert_eq!(population.prey, 9);
+                assert!(caught.is_none());
+            }
+            CatchAction::Respawn => {
+                assert_eq!(population.prey, 10);
+                let caught = caught.unwrap();
+                assert!(distance_squared(caught.position, spot) > 1.0);
+            }
+        }
+        assert!(manager.boids().iter().any(|b| b.id == 3));
+
+        let restored = BoidManager::from_snapshot(manager.snapshot());
+        assert_eq!(restored.population(), population);
+    }
+}
+
+#[test]
+fn boids_stay_out_of_obstacles() {
+    use crate::logic::obstacle::Obstacle;
+
+    let mut config = SimulationConfig {
+        seed: Some(12),
+        ..Default::default()
+    };
+    config.obstacles = vec![
+        Obstacle::Circle {
+            center: Vector2::new(250.0, 300.0),
+            radius: 60.0,
+        },
+        Obstacle::Rect {
+            left_up: Vector2::new(450.0, 150.0),
+            right_down: Vector2::new(600.0, 250.0),
+        },
+        Obstacle::Polygon {
+            vertices: vec![
+                Vector2::new(400.0, 400.0),
+                Vector2::new(550.0, 420.0),
+                Vector2::new(460.0, 520.0),
+            ],
+        },
+    ];
+    let mut manager = BoidManager::new(config);
+    manager.spawn_boid(60);
+    manager.add_species(Species::Predator, 2);
+    for _ in 0..200 {
+        for boid in manager.boids() {
+            for obstacle in &manager.context().config.obstacles {
+                assert!(
+                    !obstacle.contains(boid.position),
+                    "{:?} in {:?}",
+                    boid,
+                    obstacle
                 );
-                r.render(canvas, camera);
             }
+        }
+        manager.update();
+    }
+}
+
+#[test]
+fn speeds_are_per_second_at_any_tick_rate() {
+    for tick_rate in [30.0, 60.0, 144.0] {
+        let mut manager = BoidManager::new(SimulationConfig {
+            tick_rate,
+            ..Default::default()
         });
+        manager.set_behaviours(Species::Prey, vec![]).unwrap();
+        manager.set_boids(vec![Boid::new(
+            0,
+            Vector2::new(100.0, 300.0),
+            Vector2::new(100.0, 0.0),
+        )]);
+        for _ in 0..tick_rate as u32 {
+            manager.update();
+        }
+        approx::assert_relative_eq!(manager.boids()[0].position.x, 200.0, epsilon = 1e-2);
     }
 }
 
-impl Default for BoidManager {
-    fn default() -> Self {
-        Self::new(Region::default())
+#[test]
+fn drawn_positions_blend_the_last_two_steps() {
+    let mut manager = BoidManager::default().with_seed(5);
+    manager.spawn_boid(3);
+    /* Before the first update there is nothing to blend. */
+    assert_eq!(
+        manager.interpolated_position(0, 0.5),
+        manager.boids()[0].position
+    );
+    let before = manager.boids()[0].position;
+    manager.update();
+    let after = manager.boids()[0].position;
+    assert_eq!(manager.interpolated_position(0, 0.0), before);
+    assert_eq!(manager.interpolated_position(0, 1.0), after);
+    let middle = manager.interpolated_position(0, 0.5);
+    approx::assert_relative_eq!(middle.x, (before.x + after.x) / 2.0, epsilon = 1e-3);
+    approx::assert_relative_eq!(middle.y, (before.y + after.y) / 2.0, epsilon = 1e-3);
+
+    /* A jump, like wrapping around the border, is not smeared across the screen. */
+    modify_at(&mut manager, 1, |b| b.position += Vector2::new(300.0, 0.0));
+    assert_eq!(
+        manager.interpolated_position(1, 0.5),
+        manager.boids()[1].position
+    );
+}
+
+#[test]
+fn handles_survive_spawn_remove_respawn_cycles() {
+    let mut manager = BoidManager::default().with_seed(6);
+    let first = manager.spawn_boid(5);
+    let removed = manager.remove_boid(first[1]).unwrap();
+    assert_eq!(removed.id, first[1].id);
+    assert_eq!(manager.remove_boid(first[1]), None);
+    assert_eq!(manager.get(first[1]), None);
+    /* The last boid took the freed place and is still found. */
+    assert_eq!(manager.boids()[1].id, first[4].id);
+    for handle in [first[0], first[2], first[3], first[4]] {
+        assert_eq!(manager.get(handle).unwrap().id, handle.id);
+    }
+
+    /* The id comes back, the old handle still doesn't reach it. */
+    let added = manager.add_boid(1)[0];
+    assert_eq!(added.id, first[1].id);
+    assert_eq!(manager.get(first[1]), None);
+    assert_eq!(manager.get(added).unwrap().id, added.id);
+    manager.update();
+    assert_eq!(manager.handle(manager.boids()[4].id), Some(added));
+
+    let restored = BoidManager::from_snapshot(manager.snapshot());
+    assert_eq!(restored.get(added), manager.get(added));
+
+    /* Starting over makes every handle stale, not just the removed ones. */
+    let second = manager.spawn_boid(5);
+    assert_eq!(
+        second.iter().map(|h| h.id).collect::<Vec<_>>(),
+        [0, 1, 2, 3, 4]
+    );
+    for handle in first.iter().chain([&added]) {
+        assert_eq!(manager.get(*handle), None);
+    }
+    for handle in &second {
+        assert!(manager.get(*handle).is_some());
     }
+
+    /* Managers hand out their own ids. */
+    let mut other = BoidManager::default().with_seed(6);
+    let elsewhere = other.add_boid(1)[0];
+    assert_eq!(elsewhere.id, 0);
+    other.remove_boid(elsewhere);
+    assert!(manager.get(second[0]).is_some());
 }
 
-impl Updatable for BoidManager {
-    fn update(&mut self) {
-        if self.update_tick == crate::constants::UPDATE_EVERY_TICK {
-            let scren_size_region: Region = Region::new(Vector2::new(0.0, 0.0), VIEW_PORT_SIZE);
-            self.quad_tree = QuadTree::new(scren_size_region);
-            for b in self.boids.iter_mut() {
-                log::info!("Inser {:?} into qTree", b);
-                match self.quad_tree.insert(*b) {
-                    Err(err) => {
-                        log::error!(
-                            "Panic {} for {:?}, quad_tree = {:?}",
-                            err,
-                            *b,
-                            self.quad_tree
-                        );
-                        panic!(
-                            "Panic {} for {:?}, quad_tree = {:?}",
-                            err, *b, self.quad_tree
-                        );
-                    }
-                    _ => {}
+#[test]
+fn boids_carry_extra_components_for_systems_of_their_own() {
+    use crate::ecs::{signature::Signature, system::System};
+
+    struct Energy(u32);
+    /* Tired boids stop. */
+    struct Fatigue;
+    impl System for Fatigue {
+        fn run(&mut self, entities: &[Entity], world: &mut World) {
+            for &entity in entities {
+                let energy = world.get_mut::<Energy>(entity).unwrap();
+                energy.0 = energy.0.saturating_sub(1);
+                if energy.0 == 0 {
+                    world.get_mut::<Velocity>(entity).unwrap().0 = V2f32::zero();
                 }
             }
-            self.update_tick = 0;
         }
-        self.update_boids_in_quad_tree();
-        self.update_tick += 1;
     }
+
+    let mut manager = BoidManager::default().with_seed(9);
+    manager.set_behaviours(Species::Prey, vec![]).unwrap();
+    let handles = manager.spawn_boid(3);
+    assert!(manager.add_component(handles[1], Energy(2)));
+    let world = manager.world_mut();
+    let energy = world.component_type::<Energy>().unwrap();
+    world.register_system(Fatigue, Signature::empty().with(energy));
+
+    manager.update();
+    assert_eq!(
+        manager.component::<Energy>(handles[1]).map(|e| e.0),
+        Some(1)
+    );
+    manager.update();
+    manager.update();
+    assert_eq!(manager.get(handles[1]).unwrap().velocity, V2f32::zero());
+    assert_ne!(manager.get(handles[0]).unwrap().velocity, V2f32::zero());
+    assert!(manager.component::<Energy>(handles[0]).is_none());
+
+    /* Without a position the boid is left out of the flock until it has one again. */
+    let position = manager.remove_component::<Position>(handles[2]).unwrap();
+    assert_eq!(manager.boids().len(), 2);
+    manager.update();
+    assert!(manager.add_component(handles[2], position));
+    assert_eq!(manager.boids().len(), 3);
+
+    let removed = manager.remove_boid(handles[1]).unwrap();
+    assert_eq!(removed.velocity, V2f32::zero());
+    assert!(!manager.add_component(handles[1], Energy(5)));
+    assert_eq!(manager.world().storage::<Energy>().unwrap().len(), 0);
 }
+
 #[test]
-fn get_all_boids_in_boundry() {}
+fn wander_follows_the_seed_of_the_manager() {
+    use crate::logic::behaviour::steering::WanderMode;
+
+    let run = |seed: u64, mode: WanderMode| {
+        let mut manager = BoidManager::default().with_seed(seed);
+        let wander = BehaviourConfig::new("wander", 1.0).with_param("mode", mode);
+        manager.set_behaviours(Species::Prey, vec![wander]).unwrap();
+        manager.set_boids(
+            (0..10)
+                .map(|i| {
+                    Boid::new(
+                        i,
+                        Vector2::new(100.0 + 50.0 * i as f32, 300.0),
+                        Vector2::new(20.0, 0.0),
+                    )
+                })
+                .collect(),
+        );
+        for _ in 0..30 {
+            manager.update();
+        }
+        manager.boids().to_vec()
+    };
+    for mode in WanderMode::ALL {
+        assert_eq!(run(1, mode), run(1, mode), "{}", mode);
+        assert_ne!(run(1, mode), run(2, mode), "{}", mode);
+    }
+}
 
 #[test]
-fn update_boids_in_quad_tree() {}
+fn behaviours_changed_in_the_context_get_rebuilt() {
+    let mut manager = BoidManager::default();
+    manager.spawn_boid(5);
+    manager.context_mut().config.behaviours.remove(0);
+    assert!(!manager
+        .pipeline(Species::Prey)
+        .matches(&manager.context().config.behaviours));
+    manager.update();
+    assert!(manager
+        .pipeline(Species::Prey)
+        .matches(&manager.context().config.behaviours));
+}
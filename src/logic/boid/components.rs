@@ -0,0 +1,48 @@
+use crate::{
+    constants::{BOID_COLOR, BOID_SIZE, PREDATOR_COLOR},
+    math::vec::V2f32,
+};
+
+use super::species::Species;
+
+/*
+ * Components of a boid entity next to its `Species`, `Perception` and
+ * `BoidHandle`. Anything else can be attached to a boid as well, see
+ * `BoidManager::add_component`.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position(pub V2f32);
+
+/* Units per second. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Velocity(pub V2f32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mass(pub f32);
+
+/* Where the boid was before the last update, drawing blends between the two. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviousPosition(pub V2f32);
+
+/* Drawn as a square `size` wide in `color`. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Renderable {
+    pub color: [u8; 3],
+    pub size: f32,
+}
+
+impl Renderable {
+    /* Predators are drawn bigger and in their own colour. */
+    pub fn of(species: Species) -> Self {
+        match species {
+            Species::Prey => Self {
+                color: BOID_COLOR,
+                size: BOID_SIZE as f32,
+            },
+            Species::Predator => Self {
+                color: PREDATOR_COLOR,
+                size: BOID_SIZE as f32 * 2.0,
+            },
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::types::BoidId, ecs::entity::Entity};
+
+/*
+ * Refers to one boid for as long as it lives, see `BoidManager::get`.
+ * Ids are reused once a boid is removed, but never with the same generation,
+ * so a handle kept past the removal can't reach the boid that comes next.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BoidHandle {
+    pub id: BoidId,
+    pub generation: u32,
+}
+
+impl fmt::Display for BoidHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.id, self.generation)
+    }
+}
+
+/*
+ * `entity` holds the components of the boid, None while the id is free.
+ * `queued` is set while the id is in `BoidHandles::free`, retired ids never are.
+ */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Slot {
+    generation: u32,
+    entity: Option<Entity>,
+    #[serde(skip)]
+    queued: bool,
+}
+
+impl Slot {
+    fn is_free(&self) -> bool {
+        self.queued && self.entity.is_none()
+    }
+}
+
+/* Given ids above this get a new one instead, they may come from a corrupt file. */
+const MAX_GIVEN_ID: BoidId = 1 << 20;
+
+/*
+ * Hands out the ids of a BoidManager, slot map style: one slot per id holding
+ * its generation and the entity of the boid. Freed ids are handed out again
+ * lowest first after `clear`, otherwise the last freed first. A restored
+ * snapshot points the ids at entities of its own with `moved`.
+ * `free` is the order ids are handed out in, from the back. Ids given to
+ * `insert_at` stay in it until they come up, so check the slot, and keep
+ * their place if they are freed before that.
+ */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(into = "StoredHandles", try_from = "StoredHandles")]
+pub struct BoidHandles {
+    slots: Vec<Slot>,
+    free: VecDeque<BoidId>,
+}
+
+/* What a snapshot holds of BoidHandles, only the ids that really are free. */
+#[derive(Serialize, Deserialize)]
+struct StoredHandles {
+    slots: Vec<Slot>,
+    free: Vec<BoidId>,
+}
+
+impl From<BoidHandles> for StoredHandles {
+    fn from(handles: BoidHandles) -> Self {
+        let free = handles.free_ids().collect();
+        Self {
+            slots: handles.slots,
+            free,
+        }
+    }
+}
+
+impl TryFrom<StoredHandles> for BoidHandles {
+    type Error = String;
+
+    fn try_from(stored: StoredHandles) -> Result<Self, Self::Error> {
+        let mut slots = stored.slots;
+        for &id in &stored.free {
+            match slots.get_mut(id) {
+                Some(slot) if slot.entity.is_none() && !slot.queued => slot.queued = true,
+                _ => return Err(format!("boid id {} can't be free", id)),
+            }
+        }
+        Ok(Self {
+            slots,
+            free: stored.free.into(),
+        })
+    }
+}
+
+/* Equal when the same handles are live, whatever entities they point at. */
+impl PartialEq for BoidHandles {
+    fn eq(&self, other: &Self) -> bool {
+        let live = |s: &Slot| (s.generation, s.entity.is_some());
+        self.free_ids().eq(other.free_ids())
+            && self.slots.iter().map(live).eq(other.slots.iter().map(live))
+    }
+}
+
+impl BoidHandles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Number of live handles. */
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|s| s.entity.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /* Ids `insert` would hand out, the next one last. */
+    fn free_ids(&self) -> impl Iterator<Item = BoidId> + '_ {
+        self.free
+            .iter()
+            .copied()
+            .filter(|&id| self.slots[id].is_free())
+    }
+
+    /* A handle for a new boid of `entity`. */
+    pub fn insert(&mut self, entity: Entity) -> BoidHandle {
+        let id = loop {
+            let Some(id) = self.free.pop_back() else {
+                self.slots.push(Slot::default());
+                break self.slots.len() - 1;
+            };
+            let slot = &mut self.slots[id];
+            let free = slot.is_free();
+            slot.queued = false;
+            if free {
+                break id;
+            }
+        };
+        let slot = &mut self.slots[id];
+        slot.entity = Some(entity);
+        BoidHandle {
+            id,
+            generation: slot.generation,
+        }
+    }
+
+    /*
+     * Like `insert`, but with the id given, for boids that already have one.
+     * None if the id is taken, retired or above MAX_GIVEN_ID.
+     */
+    pub fn insert_at(&mut self, id: BoidId, entity: Entity) -> Option<BoidHandle> {
+        if id > MAX_GIVEN_ID {
+            return None;
+        }
+        while self.slots.len() <= id {
+            self.free.push_front(self.slots.len());
+            self.slots.push(Slot {
+                queued: true,
+                ..Slot::default()
+            });
+        }
+        let slot = &mut self.slots[id];
+        if !slot.is_free() {
+            return None;
+        }
+        slot.entity = Some(entity);
+        Some(BoidHandle {
+            id,
+            generation: slot.generation,
+        })
+    }
+
+    /*
+     * Frees the id of `handle` and returns the entity of its boid, None if
+     * the handle is stale. An id whose generations ran out is never reused.
+     */
+    pub fn remove(&mut self, handle: BoidHandle) -> Option<Entity> {
+        let entity = self.entity(handle)?;
+        let slot = &mut self.slots[handle.id];
+        slot.entity = None;
+        match slot.generation.checked_add(1) {
+            Some(generation) => {
+                slot.generation = generation;
+                if !slot.queued {
+                    slot.queued = true;
+                    self.free.push_back(handle.id);
+                }
+            }
+            None => slot.queued = false,
+        }
+        Some(entity)
+    }
+
+    /* The entity of the boid of `handle`, None if it was removed. */
+    pub fn entity(&self, handle: BoidHandle) -> Option<Entity> {
+        self.slots
+            .get(handle.id)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.entity)
+    }
+
+    /* The live handle of `id`. */
+    pub fn handle(&self, id: BoidId) -> Option<BoidHandle> {
+        self.slots
+            .get(id)
+            .filter(|slot| slot.entity.is_some())
+            .map(|slot| BoidHandle {
+                id,
+                generation: slot.generation,
+            })
+    }
+
+    /* The boid with `id` now lives in `entity`. */
+    pub fn moved(&mut self, id: BoidId, entity: Entity) {
+        if let Some(slot) = self.slots.get_mut(id).filter(|s| s.entity.is_some()) {
+            slot.entity = Some(entity);
+        }
+    }
+
+    /* Removes every handle, the ids are handed out again from the lowest. */
+    pub fn clear(&mut self) {
+        for id in 0..self.slots.len() {
+            if let Some(handle) = self.handle(id) {
+                self.remove(handle);
+            }
+        }
+        self.free = (0..self.slots.len())
+            .rev()
+            .filter(|&id| self.slots[id].is_free())
+            .collect();
+    }
+}
+
+#[test]
+fn stale_handles_never_reach_a_reused_id() {
+    let mut handles = BoidHandles::new();
+    let first = handles.insert(0);
+    let second = handles.insert(1);
+    assert_eq!((first.id, second.id), (0, 1));
+    assert_eq!(handles.entity(second), Some(1));
+
+    assert_eq!(handles.remove(first), Some(0));
+    assert_eq!(handles.remove(first), None);
+    handles.moved(second.id, 7);
+    assert_eq!(handles.entity(second), Some(7));
+
+    let reused = handles.insert(1);
+    assert_eq!(reused.id, first.id);
+    assert_ne!(reused, first);
+    assert_eq!(handles.entity(first), None);
+    assert_eq!(handles.entity(reused), Some(1));
+    assert_eq!(handles.handle(reused.id), Some(reused));
+    assert_eq!(handles.len(), 2);
+
+    handles.clear();
+    assert!(handles.is_empty());
+    assert_eq!(handles.entity(second), None);
+    assert_eq!(handles.insert(0).id, 0);
+    assert_eq!(handles.insert(1).id, 1);
+}
+
+#[test]
+fn given_ids_are_taken_out_of_the_free_ones() {
+    let mut handles = BoidHandles::new();
+    let given = handles.insert_at(3, 0).unwrap();
+    assert_eq!((given.id, given.generation), (3, 0));
+    assert_eq!(handles.insert_at(3, 1), None);
+    assert_eq!(handles.insert_at(1, 1).map(|h| h.id), Some(1));
+    assert_eq!(handles.insert(2).id, 0);
+    assert_eq!(handles.insert(3).id, 2);
+    assert_eq!(handles.insert(4).id, 4);
+}
+
+#[test]
+fn exhausted_ids_are_retired() {
+    let mut handles = BoidHandles::new();
+    let handle = handles.insert(0);
+    handles.slots[handle.id].generation = u32::MAX;
+    let last = handles.handle(handle.id).unwrap();
+    assert_eq!(handles.remove(last), Some(0));
+    assert_ne!(handles.insert(0).id, handle.id);
+}
+
+#[test]
+fn refilling_keeps_the_free_ids_in_order() {
+    let mut handles = BoidHandles::new();
+    for _ in 0..2 {
+        /* What BoidManager::set_boids does: clear, then the ids of the boids given. */
+        handles.clear();
+        for (entity, id) in [4, 1, 1, 6].into_iter().enumerate() {
+            if handles.insert_at(id, entity as Entity).is_none() {
+                handles.insert(entity as Entity);
+            }
+        }
+        assert_eq!(handles.len(), 4);
+    }
+    let free: Vec<BoidId> = handles.free_ids().collect();
+    assert_eq!(free, [5, 3, 2]);
+
+    let stale = handles.handle(4).unwrap();
+    handles.remove(stale);
+    handles.remove(stale);
+    let free: Vec<BoidId> = handles.free_ids().collect();
+    assert_eq!(free, [5, 4, 3, 2]);
+    assert_eq!(
+        (0..4).map(|e| handles.insert(e).id).collect::<Vec<_>>(),
+        [2, 3, 4, 5]
+    );
+    assert_eq!(handles.insert(4).id, 7);
+}
+
+#[test]
+fn given_ids_are_bounded() {
+    let mut handles = BoidHandles::new();
+    assert_eq!(handles.insert_at(BoidId::MAX, 0), None);
+    assert_eq!(handles.insert_at(MAX_GIVEN_ID + 1, 0), None);
+    assert!(handles.slots.is_empty());
+    assert_eq!(handles.insert(0).id, 0);
+}
+
+#[test]
+fn snapshots_hold_only_free_ids() {
+    let mut handles = BoidHandles::new();
+    handles.insert_at(2, 0).unwrap();
+    let taken = handles.insert_at(0, 1).unwrap();
+    handles.remove(taken);
+
+    let json = serde_json::to_string(&handles).unwrap();
+    let stored: StoredHandles = serde_json::from_str(&json).unwrap();
+    assert_eq!(stored.free, [1, 0]);
+    let restored: BoidHandles = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, handles);
+    assert_eq!(handles.clone().insert(3).id, 0);
+    assert_eq!(restored.clone().insert(3).id, 0);
+
+    let taken = json.replace("\"free\":[1,0]", "\"free\":[2]");
+    assert!(serde_json::from_str::<BoidHandles>(&taken).is_err());
+}
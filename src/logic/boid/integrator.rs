@@ -0,0 +1,214 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::vec::V2f32;
+#[cfg(test)]
+use {
+    super::traits::UpdatableAcceleration,
+    crate::{
+        logic::context::SimulationContext,
+        math::vec::{Distance, DotProduct, Vector2},
+    },
+};
+
+use super::boid_impl::Boid;
+
+/*
+ * How a boid is moved one step on from its velocity and the acceleration its
+ * steering gives. The acceleration is asked for again at the states in
+ * between, so the higher orders follow forces that change with position, like
+ * seeking, more closely at the cost of running the pipeline more often.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /* Position from the old velocity, then velocity. Gains energy every step. */
+    #[default]
+    ExplicitEuler,
+    /* Velocity first, then position from the new velocity. Same cost, stays stable. */
+    SemiImplicitEuler,
+    /* Second order, asks for the acceleration twice per step. */
+    VelocityVerlet,
+    /* Runge-Kutta of fourth order, asks for the acceleration four times per step. */
+    Rk4,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 4] = [
+        Integrator::ExplicitEuler,
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Integrator::ExplicitEuler => "explicit_euler",
+            Integrator::SemiImplicitEuler => "semi_implicit_euler",
+            Integrator::VelocityVerlet => "velocity_verlet",
+            Integrator::Rk4 => "rk4",
+        }
+    }
+
+    /*
+     * `boid` moved on by `dt` seconds. `acceleration` gives the acceleration of
+     * the boid in any state, in units per second squared.
+     */
+    pub fn step<F: Fn(&Boid) -> V2f32>(self, boid: &Boid, dt: f32, acceleration: F) -> Boid {
+        let state = |position: V2f32, velocity: V2f32| Boid {
+            position,
+            velocity,
+            ..*boid
+        };
+        let (p, v) = (boid.position, boid.velocity);
+        match self {
+            Integrator::ExplicitEuler => {
+                let a = acceleration(boid);
+                state(p + v * dt, v + a * dt)
+            }
+            Integrator::SemiImplicitEuler => {
+                let v = v + acceleration(boid) * dt;
+                state(p + v * dt, v)
+            }
+            Integrator::VelocityVerlet => {
+                let a = acceleration(boid);
+                let p = p + v * dt + a * (dt * dt / 2.0);
+                /* The velocity at the end isn't known yet, the Euler guess stands in. */
+                let next_a = acceleration(&state(p, v + a * dt));
+                state(p, v + (a + next_a) * (dt / 2.0))
+            }
+            Integrator::Rk4 => {
+                let a1 = acceleration(boid);
+                let (p2, v2) = (p + v * (dt / 2.0), v + a1 * (dt / 2.0));
+                let a2 = acceleration(&state(p2, v2));
+                let (p3, v3) = (p + v2 * (dt / 2.0), v + a2 * (dt / 2.0));
+                let a3 = acceleration(&state(p3, v3));
+                let (p4, v4) = (p + v3 * dt, v + a3 * dt);
+                let a4 = acceleration(&state(p4, v4));
+                state(
+                    p + (v + v2 * 2.0 + v3 * 2.0 + v4) * (dt / 6.0),
+                    v + (a1 + a2 * 2.0 + a3 * 2.0 + a4) * (dt / 6.0),
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|i| i.name() == s)
+            .ok_or_else(|| {
+                "expected one of explicit_euler, semi_implicit_euler, velocity_verlet, rk4"
+                    .to_string()
+            })
+    }
+}
+
+/*
+ * A boid seeking the origin with a force growing with the distance, like a
+ * spring: it swings through the target once a second. Returns the largest
+ * relative change of its energy and how far it ended up from where it should
+ * be after `seconds`.
+ */
+#[cfg(test)]
+fn seek_drift(integrator: Integrator, seconds: u32) -> (f32, f32) {
+    use std::f32::consts::TAU;
+
+    let (tick_rate, amplitude, omega) = (60, 100.0, TAU);
+    let stiffness = omega * omega;
+    let energy = |b: &Boid| (b.velocity.dot_self() + stiffness * b.position.dot_self()) / 2.0;
+    let mut boid = Boid::new(0, Vector2::new(amplitude, 0.0), V2f32::zero());
+    let start = energy(&boid);
+    let mut drift: f32 = 0.0;
+    for _ in 0..seconds * tick_rate {
+        boid = integrator.step(&boid, 1.0 / tick_rate as f32, |b| b.position * -stiffness);
+        drift = drift.max((energy(&boid) / start - 1.0).abs());
+    }
+    let expected = Vector2::new(amplitude * (omega * seconds as f32).cos(), 0.0);
+    (drift, V2f32::distance(boid.position, expected))
+}
+
+#[test]
+fn higher_orders_drift_less_when_seeking() {
+    let [explicit, semi_implicit, verlet, rk4] = Integrator::ALL.map(|i| seek_drift(i, 10));
+
+    /* Explicit Euler spirals out, the rest keep their energy. */
+    assert!(explicit.0 > 10.0, "{:?}", explicit);
+    assert!(explicit.1 > 100.0, "{:?}", explicit);
+    assert!(semi_implicit.0 < 0.15, "{:?}", semi_implicit);
+    assert!(verlet.0 < 0.01, "{:?}", verlet);
+    assert!(rk4.0 < 1e-3, "{:?}", rk4);
+
+    /* And each order stays closer to the real swing. */
+    assert!(semi_implicit.1 < 15.0, "{:?}", semi_implicit);
+    assert!(verlet.1 < 5.0, "{:?}", verlet);
+    assert!(rk4.1 < 0.5, "{:?}", rk4);
+    assert!(rk4.1 < verlet.1 && verlet.1 < explicit.1);
+}
+
+#[test]
+fn a_constant_acceleration_is_followed_exactly_by_the_higher_orders() {
+    let boid = Boid::new(0, V2f32::zero(), Vector2::new(1.0, 0.0));
+    let gravity = |_: &Boid| Vector2::new(0.0, 2.0);
+    for integrator in [Integrator::VelocityVerlet, Integrator::Rk4] {
+        let moved = integrator.step(&boid, 0.5, gravity);
+        assert_eq!(moved.position, Vector2::new(0.5, 0.25), "{}", integrator);
+        assert_eq!(moved.velocity, Vector2::new(1.0, 1.0), "{}", integrator);
+    }
+    let explicit = Integrator::ExplicitEuler.step(&boid, 0.5, gravity);
+    assert_eq!(explicit.position, Vector2::new(0.5, 0.0));
+    let semi_implicit = Integrator::SemiImplicitEuler.step(&boid, 0.5, gravity);
+    assert_eq!(semi_implicit.position, Vector2::new(0.5, 0.5));
+}
+
+#[test]
+fn forces_are_clamped_then_divided_by_the_mass() {
+    let mut ctx = SimulationContext::default();
+    ctx.config.max_boid_force = 120.0;
+    let dt = ctx.config.time_step();
+    let start = Boid::new(0, Vector2::new(400.0, 300.0), V2f32::zero());
+    for integrator in Integrator::ALL {
+        ctx.config.integrator = integrator;
+        let mut light = start;
+        light.update(|_| Vector2::new(60.0, 0.0), &ctx);
+        let mut heavy = start.with_mass(2.0);
+        heavy.update(|_| Vector2::new(60.0, 0.0), &ctx);
+        let mut pushed = start;
+        pushed.update(|_| Vector2::new(0.0, 1e6), &ctx);
+
+        assert!(
+            (light.velocity.x - 60.0 * dt).abs() < 1e-4,
+            "{}",
+            integrator
+        );
+        assert!(
+            (heavy.velocity.x - 30.0 * dt).abs() < 1e-4,
+            "{}",
+            integrator
+        );
+        assert!(
+            (pushed.velocity.y - 120.0 * dt).abs() < 1e-4,
+            "{}",
+            integrator
+        );
+    }
+}
+
+#[test]
+fn integrators_parse_by_name() {
+    for integrator in Integrator::ALL {
+        assert_eq!(integrator.to_string().parse(), Ok(integrator));
+    }
+    assert!("leapfrog".parse::<Integrator>().is_err());
+}
@@ -1,3 +1,10 @@
 pub mod boid_impl;
 pub mod boid_mgr;
+pub mod components;
+pub mod handle;
+pub mod integrator;
+pub mod perception;
+pub mod snapshot;
+pub mod species;
+pub mod systems;
 pub mod traits;
@@ -0,0 +1,137 @@
+use std::f32::consts::TAU;
+
+#[cfg(test)]
+use crate::math::vec::Vector2;
+use crate::{
+    config::SimulationConfig,
+    math::vec::{DotProduct, Magnitude, V2f32},
+};
+
+use super::{boid_impl::Boid, species::Species};
+
+/*
+ * What a boid can see: other boids at most `radius` away and at most `angle / 2`
+ * to either side of its heading, which leaves a blind spot of `TAU - angle`
+ * behind it. A boid that doesn't move has no heading and sees all around.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Perception {
+    pub radius: f32,
+    /* Whole field of view in radians, TAU for no blind spot. */
+    pub angle: f32,
+    /* Whether the line of sight is checked against the occluders. */
+    pub occlusion: bool,
+}
+
+impl Perception {
+    pub fn from_config(config: &SimulationConfig, species: Species) -> Self {
+        Self {
+            radius: config.view_distance(species),
+            angle: config.view_angle.to_radians(),
+            occlusion: config.occlusion,
+        }
+    }
+
+    pub fn sees_all_around(&self) -> bool {
+        self.angle >= TAU
+    }
+}
+
+/* Something boids can't see through. */
+pub trait Occluder: Send + Sync {
+    /* True when the segment from `from` to `to` is blocked. */
+    fn occludes(&self, from: V2f32, to: V2f32) -> bool;
+}
+
+impl Boid {
+    /* Unit vector of the velocity, None while standing still. */
+    pub fn heading(&self) -> Option<V2f32> {
+        let speed = self.velocity.calc_magnitude();
+        (speed > 0.0).then(|| self.velocity / speed)
+    }
+
+    pub fn can_see(
+        &self,
+        position: V2f32,
+        perception: &Perception,
+        occluders: &[Box<dyn Occluder>],
+    ) -> bool {
+        let offset = position - self.position;
+        let distance_squared = offset.dot_self();
+        if distance_squared.is_nan() || distance_squared > perception.radius * perception.radius {
+            return false;
+        }
+        if !perception.sees_all_around() && distance_squared > 0.0 {
+            if let Some(heading) = self.heading() {
+                let cos = heading.dot(offset) / distance_squared.sqrt();
+                if cos < (perception.angle / 2.0).cos() {
+                    return false;
+                }
+            }
+        }
+        !perception.occlusion
+            || !occluders
+                .iter()
+                .any(|occluder| occluder.occludes(self.position, position))
+    }
+}
+
+#[cfg(test)]
+fn perception(angle_degrees: f32) -> Perception {
+    Perception {
+        radius: 10.0,
+        angle: angle_degrees.to_radians(),
+        occlusion: true,
+    }
+}
+
+#[test]
+fn blind_spot_is_behind_the_heading() {
+    let boid = Boid::new(0, Vector2::new(0.0, 0.0), Vector2::new(2.0, 0.0));
+    let ahead = Vector2::new(5.0, 0.0);
+    let side = Vector2::new(0.0, 5.0);
+    let behind = Vector2::new(-5.0, 1.0);
+
+    let half = perception(180.0);
+    assert!(boid.can_see(ahead, &half, &[]));
+    assert!(boid.can_see(side, &half, &[]));
+    assert!(!boid.can_see(behind, &half, &[]));
+    assert!(!boid.can_see(Vector2::new(-4.0, -4.0), &half, &[]));
+
+    let wide = perception(300.0);
+    assert!(boid.can_see(Vector2::new(-5.0, 4.0), &wide, &[]));
+    assert!(!boid.can_see(behind, &wide, &[]));
+
+    let all_around = perception(360.0);
+    assert!(all_around.sees_all_around());
+    assert!(boid.can_see(behind, &all_around, &[]));
+}
+
+#[test]
+fn radius_and_standing_still() {
+    let narrow = perception(90.0);
+    let standing = Boid::new(0, Vector2::new(0.0, 0.0), Vector2::zero());
+    assert_eq!(standing.heading(), None);
+    assert!(standing.can_see(Vector2::new(-9.0, 0.0), &narrow, &[]));
+    assert!(standing.can_see(Vector2::new(0.0, 0.0), &narrow, &[]));
+    assert!(!standing.can_see(Vector2::new(-11.0, 0.0), &narrow, &[]));
+    assert!(!standing.can_see(Vector2::new(f32::NAN, 0.0), &narrow, &[]));
+}
+
+#[test]
+fn occluders_block_the_line_of_sight() {
+    /* A wall along x = 3. */
+    struct Wall;
+    impl Occluder for Wall {
+        fn occludes(&self, from: V2f32, to: V2f32) -> bool {
+            (from.x - 3.0).signum() != (to.x - 3.0).signum()
+        }
+    }
+    let occluders: Vec<Box<dyn Occluder>> = vec![Box::new(Wall)];
+    let boid = Boid::new(0, Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0));
+    let mut p = perception(360.0);
+    assert!(boid.can_see(Vector2::new(2.0, 0.0), &p, &occluders));
+    assert!(!boid.can_see(Vector2::new(5.0, 0.0), &p, &occluders));
+    p.occlusion = false;
+    assert!(boid.can_see(Vector2::new(5.0, 0.0), &p, &occluders));
+}
@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::boid_impl::Boid;
+
+/*
+ * Kind of a boid. Speed, view distance and behaviours are per species, see
+ * `SimulationConfig`: the top level settings are the prey's and `predator`
+ * holds the predators'.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Species {
+    #[default]
+    Prey,
+    Predator,
+}
+
+impl Species {
+    pub const ALL: [Species; 2] = [Species::Prey, Species::Predator];
+
+    /* Position in `ALL`, for per species tables. */
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Species::Prey => "prey",
+            Species::Predator => "predator",
+        }
+    }
+}
+
+impl fmt::Display for Species {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Species {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| "expected one of prey, predator".to_string())
+    }
+}
+
+/* What happens to prey a predator catches. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchAction {
+    /* The prey is gone for good. */
+    Remove,
+    /* The prey comes back at a random spot, so the flock never dies out. */
+    #[default]
+    Respawn,
+}
+
+impl CatchAction {
+    pub const ALL: [CatchAction; 2] = [CatchAction::Remove, CatchAction::Respawn];
+
+    fn name(self) -> &'static str {
+        match self {
+            CatchAction::Remove => "remove",
+            CatchAction::Respawn => "respawn",
+        }
+    }
+}
+
+impl fmt::Display for CatchAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CatchAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| "expected one of remove, respawn".to_string())
+    }
+}
+
+/* Head count per species and how much prey was caught since the start. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Population {
+    pub prey: usize,
+    pub predators: usize,
+    pub caught: u64,
+}
+
+impl Population {
+    pub fn count(boids: &[Boid], caught: u64) -> Self {
+        let predators = boids
+            .iter()
+            .filter(|b| b.species == Species::Predator)
+            .count();
+        Self {
+            prey: boids.len() - predators,
+            predators,
+            caught,
+        }
+    }
+}
+
+impl fmt::Display for Population {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "prey {} predators {} caught {}",
+            self.prey, self.predators, self.caught
+        )
+    }
+}
+
+#[test]
+fn parse_species_and_catch_action() {
+    for species in Species::ALL {
+        assert_eq!(species.to_string().parse::<Species>(), Ok(species));
+        assert_eq!(Species::ALL[species.index()], species);
+    }
+    for action in CatchAction::ALL {
+        assert_eq!(action.to_string().parse::<CatchAction>(), Ok(action));
+    }
+    assert!("wolf".parse::<Species>().is_err());
+    assert!("eat".parse::<CatchAction>().is_err());
+}
@@ -0,0 +1,412 @@
+use rayon::prelude::*;
+
+use crate::{
+    constants::{BOID_RADIUS, MAX_BOID_IN_AREA},
+    ecs::{entity::Entity, signature::Signature, system::System, world::World, EcsError},
+    logic::{
+        behaviour::{pipeline::SteeringPipeline, traits::BorderBehaviour},
+        context::SimulationContext,
+        obstacle::resolve_collisions,
+    },
+    math::{
+        spatial::{distance_squared, SpatialIndex},
+        vec::V2f32,
+    },
+};
+#[cfg(feature = "render")]
+use {
+    super::components::Renderable,
+    crate::{
+        camera::Camera,
+        constants::{DrawPrimitives, VIEW_COLOR},
+        math::quadtree::region::Region,
+    },
+    sdl2::{gfx::primitives::DrawRenderer, pixels::Color, render::WindowCanvas},
+};
+
+use super::{
+    boid_impl::Boid,
+    components::{Mass, Position, PreviousPosition, Velocity},
+    handle::BoidHandle,
+    perception::{Occluder, Perception},
+    species::Species,
+    traits::UpdatableAcceleration,
+};
+
+/*
+ * The boids as values, in the order of their BoidHandle components: spawn
+ * order, with the last boid moving into the place of a removed one. Behaviours
+ * and the spatial index work on `boids`, `entities` and `perceptions` are
+ * kept in the same order.
+ */
+pub struct Flock {
+    pub boids: Vec<Boid>,
+    pub entities: Vec<Entity>,
+    pub perceptions: Vec<Perception>,
+    /* Holds indices into `boids`. */
+    pub index: Box<dyn SpatialIndex>,
+    /* The index has to be built before the next query. */
+    pub dirty: bool,
+}
+
+impl Flock {
+    pub fn new(index: Box<dyn SpatialIndex>) -> Self {
+        Self {
+            boids: Vec::new(),
+            entities: Vec::new(),
+            perceptions: Vec::new(),
+            index,
+            dirty: true,
+        }
+    }
+
+    /*
+     * Gathers the boids again from the components of their entities. An
+     * entity missing one of them is left out until it has it again. Boids
+     * that moved since are moved in the index too, when it can follow them.
+     */
+    pub fn refresh(&mut self, world: &World) {
+        let (mut boids, mut entities, mut perceptions) = (
+            Vec::with_capacity(self.boids.len()),
+            Vec::with_capacity(self.entities.len()),
+            Vec::with_capacity(self.perceptions.len()),
+        );
+        let handles = world.storage::<BoidHandle>().into_iter();
+        for &entity in handles.flat_map(|s| s.entities()) {
+            let (Some(boid), Some(perception)) =
+                (gather_boid(world, entity), world.get::<Perception>(entity))
+            else {
+                continue;
+            };
+            boids.push(boid);
+            entities.push(entity);
+            perceptions.push(*perception);
+        }
+
+        if entities != self.entities || self.index.len() != boids.len() {
+            self.dirty = true;
+        }
+        for (index, (old, new)) in self.boids.iter().zip(&boids).enumerate() {
+            if !self.dirty && old.position != new.position {
+                self.dirty = !self
+                    .index
+                    .update_position(index as u32, old.position, new.position);
+            }
+        }
+        self.boids = boids;
+        self.entities = entities;
+        self.perceptions = perceptions;
+    }
+}
+
+/* The boid of `entity` by value, None if it is missing one of its components. */
+pub fn gather_boid(world: &World, entity: Entity) -> Option<Boid> {
+    Some(Boid {
+        position: world.get::<Position>(entity)?.0,
+        velocity: world.get::<Velocity>(entity)?.0,
+        id: world.get::<BoidHandle>(entity)?.id,
+        species: *world.get::<Species>(entity)?,
+        mass: world.get::<Mass>(entity)?.0,
+    })
+}
+
+/* What the FlockingSystem steers with, next to the SimulationContext. */
+pub struct Steering {
+    /* One per species, in `Species::ALL` order. */
+    pub pipelines: Vec<SteeringPipeline>,
+    /*
+     * Checked for line of sight when `config.occlusion` is on, next to
+     * `config.obstacles` which always block the view.
+     */
+    pub occluders: Vec<Box<dyn Occluder>>,
+    /* Seed of the current tick for dithered steering, see `tick_seed`. */
+    pub dither: u64,
+}
+
+/* The components every boid has besides its PreviousPosition and Renderable. */
+pub fn boid_signature(world: &mut World) -> Result<Signature, EcsError> {
+    Ok(Signature::empty()
+        .with(world.register_component::<BoidHandle>()?)
+        .with(world.register_component::<Position>()?)
+        .with(world.register_component::<Velocity>()?)
+        .with(world.register_component::<Species>()?)
+        .with(world.register_component::<Mass>()?)
+        .with(world.register_component::<Perception>()?))
+}
+
+/*
+ * The systems of one simulation step, in the order they run. They need the
+ * Flock, Steering and SimulationContext resources, without them they do
+ * nothing.
+ */
+pub fn register_boid_systems(world: &mut World) -> Result<(), EcsError> {
+    let boid = boid_signature(world)?;
+    let position = world.register_component::<Position>()?;
+    let velocity = world.register_component::<Velocity>()?;
+    let previous = world.register_component::<PreviousPosition>()?;
+    world.register_system(
+        HistorySystem,
+        Signature::empty().with(position).with(previous),
+    );
+    world.register_system(
+        BorderSystem,
+        Signature::empty().with(position).with(velocity),
+    );
+    world.register_system(SpatialIndexSystem, boid);
+    world.register_system(FlockingSystem, boid);
+    Ok(())
+}
+
+/* Remembers where every boid was before the step, to draw it in between. */
+pub struct HistorySystem;
+
+impl System for HistorySystem {
+    fn run(&mut self, entities: &[Entity], world: &mut World) {
+        for &entity in entities {
+            if let Some(&Position(position)) = world.get::<Position>(entity) {
+                world.get_mut::<PreviousPosition>(entity).unwrap().0 = position;
+            }
+        }
+    }
+}
+
+/*
+ * Wraps or reflects everything with a position and velocity at the border,
+ * see `SimulationContext::border_behaviour`. Reflecting twice undoes it, so
+ * it runs once per step.
+ */
+pub struct BorderSystem;
+
+impl System for BorderSystem {
+    fn run(&mut self, entities: &[Entity], world: &mut World) {
+        world.resource_scope(|world, ctx: &mut SimulationContext| {
+            for &entity in entities {
+                let (Some(position), Some(velocity)) =
+                    (world.get::<Position>(entity), world.get::<Velocity>(entity))
+                else {
+                    continue;
+                };
+                let mut boid = Boid::new(0, position.0, velocity.0);
+                boid.border(&ctx.border_behaviour, &ctx.config);
+                world.get_mut::<Position>(entity).unwrap().0 = boid.position;
+                world.get_mut::<Velocity>(entity).unwrap().0 = boid.velocity;
+            }
+        });
+    }
+}
+
+/*
+ * Brings the Flock up to date with the components and builds the spatial
+ * index again when it couldn't follow the changes.
+ */
+pub struct SpatialIndexSystem;
+
+impl System for SpatialIndexSystem {
+    fn run(&mut self, _: &[Entity], world: &mut World) {
+        world.resource_scope(|world, flock: &mut Flock| {
+            flock.refresh(world);
+            if let Some(ctx) = world
+                .resource::<SimulationContext>()
+                .filter(|_| flock.dirty)
+            {
+                flock.index.build(ctx.config.view_port(), &flock.boids);
+                flock.dirty = false;
+            }
+        });
+    }
+}
+
+/*
+ * Steers and moves every boid, then keeps the spatial index in step. Backends
+ * that can't follow single moves are built again on the next step. A boid that
+ * isn't finite afterwards is recovered before anyone sees it, one that ran
+ * into an obstacle is put back on its outline.
+ */
+pub struct FlockingSystem;
+
+impl System for FlockingSystem {
+    fn run(&mut self, _: &[Entity], world: &mut World) {
+        world.resource_scope(|world, flock: &mut Flock| {
+            let next_states = match (
+                world.resource::<SimulationContext>(),
+                world.resource::<Steering>(),
+            ) {
+                (Some(ctx), Some(steering)) => next_states(flock, steering, ctx),
+                _ => return,
+            };
+            let mut in_step = !flock.dirty;
+            for (index, next) in next_states.into_iter().enumerate() {
+                let entity = flock.entities[index];
+                world.get_mut::<Position>(entity).unwrap().0 = next.position;
+                world.get_mut::<Velocity>(entity).unwrap().0 = next.velocity;
+                let previous = std::mem::replace(&mut flock.boids[index], next);
+                in_step &=
+                    flock
+                        .index
+                        .update_position(index as u32, previous.position, next.position);
+            }
+            flock.dirty = !in_step;
+        });
+    }
+}
+
+/*
+ * Where boid `index` is after this step. Only reads the flock, so the result
+ * doesn't depend on which boids were already processed this step. Behaviours
+ * only get the neighbours the boid perceives, never the boid itself; the
+ * integrator sees them stand still while it tries out states of the boid.
+ * `indices` and `neighbours` are scratch buffers reused between boids.
+ */
+fn next_state<'a>(
+    flock: &'a Flock,
+    index: usize,
+    steering: &Steering,
+    ctx: &SimulationContext,
+    indices: &mut Vec<u32>,
+    neighbours: &mut Vec<&'a Boid>,
+) -> Boid {
+    let boid = &flock.boids[index];
+    let perception = &flock.perceptions[index];
+    indices.clear();
+    flock
+        .index
+        .query_radius(&flock.boids, boid.position, perception.radius, indices);
+    neighbours.clear();
+    neighbours.extend(
+        indices
+            .iter()
+            .map(|&i| &flock.boids[i as usize])
+            .filter(|other| {
+                other.id != boid.id
+                    && boid.can_see(other.position, perception, &steering.occluders)
+                    && !(perception.occlusion
+                        && ctx
+                            .config
+                            .obstacles
+                            .iter()
+                            .any(|o| o.occludes(boid.position, other.position)))
+            }),
+    );
+
+    let pipeline = &steering.pipelines[boid.species.index()];
+    let mut next = *boid;
+    next.update(
+        |state: &Boid| pipeline.steer(state, neighbours, ctx, steering.dither),
+        ctx,
+    );
+    if !next.is_finite() {
+        log::error!("boid {} became {:?}, recovered", next.id, next);
+        next.recover(boid, &ctx.config);
+    }
+    resolve_collisions(&mut next, &ctx.config.obstacles, BOID_RADIUS);
+    next
+}
+
+fn next_states(flock: &Flock, steering: &Steering, ctx: &SimulationContext) -> Vec<Boid> {
+    let scratch = || {
+        (
+            Vec::with_capacity(MAX_BOID_IN_AREA),
+            Vec::with_capacity(MAX_BOID_IN_AREA),
+        )
+    };
+    if ctx.config.parallel {
+        (0..flock.boids.len())
+            .into_par_iter()
+            .map_init(scratch, |(indices, neighbours), index| {
+                next_state(flock, index, steering, ctx, indices, neighbours)
+            })
+            .collect()
+    } else {
+        let (mut indices, mut neighbours) = scratch();
+        (0..flock.boids.len())
+            .map(|index| next_state(flock, index, steering, ctx, &mut indices, &mut neighbours))
+            .collect()
+    }
+}
+
+/*
+ * Where to draw `entity` when `alpha` of the way from the previous step to
+ * the current one. Entities that jumped, like wrapping around the border or
+ * being respawned, are drawn where they are now.
+ */
+pub fn interpolated_position(world: &World, entity: Entity, alpha: f32) -> Option<V2f32> {
+    let position = world.get::<Position>(entity)?.0;
+    let (Some(&PreviousPosition(previous)), Some(ctx)) = (
+        world.get::<PreviousPosition>(entity),
+        world.resource::<SimulationContext>(),
+    ) else {
+        return Some(position);
+    };
+    let species = world.get::<Species>(entity).copied().unwrap_or_default();
+    let max_step = 2.0 * ctx.config.max_speed(species) * ctx.config.time_step();
+    Some(
+        match distance_squared(previous, position) <= max_step.powi(2) {
+            true => previous + (position - previous) * alpha,
+            false => position,
+        },
+    )
+}
+
+/* Draws everything Renderable. Not run with the other systems, it needs the canvas. */
+#[cfg(feature = "render")]
+pub struct RenderSystem;
+
+#[cfg(feature = "render")]
+impl RenderSystem {
+    /* `alpha` of the way between the last two steps, see `interpolated_position`. */
+    pub fn draw(
+        world: &World,
+        canvas: &mut WindowCanvas,
+        camera: &Camera,
+        primitives: DrawPrimitives,
+        alpha: f32,
+    ) {
+        let Ok(renderables) = world.storage::<Renderable>() else {
+            return;
+        };
+        for (entity, renderable) in renderables.iter() {
+            let Some(position) = interpolated_position(world, entity, alpha) else {
+                continue;
+            };
+            let [r, g, b] = renderable.color;
+            let rect = Region::rect_from_center_with_distance(
+                camera.calc_pos_v2f32(position),
+                renderable.size,
+            );
+            let _ = canvas.rectangle(
+                rect.left_up.x as i16,
+                rect.left_up.y as i16,
+                rect.right_down.x as i16,
+                rect.right_down.y as i16,
+                Color::RGB(r, g, b),
+            );
+
+            /* The field of view as a pie slice, the open side is the blind spot. */
+            if let Some(perception) = world
+                .get::<Perception>(entity)
+                .filter(|_| primitives.contains(DrawPrimitives::BOID_VIEW))
+            {
+                let velocity = world.get::<Velocity>(entity).map_or(V2f32::zero(), |v| v.0);
+                let radius = perception.radius as i16;
+                let half_angle = perception.angle.to_degrees() / 2.0;
+                let center = camera.calc_pos_v2f32(position);
+                let (x, y) = (center.x as i16, center.y as i16);
+                let heading = Boid::new(0, position, velocity).heading();
+                let _ = match heading.filter(|_| !perception.sees_all_around()) {
+                    Some(heading) => {
+                        let heading = heading.y.atan2(heading.x).to_degrees();
+                        canvas.pie(
+                            x,
+                            y,
+                            radius,
+                            (heading - half_angle) as i16,
+                            (heading + half_angle) as i16,
+                            VIEW_COLOR,
+                        )
+                    }
+                    None => canvas.circle(x, y, radius, VIEW_COLOR),
+                };
+            }
+        }
+    }
+}
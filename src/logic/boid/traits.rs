@@ -1,8 +1,9 @@
-use crate::math::vec::V2f32;
+use crate::{logic::context::SimulationContext, math::vec::V2f32};
 
 pub trait Updatable {
     fn update(&mut self);
 }
+/* `force` gives the steering force on `Self` in any state, integrators ask for it more than once. */
 pub trait UpdatableAcceleration {
-    fn update(&mut self, acceleration: V2f32);
+    fn update<F: Fn(&Self) -> V2f32>(&mut self, force: F, ctx: &SimulationContext);
 }
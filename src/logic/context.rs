@@ -0,0 +1,27 @@
+use crate::{config::SimulationConfig, logic::behaviour::traits::BorderBehaviourE};
+
+/*
+ * Everything a behaviour or a boid needs to know about the simulation it lives in.
+ * It is owned by BoidManager and only ever handed out as `&SimulationContext`,
+ * so it can be shared between rayon workers and every manager can have its own.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationContext {
+    pub config: SimulationConfig,
+    pub border_behaviour: BorderBehaviourE,
+}
+
+impl SimulationContext {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            config,
+            border_behaviour: BorderBehaviourE::GoThrough,
+        }
+    }
+}
+
+impl Default for SimulationContext {
+    fn default() -> Self {
+        Self::new(SimulationConfig::default())
+    }
+}
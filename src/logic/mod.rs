@@ -1,2 +1,4 @@
 pub mod behaviour;
 pub mod boid;
+pub mod context;
+pub mod obstacle;
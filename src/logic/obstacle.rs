@@ -0,0 +1,404 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    logic::boid::{boid_impl::Boid, perception::Occluder},
+    math::vec::{DotProduct, Magnitude, V2f32, Vector2},
+};
+#[cfg(feature = "render")]
+use {
+    crate::{
+        camera::Camera,
+        constants::{DrawPrimitives, OBSTACLE_COLOR},
+        graphics::renderer::Renderable,
+    },
+    sdl2::{gfx::primitives::DrawRenderer, render::WindowCanvas},
+};
+
+/* Outlines closer than this count as touching. */
+const EPSILON: f32 = 1e-4;
+
+/*
+ * Something solid in the world. Boids steer around obstacles with
+ * `AvoidObstaclesBehaviour`, can't see through them when occlusion is on and
+ * are pushed back out by `resolve_collisions` if they get in anyway.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Obstacle {
+    Circle { center: V2f32, radius: f32 },
+    Rect { left_up: V2f32, right_down: V2f32 },
+    /* Corners in order, the last one connects back to the first. Must not cross itself. */
+    Polygon { vertices: Vec<V2f32> },
+}
+
+/* Where a ray first touches an obstacle. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub distance: f32,
+    /* Unit normal of the outline, pointing back towards the ray's origin. */
+    pub normal: V2f32,
+}
+
+fn cross(a: V2f32, b: V2f32) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn unit(v: V2f32) -> Option<V2f32> {
+    let length = v.calc_magnitude();
+    (length > EPSILON).then(|| v / length)
+}
+
+/* Closest point to `p` on the segment from `a` to `b`. */
+fn closest_on_segment(p: V2f32, a: V2f32, b: V2f32) -> V2f32 {
+    let edge = b - a;
+    let length_squared = edge.dot_self();
+    if length_squared <= 0.0 {
+        return a;
+    }
+    let t = ((p - a).dot(edge) / length_squared).clamp(0.0, 1.0);
+    a + edge * t
+}
+
+impl Obstacle {
+    /* Corners of rectangles and polygons, empty for circles. */
+    fn corners(&self) -> Cow<'_, [V2f32]> {
+        match self {
+            Obstacle::Circle { .. } => Cow::Borrowed(&[]),
+            Obstacle::Rect {
+                left_up,
+                right_down,
+            } => Cow::Owned(vec![
+                *left_up,
+                Vector2::new(right_down.x, left_up.y),
+                *right_down,
+                Vector2::new(left_up.x, right_down.y),
+            ]),
+            Obstacle::Polygon { vertices } => Cow::Borrowed(vertices),
+        }
+    }
+
+    fn edges(corners: &[V2f32]) -> impl Iterator<Item = (V2f32, V2f32)> + '_ {
+        corners
+            .iter()
+            .zip(corners.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    /* Unit normal of the edge from `a` to `b` that points out of the corners' polygon. */
+    fn outward_normal(corners: &[V2f32], a: V2f32, b: V2f32) -> V2f32 {
+        let area: f32 = Self::edges(corners).map(|(a, b)| cross(a, b)).sum();
+        let edge = b - a;
+        let normal = Vector2::new(edge.y, -edge.x) * area.signum();
+        unit(normal).unwrap_or(Vector2::new(1.0, 0.0))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let finite = |v: &V2f32| v.x.is_finite() && v.y.is_finite();
+        match self {
+            Obstacle::Circle { center, radius } => {
+                if !(finite(center) && radius.is_finite() && *radius > 0.0) {
+                    return Err(format!("circle at {} needs a positive radius", center));
+                }
+            }
+            Obstacle::Rect {
+                left_up,
+                right_down,
+            } => {
+                if !(finite(left_up)
+                    && finite(right_down)
+                    && left_up.x < right_down.x
+                    && left_up.y < right_down.y)
+                {
+                    return Err(format!(
+                        "rect {} {} needs left_up above and left of right_down",
+                        left_up, right_down
+                    ));
+                }
+            }
+            Obstacle::Polygon { vertices } => {
+                if vertices.len() < 3 || !vertices.iter().all(finite) {
+                    return Err("polygon needs at least 3 finite vertices".to_string());
+                }
+                let area: f32 = Self::edges(vertices).map(|(a, b)| cross(a, b)).sum();
+                if area.abs() <= EPSILON {
+                    return Err("polygon has no area".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, p: V2f32) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => (p - *center).dot_self() < radius * radius,
+            _ => {
+                /* Even-odd rule, a ray to the right crosses the outline an odd number of times. */
+                let corners = self.corners();
+                Self::edges(&corners)
+                    .filter(|(a, b)| (a.y > p.y) != (b.y > p.y))
+                    .filter(|(a, b)| p.x < a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y))
+                    .count()
+                    % 2
+                    == 1
+            }
+        }
+    }
+
+    /* Closest point on the outline and the unit normal there, pointing out of the obstacle. */
+    pub fn closest_on_outline(&self, p: V2f32) -> (V2f32, V2f32) {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let normal = unit(p - *center).unwrap_or(Vector2::new(1.0, 0.0));
+                (*center + normal * *radius, normal)
+            }
+            _ => {
+                let corners = self.corners();
+                let (a, b, closest) = Self::edges(&corners)
+                    .map(|(a, b)| (a, b, closest_on_segment(p, a, b)))
+                    .min_by(|x, y| (x.2 - p).dot_self().total_cmp(&(y.2 - p).dot_self()))
+                    .expect("validated polygons have edges");
+                let normal = match unit(p - closest) {
+                    Some(away) if self.contains(p) => away * -1.0,
+                    Some(away) => away,
+                    None => Self::outward_normal(&corners, a, b),
+                };
+                (closest, normal)
+            }
+        }
+    }
+
+    /*
+     * First point at most `length` along the ray from `origin` in the unit
+     * `direction` where it meets the outline. A ray starting inside hits at 0.
+     */
+    pub fn ray_hit(&self, origin: V2f32, direction: V2f32, length: f32) -> Option<RayHit> {
+        if self.contains(origin) {
+            let (_, normal) = self.closest_on_outline(origin);
+            return Some(RayHit {
+                distance: 0.0,
+                normal,
+            });
+        }
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let m = origin - *center;
+                let b = m.dot(direction);
+                let discriminant = b * b - (m.dot_self() - radius * radius);
+                if b > 0.0 || discriminant < 0.0 {
+                    return None;
+                }
+                let distance = -b - discriminant.sqrt();
+                (distance <= length).then(|| RayHit {
+                    distance,
+                    normal: unit(origin + direction * distance - *center)
+                        .unwrap_or(direction * -1.0),
+                })
+            }
+            _ => {
+                let corners = self.corners();
+                Self::edges(&corners)
+                    .filter_map(|(a, b)| {
+                        let edge = b - a;
+                        let denominator = cross(direction, edge);
+                        if denominator.abs() <= f32::EPSILON {
+                            return None;
+                        }
+                        let offset = a - origin;
+                        let distance = cross(offset, edge) / denominator;
+                        let along = cross(offset, direction) / denominator;
+                        let on_edge = (0.0..=1.0).contains(&along);
+                        (on_edge && (0.0..=length).contains(&distance)).then(|| {
+                            let mut normal = Self::outward_normal(&corners, a, b);
+                            if normal.dot(direction) > 0.0 {
+                                normal *= -1.0;
+                            }
+                            RayHit { distance, normal }
+                        })
+                    })
+                    .min_by(|x, y| x.distance.total_cmp(&y.distance))
+            }
+        }
+    }
+
+    /*
+     * Where a round body of `radius` at `p` has to go to not overlap the
+     * obstacle, with the outward normal there. None if it doesn't overlap.
+     */
+    pub fn push_out(&self, p: V2f32, radius: f32) -> Option<(V2f32, V2f32)> {
+        let (closest, normal) = self.closest_on_outline(p);
+        let inside = self.contains(p);
+        if !inside && (p - closest).dot_self() >= radius * radius {
+            return None;
+        }
+        Some((closest + normal * radius, normal))
+    }
+}
+
+impl Occluder for Obstacle {
+    fn occludes(&self, from: V2f32, to: V2f32) -> bool {
+        let offset = to - from;
+        match unit(offset) {
+            Some(direction) => self
+                .ray_hit(from, direction, offset.calc_magnitude())
+                .is_some(),
+            None => false,
+        }
+    }
+}
+
+/*
+ * Moves `boid` out of every obstacle it overlaps and takes away the part of
+ * its velocity that points into them, so it slides along the outline.
+ * Overlapping obstacles can push a boid into each other, so a few rounds are run.
+ */
+pub fn resolve_collisions(boid: &mut Boid, obstacles: &[Obstacle], radius: f32) {
+    for _ in 0..4 {
+        let mut moved = false;
+        for obstacle in obstacles {
+            if let Some((position, normal)) = obstacle.push_out(boid.position, radius) {
+                boid.position = position;
+                let into = boid.velocity.dot(normal);
+                if into < 0.0 {
+                    boid.velocity -= normal * into;
+                }
+                moved = true;
+            }
+        }
+        if !moved {
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl Renderable for Obstacle {
+    fn render(&mut self, canvas: &mut WindowCanvas, camera: &Camera, _: DrawPrimitives) {
+        let _ = match self {
+            Obstacle::Circle { center, radius } => {
+                let c = camera.calc_pos_v2f32(*center);
+                canvas.circle(c.x as i16, c.y as i16, *radius as i16, OBSTACLE_COLOR)
+            }
+            Obstacle::Rect {
+                left_up,
+                right_down,
+            } => {
+                let (a, b) = (
+                    camera.calc_pos_v2f32(*left_up),
+                    camera.calc_pos_v2f32(*right_down),
+                );
+                canvas.rectangle(
+                    a.x as i16,
+                    a.y as i16,
+                    b.x as i16,
+                    b.y as i16,
+                    OBSTACLE_COLOR,
+                )
+            }
+            Obstacle::Polygon { vertices } => {
+                let points: Vec<V2f32> =
+                    vertices.iter().map(|v| camera.calc_pos_v2f32(*v)).collect();
+                let xs: Vec<i16> = points.iter().map(|p| p.x as i16).collect();
+                let ys: Vec<i16> = points.iter().map(|p| p.y as i16).collect();
+                canvas.polygon(&xs, &ys, OBSTACLE_COLOR)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+fn shapes() -> Vec<Obstacle> {
+    vec![
+        Obstacle::Circle {
+            center: Vector2::new(0.0, 0.0),
+            radius: 10.0,
+        },
+        Obstacle::Rect {
+            left_up: Vector2::new(-10.0, -10.0),
+            right_down: Vector2::new(10.0, 10.0),
+        },
+        /* A diamond, clockwise on screen. */
+        Obstacle::Polygon {
+            vertices: vec![
+                Vector2::new(0.0, -10.0),
+                Vector2::new(10.0, 0.0),
+                Vector2::new(0.0, 10.0),
+                Vector2::new(-10.0, 0.0),
+            ],
+        },
+    ]
+}
+
+#[test]
+fn contains_and_outline() {
+    for shape in shapes() {
+        assert!(shape.validate().is_ok(), "{:?}", shape);
+        assert!(shape.contains(Vector2::new(0.0, 0.0)), "{:?}", shape);
+        assert!(shape.contains(Vector2::new(5.0, 1.0)), "{:?}", shape);
+        assert!(!shape.contains(Vector2::new(20.0, 0.0)), "{:?}", shape);
+        assert!(!shape.contains(Vector2::new(-3.0, 15.0)), "{:?}", shape);
+
+        /* Both from outside and inside the normal points out to the right. */
+        let (closest, normal) = shape.closest_on_outline(Vector2::new(15.0, 0.0));
+        approx::assert_relative_eq!(closest.x, 10.0, epsilon = 1e-4);
+        approx::assert_relative_eq!(closest.y, 0.0, epsilon = 1e-4);
+        assert!(normal.x > 0.7, "{:?} {}", shape, normal);
+        let (closest, normal) = shape.closest_on_outline(Vector2::new(7.0, 0.0));
+        assert!(
+            closest.x > 7.0 && !shape.contains(closest + normal),
+            "{:?}",
+            shape
+        );
+        assert!(normal.x > 0.7, "{:?} {}", shape, normal);
+    }
+    let flat = Obstacle::Polygon {
+        vertices: vec![
+            Vector2::zero(),
+            Vector2::new(1.0, 1.0),
+            Vector2::new(2.0, 2.0),
+        ],
+    };
+    assert!(flat.validate().is_err());
+    let backwards = Obstacle::Rect {
+        left_up: Vector2::new(1.0, 1.0),
+        right_down: Vector2::zero(),
+    };
+    assert!(backwards.validate().is_err());
+}
+
+#[test]
+fn rays_hit_the_near_side() {
+    let right: V2f32 = Vector2::new(1.0, 0.0);
+    for shape in shapes() {
+        let hit = shape
+            .ray_hit(Vector2::new(-30.0, 0.0), right, 50.0)
+            .unwrap();
+        approx::assert_relative_eq!(hit.distance, 20.0, epsilon = 1e-4);
+        assert!(hit.normal.x < -0.7, "{:?} {}", shape, hit.normal);
+        /* Too short, pointing away and passing by. */
+        assert_eq!(shape.ray_hit(Vector2::new(-30.0, 0.0), right, 15.0), None);
+        assert_eq!(shape.ray_hit(Vector2::new(30.0, 0.0), right, 50.0), None);
+        assert_eq!(shape.ray_hit(Vector2::new(-30.0, 20.0), right, 50.0), None);
+        assert!(shape.occludes(Vector2::new(-30.0, 0.0), Vector2::new(30.0, 0.0)));
+        assert!(!shape.occludes(Vector2::new(-30.0, 20.0), Vector2::new(30.0, 20.0)));
+    }
+}
+
+#[test]
+fn collisions_push_boids_out_and_slide() {
+    for shape in shapes() {
+        let mut boid = Boid::new(0, Vector2::new(8.0, 1.0), Vector2::new(-2.0, 1.0));
+        resolve_collisions(&mut boid, std::slice::from_ref(&shape), 2.0);
+        assert!(!shape.contains(boid.position), "{:?}", shape);
+        assert!(shape.push_out(boid.position, 1.9).is_none(), "{:?}", shape);
+        /* The part into the obstacle is gone, the slide along it is kept. */
+        let (_, normal) = shape.closest_on_outline(boid.position);
+        assert!(boid.velocity.dot(normal) >= -1e-4, "{:?}", shape);
+        assert!(boid.velocity.y > 0.0);
+    }
+    let mut free = Boid::new(0, Vector2::new(50.0, 50.0), Vector2::new(-1.0, 0.0));
+    resolve_collisions(&mut free, &shapes(), 2.0);
+    assert_eq!(free.position, Vector2::new(50.0, 50.0));
+    assert_eq!(free.velocity, Vector2::new(-1.0, 0.0));
+}
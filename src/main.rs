@@ -1,135 +1,164 @@
-pub mod camera;
-pub mod constants;
-pub mod ecs;
-pub mod game;
-pub mod graphics;
-pub mod logic;
-pub mod math;
+use std::path::PathBuf;
 
-extern crate approx;
-extern crate crossbeam;
-extern crate sdl2;
-
-use std::time::Duration;
-
-use constants::{
-    BehaviourEnabled, DrawPrimitives, BEHAVIOUR_ENABLED, BOIDS_AMOUNT, BORDER_BEHAVIOUR,
-    DRAW_PRIMITIVES, SCREEN_SIZE, VIEW_PORT_SIZE,
+use game::config::SimulationConfig;
+use game::headless::HeadlessRunner;
+use game::logic::{
+    behaviour::{pipeline::SteeringPipeline, registry::BehaviourRegistry},
+    boid::{boid_mgr::BoidManager, species::Species},
 };
-use game::GameBuilder;
-use graphics::renderer::{GfxSubsystem, RendererManager};
-use logic::behaviour::traits::BorderBehaviourE;
-use logic::boid::boid_mgr::BoidManager;
-use logic::boid::traits::Updatable;
-use math::quadtree::region::Region;
-use math::vec::Vector2;
-use sdl2::event::Event;
-use sdl2::gfx::framerate::FPSManager;
-use sdl2::keyboard::Keycode;
 
-use log::LevelFilter;
-use log4rs::append::file::FileAppender;
-use log4rs::config::{Appender, Config, Root};
-use log4rs::encode::pattern::PatternEncoder;
-
-pub fn main() -> Result<(), String> {
-    let ttf_context = sdl2::ttf::init().unwrap();
-    let gss = GfxSubsystem::new(&ttf_context);
-
-    let video_subsystem = gss.sdl_context.video()?;
-    let window = video_subsystem
-        .window("Boids", SCREEN_SIZE.x as u32, SCREEN_SIZE.y as u32)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())?;
+#[cfg(feature = "render")]
+use game::{
+    game::GameBuilder,
+    recording::{replay::Replay, Recording},
+};
 
-    let mut event_pump = gss.sdl_context.event_pump()?;
-    let mut renderer = RendererManager::new(window, gss);
+const DEFAULT_HEADLESS_STEPS: u64 = 1000;
 
-    let mut fps_manager: FPSManager = FPSManager::new();
-    fps_manager.set_framerate(100)?;
+struct Options {
+    headless: bool,
+    steps: u64,
+    config: SimulationConfig,
+    load_snapshot: Option<PathBuf>,
+    save_snapshot: Option<PathBuf>,
+    record: Option<PathBuf>,
+    #[cfg_attr(not(feature = "render"), allow(dead_code))]
+    replay: Option<PathBuf>,
+}
 
-    let r: Region = Region::new(Vector2::new(0.0, 0.0), VIEW_PORT_SIZE);
-    let mut boid_manager = BoidManager::new(r.clone());
-    boid_manager.spawn_boid(BOIDS_AMOUNT);
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut headless = !cfg!(feature = "render");
+        let mut steps = DEFAULT_HEADLESS_STEPS;
+        let mut config_path: Option<PathBuf> = None;
+        let mut overrides: Vec<String> = vec![];
+        let mut load_snapshot: Option<PathBuf> = None;
+        let mut save_snapshot: Option<PathBuf> = None;
+        let mut record: Option<PathBuf> = None;
+        let mut replay: Option<PathBuf> = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--steps" => {
+                    let value = args.next().ok_or("--steps needs a value")?;
+                    steps = value
+                        .parse()
+                        .map_err(|e| format!("invalid --steps value {}: {}", value, e))?;
+                }
+                "--config" => {
+                    config_path = Some(args.next().ok_or("--config needs a path")?.into());
+                }
+                "--set" => overrides.push(args.next().ok_or("--set needs key=value")?),
+                "--load" => {
+                    load_snapshot = Some(args.next().ok_or("--load needs a path")?.into());
+                }
+                "--save" => {
+                    save_snapshot = Some(args.next().ok_or("--save needs a path")?.into());
+                }
+                "--record" => {
+                    record = Some(args.next().ok_or("--record needs a path")?.into());
+                }
+                "--replay" => {
+                    replay = Some(args.next().ok_or("--replay needs a path")?.into());
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if !headless && !cfg!(feature = "render") {
+            return Err("built without the `render` feature, only --headless is available".into());
+        }
+        if headless && replay.is_some() {
+            return Err("--replay needs a window, it cannot be combined with --headless".into());
+        }
 
-    let mut camera = camera::Camera::new(r.left_up);
-    log::info!("camera position {:?}", camera);
+        let mut config = match config_path {
+            Some(path) => SimulationConfig::from_file(&path).map_err(|e| e.to_string())?,
+            None => SimulationConfig::default(),
+        };
+        for assignment in &overrides {
+            config.set_from_str(assignment).map_err(|e| e.to_string())?;
+        }
+        config.validate().map_err(|e| e.to_string())?;
+        let registry = BehaviourRegistry::default();
+        for species in Species::ALL {
+            SteeringPipeline::build(&registry, &config, species).map_err(|e| e.to_string())?;
+        }
 
-    'running: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => break 'running,
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => match keycode {
-                    Keycode::W => {
-                        boid_manager.add_boid(1);
-                    }
-                    Keycode::R => {
-                        BORDER_BEHAVIOUR.with(|value: &std::cell::RefCell<BorderBehaviourE>| {
-                            let v = match *value.borrow() {
-                                BorderBehaviourE::GoThrough => BorderBehaviourE::Reflect,
-                                BorderBehaviourE::Reflect => BorderBehaviourE::GoThrough,
-                            };
-                            *value.borrow_mut() = v;
-                        });
-                    }
-                    Keycode::Num1 => unsafe {
-                        BEHAVIOUR_ENABLED ^= BehaviourEnabled::COHESION;
-                    },
-                    Keycode::Num2 => unsafe {
-                        BEHAVIOUR_ENABLED ^= BehaviourEnabled::ALLIGN;
-                    },
-                    Keycode::Num3 => unsafe {
-                        BEHAVIOUR_ENABLED ^= BehaviourEnabled::SEPERATE;
-                    },
+        Ok(Options {
+            headless,
+            steps,
+            config,
+            load_snapshot,
+            save_snapshot,
+            record,
+            replay,
+        })
+    }
+}
 
-                    Keycode::Num4 => unsafe {
-                        BEHAVIOUR_ENABLED ^= BehaviourEnabled::BOUND;
-                    },
-                    Keycode::Num5 => {
-                        DRAW_PRIMITIVES.with(|value| {
-                            *value.borrow_mut() ^= DrawPrimitives::QUAD_TREE;
-                        });
-                    }
-                    Keycode::Num6 => {
-                        DRAW_PRIMITIVES.with(|value| {
-                            *value.borrow_mut() ^= DrawPrimitives::BOID_VIEW;
-                        });
-                    }
-                    Keycode::Num7 => {
-                        DRAW_PRIMITIVES.with(|value| {
-                            *value.borrow_mut() ^= DrawPrimitives::BOUND_VIEW;
-                        });
-                    }
-                    Keycode::Left => {
-                        camera.pos.x -= 20.0;
-                    }
-                    Keycode::Right => {
-                        camera.pos.x += 20.0;
-                    }
-                    Keycode::Down => {
-                        camera.pos.y += 20.0;
-                    }
-                    Keycode::Up => {
-                        camera.pos.y -= 20.0;
-                    }
-                    Keycode::Escape => break 'running,
-                    _ => {}
-                },
-                _ => {}
-            }
+/* A loaded snapshot brings its own config, `--config` and `--set` only apply to new flocks. */
+fn create_boid_manager(options: &Options) -> Result<BoidManager, String> {
+    let mut boid_manager = match &options.load_snapshot {
+        Some(path) => BoidManager::load_snapshot(path).map_err(|e| e.to_string())?,
+        None => {
+            let mut boid_manager = BoidManager::new(options.config.clone());
+            boid_manager.spawn_boid(options.config.boids_amount);
+            boid_manager.add_species(Species::Predator, options.config.predator.amount);
+            boid_manager
         }
-        renderer.draw(&mut boid_manager, &camera);
-        boid_manager.update();
-        ::std::thread::sleep(Duration::new(
-            0,
-            1_000_000_000u32 / fps_manager.get_framerate() as u32,
-        ));
+    };
+    if let Some(path) = &options.record {
+        boid_manager.record_to(path).map_err(|e| e.to_string())?;
     }
+    Ok(boid_manager)
+}
 
+fn run_headless(options: &Options) -> Result<(), String> {
+    let boid_manager = create_boid_manager(options)?;
+    let mut runner = HeadlessRunner::new(boid_manager);
+    runner.run(options.steps);
+    runner
+        .simulation_mut()
+        .stop_recording()
+        .map_err(|e| e.to_string())?;
+    if let Some(path) = &options.save_snapshot {
+        runner
+            .simulation()
+            .save_snapshot(path)
+            .map_err(|e| e.to_string())?;
+    }
+    println!(
+        "simulated {} (seed {}) for {} steps ({:.1} s) in {:?}",
+        runner.simulation().population(),
+        runner.simulation().seed(),
+        runner.ticks(),
+        runner.ticks() as f32 * runner.simulation().context().config.time_step(),
+        runner.elapsed()
+    );
     Ok(())
 }
+
+pub fn main() -> Result<(), String> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    if options.headless {
+        return run_headless(&options);
+    }
+    #[cfg(feature = "render")]
+    return run_windowed(&options);
+    #[cfg(not(feature = "render"))]
+    unreachable!("Options::parse rejects windowed mode without `render`")
+}
+
+#[cfg(feature = "render")]
+fn run_windowed(options: &Options) -> Result<(), String> {
+    let builder = match &options.replay {
+        Some(path) => {
+            let recording = Recording::load(path).map_err(|e| e.to_string())?;
+            GameBuilder::new(BoidManager::new(options.config.clone()))
+                .with_replay(Replay::new(recording))
+        }
+        None => GameBuilder::new(create_boid_manager(options)?),
+    };
+    let mut boid_manager = builder.build().run()?;
+    boid_manager.stop_recording().map_err(|e| e.to_string())
+}
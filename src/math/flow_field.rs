@@ -0,0 +1,220 @@
+use std::{f32::consts::TAU, fmt, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    noise::Perlin,
+    vec::{V2f32, Vector2},
+};
+
+#[derive(Debug)]
+pub enum FlowFieldError {
+    Io(String),
+    Parse(String),
+    UnknownFormat(String),
+    Invalid(String),
+}
+
+impl fmt::Display for FlowFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlowFieldError::Io(e) => write!(f, "cannot read flow field: {}", e),
+            FlowFieldError::Parse(e) => write!(f, "cannot parse flow field: {}", e),
+            FlowFieldError::UnknownFormat(path) => write!(
+                f,
+                "unknown flow field format of {}, use .toml, .ron or .json",
+                path
+            ),
+            FlowFieldError::Invalid(reason) => write!(f, "invalid flow field: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FlowFieldError {}
+
+/*
+ * A grid of vectors over the world, `columns` by `rows` square cells of
+ * `cell_size` starting at the origin. `vectors` holds one per cell, row by row,
+ * and is meant for the middle of the cell; in between they are blended.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowField {
+    pub cell_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub vectors: Vec<V2f32>,
+}
+
+impl FlowField {
+    /*
+     * Unit vectors turning with Perlin noise over the grid, `frequency` is in
+     * noise cycles per cell. The same seed gives the same field.
+     */
+    pub fn from_perlin(
+        seed: u64,
+        cell_size: f32,
+        columns: usize,
+        rows: usize,
+        frequency: f32,
+    ) -> Self {
+        let noise = Perlin::new(seed);
+        let vectors = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let angle = noise.get(column as f32 * frequency, row as f32 * frequency) * TAU;
+                Vector2::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        Self {
+            cell_size,
+            columns,
+            rows,
+            vectors,
+        }
+    }
+
+    /* A `.toml`, `.ron` or `.json` file holding the fields of `FlowField`. */
+    pub fn from_file(path: &Path) -> Result<Self, FlowFieldError> {
+        let content = fs::read_to_string(path).map_err(|e| FlowFieldError::Io(e.to_string()))?;
+        let parse = |e: &dyn fmt::Display| FlowFieldError::Parse(e.to_string());
+        let field: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| parse(&e))?,
+            Some("ron") => ron::from_str(&content).map_err(|e| parse(&e))?,
+            Some("json") => serde_json::from_str(&content).map_err(|e| parse(&e))?,
+            _ => return Err(FlowFieldError::UnknownFormat(path.display().to_string())),
+        };
+        field.validate().map_err(FlowFieldError::Invalid)?;
+        Ok(field)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.cell_size.is_finite() && self.cell_size > 0.0) {
+            return Err(format!("cell_size {} must be positive", self.cell_size));
+        }
+        if self.columns == 0 || self.rows == 0 {
+            return Err("needs at least one cell".to_string());
+        }
+        if self.vectors.len() != self.columns * self.rows {
+            return Err(format!(
+                "{} vectors for {} by {} cells",
+                self.vectors.len(),
+                self.columns,
+                self.rows
+            ));
+        }
+        if !self
+            .vectors
+            .iter()
+            .all(|v| v.x.is_finite() && v.y.is_finite())
+        {
+            return Err("vectors must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    fn vector(&self, column: usize, row: usize) -> V2f32 {
+        self.vectors[row * self.columns + column]
+    }
+
+    /* The blended vector at `p`, None outside the grid. */
+    pub fn sample(&self, p: V2f32) -> Option<V2f32> {
+        let (x, y) = (p.x / self.cell_size, p.y / self.cell_size);
+        let inside =
+            (0.0..self.columns as f32).contains(&x) && (0.0..self.rows as f32).contains(&y);
+        if !inside {
+            return None;
+        }
+        /* Cell middles sit at .5, past the outer ones the edge vector holds. */
+        let (x, y) = (
+            (x - 0.5).clamp(0.0, (self.columns - 1) as f32),
+            (y - 0.5).clamp(0.0, (self.rows - 1) as f32),
+        );
+        let (column, row) = (x as usize, y as usize);
+        let (next_column, next_row) = (
+            (column + 1).min(self.columns - 1),
+            (row + 1).min(self.rows - 1),
+        );
+        let (u, v) = (x - column as f32, y - row as f32);
+        let top = self.vector(column, row) * (1.0 - u) + self.vector(next_column, row) * u;
+        let bottom =
+            self.vector(column, next_row) * (1.0 - u) + self.vector(next_column, next_row) * u;
+        Some(top * (1.0 - v) + bottom * v)
+    }
+}
+
+#[test]
+fn sample_blends_between_cells() {
+    let field = FlowField {
+        cell_size: 10.0,
+        columns: 2,
+        rows: 1,
+        vectors: vec![Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)],
+    };
+    assert!(field.validate().is_ok());
+    assert_eq!(
+        field.sample(Vector2::new(5.0, 5.0)),
+        Some(Vector2::new(1.0, 0.0))
+    );
+    assert_eq!(
+        field.sample(Vector2::new(10.0, 2.0)),
+        Some(Vector2::new(0.5, 0.5))
+    );
+    assert_eq!(
+        field.sample(Vector2::new(1.0, 9.0)),
+        Some(Vector2::new(1.0, 0.0))
+    );
+    assert_eq!(
+        field.sample(Vector2::new(19.0, 0.0)),
+        Some(Vector2::new(0.0, 1.0))
+    );
+    assert_eq!(field.sample(Vector2::new(20.0, 5.0)), None);
+    assert_eq!(field.sample(Vector2::new(5.0, -1.0)), None);
+
+    let short = FlowField {
+        vectors: vec![Vector2::new(1.0, 0.0)],
+        ..field
+    };
+    assert!(short.validate().is_err());
+}
+
+#[test]
+fn perlin_fields_are_reproducible_unit_vectors() {
+    let field = FlowField::from_perlin(3, 20.0, 8, 6, 0.15);
+    assert!(field.validate().is_ok());
+    assert_eq!(field, FlowField::from_perlin(3, 20.0, 8, 6, 0.15));
+    assert_ne!(field, FlowField::from_perlin(4, 20.0, 8, 6, 0.15));
+    for v in &field.vectors {
+        assert!((v.x.hypot(v.y) - 1.0).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn flow_fields_load_from_files() {
+    let field = FlowField::from_perlin(5, 10.0, 3, 2, 0.3);
+    let dir = std::env::temp_dir();
+    let json = dir.join(format!("flow-field-{}.json", std::process::id()));
+    let ron = dir.join(format!("flow-field-{}.ron", std::process::id()));
+    let txt = dir.join(format!("flow-field-{}.txt", std::process::id()));
+    fs::write(&json, serde_json::to_string(&field).unwrap()).unwrap();
+    fs::write(
+        &ron,
+        "(cell_size: 5.0, columns: 1, rows: 1, vectors: [(x: 1.0, y: 0.0)])",
+    )
+    .unwrap();
+    fs::write(&txt, "").unwrap();
+    let from_json = FlowField::from_file(&json);
+    let from_ron = FlowField::from_file(&ron);
+    let from_txt = FlowField::from_file(&txt);
+    let _ = fs::remove_file(json);
+    let _ = fs::remove_file(ron);
+    let _ = fs::remove_file(txt);
+
+    assert_eq!(from_json.unwrap(), field);
+    assert_eq!(from_ron.unwrap().vectors, vec![Vector2::new(1.0, 0.0)]);
+    assert!(matches!(from_txt, Err(FlowFieldError::UnknownFormat(_))));
+    assert!(matches!(
+        FlowField::from_file(Path::new("/nonexistent/field.toml")),
+        Err(FlowFieldError::Io(_))
+    ));
+}
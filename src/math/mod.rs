@@ -1,2 +1,6 @@
+pub mod flow_field;
+pub mod noise;
+pub mod path;
 pub mod quadtree;
+pub mod spatial;
 pub mod vec;
@@ -0,0 +1,108 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/* splitmix64 finaliser, a cheap well mixed hash for reproducible randomness. */
+pub fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/* Uniform in [0, 1) from a hash. */
+pub fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/*
+ * 2D gradient noise (Ken Perlin's improved noise). Smooth, 0 on every integer
+ * lattice point and roughly within -1..=1. The same seed gives the same noise.
+ */
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+        let mut permutation = [0; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = table[i % 256];
+        }
+        Self { permutation }
+    }
+
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        if !(x.is_finite() && y.is_finite()) {
+            return 0.0;
+        }
+        let (xf, yf) = (x.floor(), y.floor());
+        let (xi, yi) = ((xf as i64 & 255) as usize, (yf as i64 & 255) as usize);
+        let (x, y) = (x - xf, y - yf);
+        let p = &self.permutation;
+        let corner = |i: usize, j: usize| p[p[xi + i] as usize + yi + j];
+        let (u, v) = (fade(x), fade(y));
+        let bottom = lerp(
+            u,
+            gradient(corner(0, 0), x, y),
+            gradient(corner(1, 0), x - 1.0, y),
+        );
+        let top = lerp(
+            u,
+            gradient(corner(0, 1), x, y - 1.0),
+            gradient(corner(1, 1), x - 1.0, y - 1.0),
+        );
+        lerp(v, bottom, top)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/* Dot product with one of 8 gradient directions. */
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+#[test]
+fn perlin_is_smooth_and_reproducible() {
+    let noise = Perlin::new(7);
+    let other = Perlin::new(8);
+    let mut differs = false;
+    for i in 0..200 {
+        let (x, y) = (i as f32 * 0.37 - 20.0, i as f32 * 0.11 + 3.0);
+        let value = noise.get(x, y);
+        assert!((-1.0..=1.0).contains(&value), "{} {} {}", x, y, value);
+        assert_eq!(value, Perlin::new(7).get(x, y));
+        assert!((noise.get(x + 0.001, y) - value).abs() < 0.01);
+        differs |= other.get(x, y) != value;
+    }
+    assert!(differs);
+    assert_eq!(noise.get(3.0, -4.0), 0.0);
+    assert_eq!(noise.get(f32::NAN, 1.0), 0.0);
+}
+
+#[test]
+fn unit_is_in_range() {
+    for i in 0..1000 {
+        let u = unit(mix(i));
+        assert!((0.0..1.0).contains(&u));
+    }
+    assert_eq!(unit(u64::MAX), 1.0 - 1.0 / (1u64 << 24) as f32);
+}
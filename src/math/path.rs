@@ -0,0 +1,163 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::vec::{Distance, DotProduct, Magnitude, V2f32, Vector2};
+
+/*
+ * A line through `points`, closed into a loop when `closed`. Places on it are
+ * given as the distance travelled along it from the first point.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Polyline {
+    pub points: Vec<V2f32>,
+    pub closed: bool,
+}
+
+impl Polyline {
+    pub fn new(points: Vec<V2f32>, closed: bool) -> Result<Self, String> {
+        let line = Self { points, closed };
+        line.validate()?;
+        Ok(line)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.len() < 2 {
+            return Err("a path needs at least 2 points".to_string());
+        }
+        if !self
+            .points
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite())
+        {
+            return Err("path points must be finite".to_string());
+        }
+        if self.length() <= 0.0 {
+            return Err("path has no length".to_string());
+        }
+        Ok(())
+    }
+
+    /* Start and end of every segment, the closing one included. */
+    pub fn segments(&self) -> impl Iterator<Item = (V2f32, V2f32)> + '_ {
+        let closing = self
+            .closed
+            .then(|| (self.points[self.points.len() - 1], self.points[0]));
+        self.points.windows(2).map(|w| (w[0], w[1])).chain(closing)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.segments().map(|(a, b)| V2f32::distance(a, b)).sum()
+    }
+
+    /* Point on the line closest to `p` and the distance along the line to it. */
+    pub fn closest(&self, p: V2f32) -> (V2f32, f32) {
+        let mut best = (self.points[0], 0.0, f32::INFINITY);
+        let mut start = 0.0;
+        for (a, b) in self.segments() {
+            let edge = b - a;
+            let length = edge.calc_magnitude();
+            let t = if length > 0.0 {
+                ((p - a).dot(edge) / (length * length)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let point = a + edge * t;
+            let distance_squared = (p - point).dot_self();
+            if distance_squared < best.2 {
+                best = (point, start + length * t, distance_squared);
+            }
+            start += length;
+        }
+        (best.0, best.1)
+    }
+
+    /*
+     * Point `along` the line and the unit direction of the segment it is on.
+     * Loops wrap around, open lines stop at their ends.
+     */
+    pub fn at(&self, along: f32) -> (V2f32, V2f32) {
+        let total = self.length();
+        let mut along = if self.closed {
+            along.rem_euclid(total)
+        } else {
+            along.clamp(0.0, total)
+        };
+        let mut last = (self.points[0], Vector2::new(1.0, 0.0));
+        for (a, b) in self.segments() {
+            let length = V2f32::distance(a, b);
+            if length <= 0.0 {
+                continue;
+            }
+            let direction = (b - a) / length;
+            if along <= length {
+                return (a + direction * along, direction);
+            }
+            along -= length;
+            last = (b, direction);
+        }
+        last
+    }
+}
+
+/* Points as `x,y` pairs split by spaces, like `100,100 300,100 300,400`. */
+impl FromStr for Polyline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .split_whitespace()
+            .map(|pair| {
+                let parsed = pair
+                    .split_once(',')
+                    .map(|(x, y)| (x.parse::<f32>(), y.parse::<f32>()));
+                match parsed {
+                    Some((Ok(x), Ok(y))) => Ok(Vector2::new(x, y)),
+                    _ => Err(format!("{} is not a point like 10,20", pair)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(points, false)
+    }
+}
+
+impl fmt::Display for Polyline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, p) in self.points.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{}{},{}", separator, p.x, p.y)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_and_walk_a_polyline() {
+    let mut line: Polyline = "0,0 10,0 10,10".parse().unwrap();
+    assert_eq!(line.to_string().parse::<Polyline>(), Ok(line.clone()));
+    assert_eq!(line.length(), 20.0);
+    assert_eq!(
+        line.at(15.0),
+        (Vector2::new(10.0, 5.0), Vector2::new(0.0, 1.0))
+    );
+    assert_eq!(line.at(25.0).0, Vector2::new(10.0, 10.0));
+    assert_eq!(
+        line.closest(Vector2::new(4.0, -3.0)),
+        (Vector2::new(4.0, 0.0), 4.0)
+    );
+    assert_eq!(
+        line.closest(Vector2::new(14.0, 6.0)),
+        (Vector2::new(10.0, 6.0), 16.0)
+    );
+
+    line.closed = true;
+    assert!((line.length() - (20.0 + 200f32.sqrt())).abs() < 1e-4);
+    assert_eq!(line.at(line.length() + 3.0).0, Vector2::new(3.0, 0.0));
+    let (point, along) = line.closest(Vector2::new(2.0, 6.0));
+    assert!((point.x - 4.0).abs() < 1e-4 && (point.y - 4.0).abs() < 1e-4);
+    assert!(along > 20.0);
+
+    assert!("0,0".parse::<Polyline>().is_err());
+    assert!("0,0 1;1".parse::<Polyline>().is_err());
+    assert!("5,5 5,5".parse::<Polyline>().is_err());
+}
@@ -1,140 +1,661 @@
-use std::mem;
+use std::{cmp::Ordering, collections::BinaryHeap, iter, mem};
 
-use crate::camera::Camera;
-use crate::constants::{BOID_SIZE, MAX_BOID_IN_AREA, QUAD_TREE_COLOR};
+use crate::constants::{MAX_BOID_IN_AREA, MAX_QUAD_TREE_DEPTH};
 use crate::logic::boid::boid_impl::Boid;
-
-use crate::graphics::renderer::Renderable;
-use crate::math::vec::Vector2;
+use crate::math::{
+    spatial::{distance_squared, SpatialIndex},
+    vec::V2f32,
+};
 
 use super::region::Region;
 use super::traits::{Intersect, SubInto};
 
-use sdl2::rect::Rect;
+#[cfg(test)]
+use crate::{constants::BOID_SIZE, math::vec::Vector2};
+#[cfg(feature = "render")]
+use {
+    crate::{
+        camera::Camera,
+        constants::{DrawPrimitives, QUAD_TREE_COLOR},
+        graphics::renderer::Renderable,
+    },
+    sdl2::rect::Rect,
+};
+
+#[cfg(feature = "render")]
 macro_rules! rect(
     ($x:expr, $y:expr, $w:expr, $h:expr) => (
         Rect::new($x as i32, $y as i32, $w as u32, $h as u32)
     )
 );
-#[derive(Debug)]
-pub enum QuadTree {
-    Leaf { boundary: Region, boids: Vec<Boid> },
-    Root { neighbours: [Box<QuadTree>; 4] },
+
+const NONE: u32 = u32::MAX;
+/* `Element::node` of the boids in the overflow list. */
+const OVERFLOW: u32 = u32::MAX - 1;
+
+#[derive(Clone, Debug)]
+struct Node {
+    boundary: Region,
+    /* NONE for the root. */
+    parent: u32,
+    /* First of four consecutive children in `nodes`, NONE for leaves. */
+    children: u32,
+    /* Head of this node's list in `elements`, NONE when empty. */
+    first: u32,
+    count: u32,
+}
+impl Node {
+    fn leaf(boundary: Region, parent: u32) -> Self {
+        Self {
+            boundary,
+            parent,
+            children: NONE,
+            first: NONE,
+            count: 0,
+        }
+    }
+    fn children(&self) -> std::ops::Range<usize> {
+        match self.children {
+            NONE => 0..0,
+            first => first as usize..first as usize + 4,
+        }
+    }
 }
-impl Renderable for QuadTree {
-    fn render(&mut self, canvas: &mut sdl2::render::WindowCanvas, camera: &Camera) {
-        canvas.set_draw_color(QUAD_TREE_COLOR);
 
+#[derive(Clone, Copy, Debug)]
+struct Element {
+    index: u32,
+    next: u32,
+    /* Node whose list this element is in, or OVERFLOW. */
+    node: u32,
+    /* Where the tree last saw the boid, so nodes can be split without the boid slice. */
+    position: V2f32,
+}
+
+/*
+ * Flat quad tree over indices into a boid slice, usually BoidManager::boids.
+ * Nodes and elements live in two arenas: the children of a node are four
+ * consecutive nodes and the boids of a node are a linked list through `elements`.
+ * `clear` keeps the capacity of both, so rebuilding every tick doesn't allocate.
+ * Queries have to be given the same slice the tree was built from.
+ *
+ * Insertion never fails: boids outside the boundary (Reflect lets them leave the
+ * view port, a NaN position is outside everything) go to `overflow`, which every
+ * query scans linearly. Leaves at MAX_QUAD_TREE_DEPTH are never split, so
+ * coincident boids just make that leaf bigger.
+ *
+ * Instead of rebuilding, moved boids can be passed to `update_position` and gone
+ * ones to `remove`. When the children of a node together hold no more than one
+ * leaf may, they are merged back into it and reused by the next split.
+ */
+#[derive(Clone, Debug)]
+pub struct QuadTree {
+    nodes: Vec<Node>,
+    /* First child of every block of four nodes given back by a merge. */
+    free_nodes: Vec<u32>,
+    elements: Vec<Element>,
+    free_elements: Vec<u32>,
+    /* Element of every boid index, NONE for indices not in the tree. */
+    slots: Vec<u32>,
+    /* List of the boids outside of the boundary, its own boundary is unused. */
+    overflow: Node,
+    leaf_capacity: u32,
+}
+
+/* Entry of the k_nearest queue, ordered so that BinaryHeap pops the closest first. */
+enum Candidate {
+    Node(f32, usize),
+    Boid(f32, u32),
+}
+impl Candidate {
+    fn distance_squared(&self) -> f32 {
         match self {
-            QuadTree::Leaf { boundary, boids: _ } => {
-                let _ = canvas.draw_rect(rect!(
-                    boundary.left_up.x - camera.pos.x,
-                    boundary.left_up.y - camera.pos.y,
-                    boundary.width_height.x,
-                    boundary.width_height.y
-                ));
-            }
-            QuadTree::Root { neighbours } => {
-                for n in neighbours {
-                    n.render(canvas, camera);
-                }
+            Candidate::Node(d, _) => *d,
+            Candidate::Boid(d, _) => *d,
+        }
+    }
+}
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance_squared().total_cmp(&self.distance_squared())
+    }
+}
+
+#[cfg(feature = "render")]
+impl Renderable for QuadTree {
+    fn render(
+        &mut self,
+        canvas: &mut sdl2::render::WindowCanvas,
+        camera: &Camera,
+        _primitives: DrawPrimitives,
+    ) {
+        canvas.set_draw_color(QUAD_TREE_COLOR);
+        /* Walked from the root, freed nodes are still in the arena. */
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.children != NONE {
+                stack.extend(node.children());
+                continue;
             }
+            let boundary = &node.boundary;
+            let _ = canvas.draw_rect(rect!(
+                boundary.left_up.x - camera.pos.x,
+                boundary.left_up.y - camera.pos.y,
+                boundary.width_height.x,
+                boundary.width_height.y
+            ));
         }
     }
 }
 impl QuadTree {
     pub fn new(boundary: Region) -> Self {
-        QuadTree::Leaf {
-            boundary,
-            boids: Vec::with_capacity(MAX_BOID_IN_AREA),
+        Self::with_leaf_capacity(boundary, MAX_BOID_IN_AREA)
+    }
+
+    pub fn with_leaf_capacity(boundary: Region, leaf_capacity: usize) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+            elements: Vec::new(),
+            free_elements: Vec::new(),
+            slots: Vec::new(),
+            overflow: Node::leaf(Region::default(), NONE),
+            leaf_capacity: leaf_capacity.max(1) as u32,
+        };
+        tree.clear(boundary);
+        tree
+    }
+
+    /* Empties the tree but keeps its memory for the next build. */
+    pub fn clear(&mut self, boundary: Region) {
+        self.nodes.clear();
+        self.free_nodes.clear();
+        self.elements.clear();
+        self.free_elements.clear();
+        self.slots.clear();
+        self.overflow = Node::leaf(Region::default(), NONE);
+        self.nodes.push(Node::leaf(boundary, NONE));
+    }
+
+    pub fn rebuild(&mut self, boundary: Region, boids: &[Boid]) {
+        self.clear(boundary);
+        for index in 0..boids.len() as u32 {
+            self.insert(boids, index);
         }
     }
 
+    pub fn boundary(&self) -> &Region {
+        &self.nodes[0].boundary
+    }
+
     pub fn count(&self) -> usize {
-        match self {
-            QuadTree::Leaf { boundary: _, boids } => return boids.len(),
-            QuadTree::Root { neighbours } => neighbours.into_iter().map(|n| n.count()).sum(),
-        }
+        self.elements.len() - self.free_elements.len()
+    }
+
+    /* Nodes in use, merged nodes waiting to be reused don't count. */
+    pub fn node_count(&self) -> usize {
+        self.nodes.len() - 4 * self.free_nodes.len()
+    }
+
+    /* Boids that are outside of `boundary`. */
+    pub fn overflow(&self) -> impl Iterator<Item = u32> + '_ {
+        self.node_elements(OVERFLOW)
     }
 
-    pub fn insert(&mut self, boid: Boid) -> Result<(), &'static str> {
+    /* Inserting an index that is already in the tree moves it. */
+    pub fn insert(&mut self, boids: &[Boid], index: u32) {
+        let boid = &boids[index as usize];
         log::debug!("Insert {:?}", boid);
-        match self {
-            QuadTree::Leaf { boundary, boids } => {
-                log::info!("its leaf");
-                if !boundary.contains_boid(&boid) {
-                    return Err("Boundary doesn't contain boid");
-                } else if boids.len() == MAX_BOID_IN_AREA {
-                    log::debug!("to much boids in area. divide");
-                    self.subdivide();
-                    return self.insert(boid);
-                } else {
-                    log::info!("its ok. Adding boid!");
-                    boids.push(boid);
-                    return Ok(());
-                }
+        self.remove(index);
+        let element = Element {
+            index,
+            next: NONE,
+            node: NONE,
+            position: boid.position,
+        };
+        let element = match self.free_elements.pop() {
+            Some(free) => {
+                self.elements[free as usize] = element;
+                free
             }
-            QuadTree::Root { neighbours } => {
-                log::info!("its root!");
-
-                log::info!("loop over neighbours start");
-                for n in neighbours {
-                    log::info!("loop over neighbours iterate");
-                    let ok = n.insert(boid);
-                    if ok.is_ok() {
-                        return ok;
-                    }
-                }
-                log::info!("loop over neighbours end");
-
-                return Err("Boid couldn't be inserted in any sub-tree ");
+            None => {
+                self.elements.push(element);
+                self.elements.len() as u32 - 1
             }
+        };
+        if self.slots.len() <= index as usize {
+            self.slots.resize(index as usize + 1, NONE);
         }
+        self.slots[index as usize] = element;
+        self.place(element);
     }
 
-    fn subdivide(&mut self) {
-        log::debug!("divide area");
-        match self {
-            QuadTree::Leaf { boundary, boids } => {
-                let b = Region::sub_into(&boundary);
-
-                let nei: [Box<QuadTree>; 4] = b
-                    .into_iter()
-                    .map(|r| Box::new(QuadTree::new(r.clone())))
-                    .collect::<Vec<Box<QuadTree>>>()
-                    .try_into()
-                    .unwrap();
-
-                let mut new = QuadTree::Root { neighbours: nei };
-                for p in boids {
-                    let _ = new.insert(*p);
+    /*
+     * Moves boid `index` from `old` to `new`, `old` being the position the tree was
+     * last given for it. Boids that didn't move are skipped without a lookup and the
+     * tree only changes when a boid leaves its leaf. False for indices not in the tree.
+     */
+    pub fn update_position(&mut self, index: u32, old: V2f32, new: V2f32) -> bool {
+        let element = match self.slots.get(index as usize) {
+            Some(&element) if element != NONE => element,
+            _ => return false,
+        };
+        if old.x.to_bits() == new.x.to_bits() && old.y.to_bits() == new.y.to_bits() {
+            return true;
+        }
+        let stored = &mut self.elements[element as usize];
+        stored.position = new;
+        let node = stored.node;
+        let stays = if node == OVERFLOW {
+            !self.nodes[0].boundary.contains_point(new)
+        } else {
+            let node = &self.nodes[node as usize];
+            node.children == NONE && node.boundary.contains_point(new)
+        };
+        if !stays {
+            self.unlink(element);
+            self.merge_from(node);
+            self.place(element);
+        }
+        true
+    }
+
+    /* Takes boid `index` out of the tree. Returns false if it wasn't in it. */
+    pub fn remove(&mut self, index: u32) -> bool {
+        let element = match self.slots.get(index as usize) {
+            Some(&element) if element != NONE => element,
+            _ => return false,
+        };
+        let node = self.elements[element as usize].node;
+        self.unlink(element);
+        self.merge_from(node);
+        self.slots[index as usize] = NONE;
+        self.free_elements.push(element);
+        true
+    }
+
+    /* Links `element` into the overflow or the leaf its position falls into. */
+    fn place(&mut self, element: u32) {
+        let position = self.elements[element as usize].position;
+        if !self.nodes[0].boundary.contains_point(position) {
+            log::debug!("{} is outside of the quad tree, overflow", position);
+            self.link(OVERFLOW, element);
+            return;
+        }
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            if self.nodes[node].children != NONE {
+                /* Half-open regions, so exactly one child contains the boid. */
+                match self.child_containing(node, position) {
+                    Some(child) => node = child,
+                    None => break,
                 }
-                let _ = mem::replace(self, new);
+                depth += 1;
+            } else if self.nodes[node].count < self.leaf_capacity || depth >= MAX_QUAD_TREE_DEPTH {
+                break;
+            } else {
+                log::debug!("to much boids in area. divide");
+                self.subdivide(node);
             }
-            _ => {}
         }
+        self.link(node as u32, element);
     }
-    pub fn get_all_boids_in_boundry(&self, query_boundry: &Region, found_boids: &mut Vec<Boid>) {
-        match self {
-            QuadTree::Leaf { boundary, boids } => {
-                if !query_boundry.intersect_with(boundary) {
-                    return;
+
+    fn child_containing(&self, node: usize, position: V2f32) -> Option<usize> {
+        self.nodes[node]
+            .children()
+            .find(|&child| self.nodes[child].boundary.contains_point(position))
+    }
+
+    fn list(&self, node: u32) -> &Node {
+        match node {
+            OVERFLOW => &self.overflow,
+            node => &self.nodes[node as usize],
+        }
+    }
+
+    fn list_mut(&mut self, node: u32) -> &mut Node {
+        match node {
+            OVERFLOW => &mut self.overflow,
+            node => &mut self.nodes[node as usize],
+        }
+    }
+
+    fn link(&mut self, node: u32, element: u32) {
+        let list = self.list_mut(node);
+        let next = mem::replace(&mut list.first, element);
+        list.count += 1;
+        let element = &mut self.elements[element as usize];
+        element.next = next;
+        element.node = node;
+    }
+
+    /* Lists hold at most a leaf worth of boids, except the overflow and the deepest leaves. */
+    fn unlink(&mut self, element: u32) {
+        let Element { node, next, .. } = self.elements[element as usize];
+        let list = self.list_mut(node);
+        list.count -= 1;
+        if list.first == element {
+            list.first = next;
+            return;
+        }
+        let mut previous = list.first;
+        while self.elements[previous as usize].next != element {
+            previous = self.elements[previous as usize].next;
+        }
+        self.elements[previous as usize].next = next;
+    }
+
+    /* Turns a leaf into an inner node and moves its boids down, no element is copied. */
+    fn subdivide(&mut self, node: usize) {
+        let regions = Region::sub_into(&self.nodes[node].boundary);
+        let first_child = match self.free_nodes.pop() {
+            Some(first) => {
+                for (i, region) in regions.into_iter().enumerate() {
+                    self.nodes[first as usize + i] = Node::leaf(region, node as u32);
                 }
-                for b in boids {
-                    if query_boundry.contains_boid(b) {
-                        found_boids.push(*b);
-                    }
+                first
+            }
+            None => {
+                let first = self.nodes.len() as u32;
+                for region in regions {
+                    self.nodes.push(Node::leaf(region, node as u32));
+                }
+                first
+            }
+        };
+        self.nodes[node].children = first_child;
+        self.nodes[node].count = 0;
+        let mut element = mem::replace(&mut self.nodes[node].first, NONE);
+        while element != NONE {
+            let Element { next, position, .. } = self.elements[element as usize];
+            let target = self.child_containing(node, position).unwrap_or(node);
+            self.link(target as u32, element);
+            element = next;
+        }
+    }
+
+    /*
+     * Walks up from `node` and merges every parent whose children are all leaves
+     * that together fit into one leaf. The freed block of four goes to `free_nodes`.
+     */
+    fn merge_from(&mut self, node: u32) {
+        let mut parent = match node {
+            OVERFLOW => NONE,
+            node => self.nodes[node as usize].parent,
+        };
+        while parent != NONE {
+            let children = self.nodes[parent as usize].children();
+            if children
+                .clone()
+                .any(|child| self.nodes[child].children != NONE)
+            {
+                break;
+            }
+            let count = self.nodes[parent as usize].count
+                + children
+                    .clone()
+                    .map(|child| self.nodes[child].count)
+                    .sum::<u32>();
+            if count > self.leaf_capacity {
+                break;
+            }
+            for child in children {
+                self.nodes[child].count = 0;
+                let mut element = mem::replace(&mut self.nodes[child].first, NONE);
+                while element != NONE {
+                    let next = self.elements[element as usize].next;
+                    self.link(parent, element);
+                    element = next;
                 }
             }
+            let node = &mut self.nodes[parent as usize];
+            self.free_nodes.push(mem::replace(&mut node.children, NONE));
+            parent = node.parent;
+        }
+    }
+
+    fn node_elements(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        let first = self.list(node).first;
+        iter::successors((first != NONE).then_some(first), |&element| {
+            let next = self.elements[element as usize].next;
+            (next != NONE).then_some(next)
+        })
+        .map(|element| self.elements[element as usize].index)
+    }
+
+    /* Boids inside `query_boundry`, including the ones on its edges. */
+    pub fn get_all_boids_in_boundry(
+        &self,
+        boids: &[Boid],
+        query_boundry: &Region,
+        found_boids: &mut Vec<u32>,
+    ) {
+        found_boids.extend(
+            self.overflow()
+                .filter(|&i| query_boundry.covers_point(boids[i as usize].position)),
+        );
+        self.region_query(0, boids, query_boundry, found_boids);
+    }
+
+    fn region_query(
+        &self,
+        node: usize,
+        boids: &[Boid],
+        query_boundry: &Region,
+        found_boids: &mut Vec<u32>,
+    ) {
+        if !query_boundry.intersect_with(&self.nodes[node].boundary) {
+            return;
+        }
+        found_boids.extend(
+            self.node_elements(node as u32)
+                .filter(|&i| query_boundry.covers_point(boids[i as usize].position)),
+        );
+        for child in self.nodes[node].children() {
+            self.region_query(child, boids, query_boundry, found_boids);
+        }
+    }
 
-            QuadTree::Root { neighbours } => {
-                for n in neighbours {
-                    n.get_all_boids_in_boundry(query_boundry, found_boids);
+    /* All boids whose position is at most `radius` away from `center`. */
+    pub fn query_radius(
+        &self,
+        boids: &[Boid],
+        center: V2f32,
+        radius: f32,
+        found_boids: &mut Vec<u32>,
+    ) {
+        let radius_squared = radius * radius;
+        found_boids.extend(
+            self.overflow().filter(|&i| {
+                distance_squared(boids[i as usize].position, center) <= radius_squared
+            }),
+        );
+        self.radius_query(0, boids, center, radius_squared, found_boids);
+    }
+
+    fn radius_query(
+        &self,
+        node: usize,
+        boids: &[Boid],
+        center: V2f32,
+        radius_squared: f32,
+        found_boids: &mut Vec<u32>,
+    ) {
+        if self.nodes[node].boundary.distance_squared_to(center) > radius_squared {
+            return;
+        }
+        found_boids.extend(
+            self.node_elements(node as u32).filter(|&i| {
+                distance_squared(boids[i as usize].position, center) <= radius_squared
+            }),
+        );
+        for child in self.nodes[node].children() {
+            self.radius_query(child, boids, center, radius_squared, found_boids);
+        }
+    }
+
+    /*
+     * The `k` boids closest to `center`, closest first. Best-first search: nodes and
+     * boids share one queue keyed by distance, so a node is only opened when it could
+     * still hold something closer than every boid already returned.
+     */
+    pub fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32> {
+        let mut nearest = Vec::with_capacity(k);
+        if k == 0 {
+            return nearest;
+        }
+        let mut queue = BinaryHeap::new();
+        for i in self.overflow() {
+            let d = distance_squared(boids[i as usize].position, center);
+            queue.push(Candidate::Boid(d, i));
+        }
+        queue.push(Candidate::Node(
+            self.nodes[0].boundary.distance_squared_to(center),
+            0,
+        ));
+        while let Some(candidate) = queue.pop() {
+            match candidate {
+                Candidate::Boid(_, index) => {
+                    nearest.push(index);
+                    if nearest.len() == k {
+                        break;
+                    }
+                }
+                Candidate::Node(_, node) => {
+                    for i in self.node_elements(node as u32) {
+                        let d = distance_squared(boids[i as usize].position, center);
+                        queue.push(Candidate::Boid(d, i));
+                    }
+                    for child in self.nodes[node].children() {
+                        let d = self.nodes[child].boundary.distance_squared_to(center);
+                        queue.push(Candidate::Node(d, child));
+                    }
                 }
             }
         }
+        nearest
+    }
+}
+
+impl SpatialIndex for QuadTree {
+    fn build(&mut self, boundary: Region, boids: &[Boid]) {
+        self.rebuild(boundary, boids);
+    }
+    fn len(&self) -> usize {
+        self.count()
+    }
+    fn query_radius(&self, boids: &[Boid], center: V2f32, radius: f32, found: &mut Vec<u32>) {
+        QuadTree::query_radius(self, boids, center, radius, found);
+    }
+    fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32> {
+        QuadTree::k_nearest(self, boids, center, k)
+    }
+    fn query_rect(&self, boids: &[Boid], region: &Region, found: &mut Vec<u32>) {
+        self.get_all_boids_in_boundry(boids, region, found);
+    }
+    fn update_position(&mut self, index: u32, old: V2f32, new: V2f32) -> bool {
+        QuadTree::update_position(self, index, old, new)
+    }
+    #[cfg(feature = "render")]
+    fn render_debug(
+        &mut self,
+        canvas: &mut sdl2::render::WindowCanvas,
+        camera: &Camera,
+        _: &[Boid],
+    ) {
+        self.render(canvas, camera, DrawPrimitives::QUAD_TREE);
+    }
+}
+
+#[cfg(test)]
+fn random_tree(amount: usize, seed: u64) -> (QuadTree, Vec<Boid>) {
+    let boids = crate::math::spatial::random_flock(
+        amount,
+        Vector2::new(1.0, 1.0),
+        Vector2::new(799.0, 599.0),
+        seed,
+    );
+    let mut q = QuadTree::new(Region::new(Vector2::zero(), Vector2::new(800.0, 600.0)));
+    q.rebuild(q.boundary().clone(), &boids);
+    (q, boids)
+}
+
+#[test]
+fn query_radius_matches_brute_force() {
+    use rand::{Rng, SeedableRng};
+
+    let (q, boids) = random_tree(500, 11);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(12);
+    for _ in 0..50 {
+        let center = Vector2::new(rng.gen_range(-50.0..850.0), rng.gen_range(-50.0..650.0));
+        let radius = rng.gen_range(0.0..200.0);
+        let mut found = vec![];
+        q.query_radius(&boids, center, radius, &mut found);
+        found.sort();
+        let expected: Vec<u32> = boids
+            .iter()
+            .filter(|b| distance_squared(b.position, center) <= radius * radius)
+            .map(|b| b.id as u32)
+            .collect();
+        assert_eq!(found, expected, "center {} radius {}", center, radius);
+    }
+}
+
+#[test]
+fn k_nearest_matches_brute_force() {
+    use rand::{Rng, SeedableRng};
+
+    let (q, boids) = random_tree(500, 21);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(22);
+    for k in [0, 1, 7, 30, 499, 500, 600] {
+        let center = Vector2::new(rng.gen_range(-50.0..850.0), rng.gen_range(-50.0..650.0));
+        let found: Vec<f32> = q
+            .k_nearest(&boids, center, k)
+            .iter()
+            .map(|&i| distance_squared(boids[i as usize].position, center))
+            .collect();
+        let mut expected: Vec<f32> = boids
+            .iter()
+            .map(|b| distance_squared(b.position, center))
+            .collect();
+        expected.sort_by(f32::total_cmp);
+        expected.truncate(k);
+        assert_eq!(found, expected, "center {} k {}", center, k);
+    }
+}
+
+#[test]
+fn rebuild_reuses_storage() {
+    let (mut q, boids) = random_tree(300, 31);
+    let capacity = (q.nodes.capacity(), q.elements.capacity());
+    let nodes = q.nodes.len();
+    let (nodes_ptr, elements_ptr) = (q.nodes.as_ptr(), q.elements.as_ptr());
+    for _ in 0..3 {
+        q.rebuild(q.boundary().clone(), &boids);
     }
+    assert_eq!(q.count(), 300);
+    assert_eq!(q.nodes.len(), nodes);
+    assert_eq!((q.nodes.capacity(), q.elements.capacity()), capacity);
+    assert_eq!(
+        (q.nodes.as_ptr(), q.elements.as_ptr()),
+        (nodes_ptr, elements_ptr)
+    );
 }
+
 #[test]
 fn get_all_boids_in_boundry() {
     let r = Region::new(Vector2::new(0.0, 0.0), Vector2::new(300.0, 300.0));
@@ -142,21 +663,26 @@ fn get_all_boids_in_boundry() {
     let x = r.right_down.x / amount as f32;
     let y = r.right_down.y / amount as f32;
     let mut q = QuadTree::new(r.clone());
-
+    let boids: Vec<Boid> = (0..amount)
+        .map(|i| {
+            Boid::new(
+                i,
+                Vector2::new(
+                    i as f32 * x + BOID_SIZE as f32,
+                    i as f32 * y + BOID_SIZE as f32,
+                ),
+                Vector2::zero(),
+            )
+        })
+        .collect();
     for i in 0..amount {
-        let _ = q.insert(Boid::new(
-            Vector2::new(
-                i as f32 * x + BOID_SIZE as f32,
-                i as f32 * y + BOID_SIZE as f32,
-            ),
-            Vector2::zero(),
-        ));
+        q.insert(&boids, i as u32);
     }
 
     assert_eq!(q.count(), amount);
     {
         let mut boids_in_region = vec![];
-        q.get_all_boids_in_boundry(&r.clone(), &mut boids_in_region);
+        q.get_all_boids_in_boundry(&boids, &r.clone(), &mut boids_in_region);
         assert_eq!(boids_in_region.len(), amount);
     }
     {
@@ -169,7 +695,7 @@ fn get_all_boids_in_boundry() {
                 ),
             );
             let mut boids_in_region = vec![];
-            q.get_all_boids_in_boundry(&r, &mut boids_in_region);
+            q.get_all_boids_in_boundry(&boids, &r, &mut boids_in_region);
             assert_eq!(boids_in_region.len(), i + 1);
         }
     }
@@ -186,6 +712,7 @@ fn get_all_boids_in_boundry_view_of_boid() {
 
     for i in 0..3 {
         let boid = Boid::new(
+            i,
             Vector2::new(
                 i as f32 * x + BOID_SIZE as f32,
                 i as f32 * y + BOID_SIZE as f32,
@@ -193,14 +720,14 @@ fn get_all_boids_in_boundry_view_of_boid() {
             Vector2::zero(),
         );
         boids.push(boid);
-        let _ = q.insert(boid);
+        q.insert(&boids, i as u32);
     }
     {
         let distance = 1.0;
         for b in &boids {
             let r = Region::rect_from_center_with_distance(b.position, distance);
             let mut boids_in_region = vec![];
-            q.get_all_boids_in_boundry(&r, &mut boids_in_region);
+            q.get_all_boids_in_boundry(&boids, &r, &mut boids_in_region);
             assert_eq!(boids_in_region.len(), 1);
         }
     }
@@ -209,8 +736,159 @@ fn get_all_boids_in_boundry_view_of_boid() {
         for b in &boids {
             let r = Region::rect_from_center_with_distance(b.position, distance);
             let mut boids_in_region = vec![];
-            q.get_all_boids_in_boundry(&r, &mut boids_in_region);
+            q.get_all_boids_in_boundry(&boids, &r, &mut boids_in_region);
             assert_eq!(boids_in_region.len(), 1); // its failing for somre reason MenosGrandes
         }
     }
 }
+
+#[test]
+fn boids_on_edges_and_split_lines_are_kept() {
+    let r = Region::new(Vector2::new(0.0, 0.0), Vector2::new(400.0, 400.0));
+    let mut boids = vec![];
+    for x in [0.0, 100.0, 200.0, 300.0, 400.0] {
+        for y in [0.0, 50.0, 200.0, 400.0] {
+            boids.push(Boid::new(boids.len(), Vector2::new(x, y), Vector2::zero()));
+        }
+    }
+    let mut q = QuadTree::new(r.clone());
+    q.rebuild(r.clone(), &boids);
+    assert_eq!(q.count(), boids.len());
+
+    let mut found = vec![];
+    q.get_all_boids_in_boundry(&boids, &r, &mut found);
+    assert_eq!(found.len(), boids.len());
+    for b in &boids {
+        let mut found = vec![];
+        q.query_radius(&boids, b.position, 0.0, &mut found);
+        assert_eq!(found, vec![b.id as u32]);
+    }
+}
+
+#[test]
+fn out_of_range_boids_go_to_overflow() {
+    let r = Region::new(Vector2::new(0.0, 0.0), Vector2::new(100.0, 100.0));
+    let boids = vec![
+        Boid::new(0, Vector2::new(50.0, 50.0), Vector2::zero()),
+        Boid::new(1, Vector2::new(-20.0, 50.0), Vector2::zero()),
+        Boid::new(2, Vector2::new(130.0, 250.0), Vector2::zero()),
+        Boid::new(3, Vector2::new(f32::NAN, 10.0), Vector2::zero()),
+    ];
+    let mut q = QuadTree::new(r.clone());
+    q.rebuild(r, &boids);
+    assert_eq!(q.count(), 4);
+    let mut overflow: Vec<u32> = q.overflow().collect();
+    overflow.sort();
+    assert_eq!(overflow, vec![1, 2, 3]);
+
+    let mut found = vec![];
+    q.query_radius(&boids, Vector2::new(0.0, 50.0), 30.0, &mut found);
+    found.sort();
+    assert_eq!(found, vec![1]);
+    assert_eq!(
+        q.k_nearest(&boids, Vector2::new(120.0, 240.0), 2),
+        vec![2, 0]
+    );
+}
+
+#[test]
+fn coincident_boids_stop_at_max_depth() {
+    let r = Region::new(Vector2::new(0.0, 0.0), Vector2::new(100.0, 100.0));
+    let boids: Vec<Boid> = (0..1000)
+        .map(|i| Boid::new(i, Vector2::new(33.3, 66.6), Vector2::zero()))
+        .collect();
+    let mut q = QuadTree::with_leaf_capacity(r.clone(), 1);
+    q.rebuild(r, &boids);
+    assert_eq!(q.count(), 1000);
+    assert_eq!(q.nodes.len(), 1 + 4 * MAX_QUAD_TREE_DEPTH as usize);
+    let mut found = vec![];
+    q.query_radius(&boids, Vector2::new(33.3, 66.6), 1.0, &mut found);
+    assert_eq!(found.len(), 1000);
+}
+
+#[test]
+fn update_position_matches_rebuild() {
+    use rand::{Rng, SeedableRng};
+
+    let (mut q, mut boids) = random_tree(400, 41);
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+    for step in 0..30 {
+        for (i, b) in boids.iter_mut().enumerate() {
+            let old = b.position;
+            /* Mostly small steps, some jumps out of the boundary and one NaN. */
+            b.position = match rng.gen_range(0..20) {
+                0 => Vector2::new(rng.gen_range(-100.0..900.0), rng.gen_range(-100.0..700.0)),
+                1 if i == step => Vector2::new(f32::NAN, 0.0),
+                _ => old + Vector2::new(rng.gen_range(-8.0..8.0), rng.gen_range(-8.0..8.0)),
+            };
+            assert!(q.update_position(i as u32, old, b.position));
+        }
+        let mut fresh = QuadTree::new(q.boundary().clone());
+        fresh.rebuild(q.boundary().clone(), &boids);
+        assert_eq!(q.count(), fresh.count());
+
+        let center = Vector2::new(rng.gen_range(-50.0..850.0), rng.gen_range(-50.0..650.0));
+        let radius = rng.gen_range(0.0..200.0);
+        let (mut found, mut expected) = (vec![], vec![]);
+        q.query_radius(&boids, center, radius, &mut found);
+        fresh.query_radius(&boids, center, radius, &mut expected);
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected, "step {}", step);
+        let distances = |found: Vec<u32>| -> Vec<u32> {
+            found
+                .iter()
+                .map(|&i| distance_squared(boids[i as usize].position, center).to_bits())
+                .collect()
+        };
+        assert_eq!(
+            distances(q.k_nearest(&boids, center, 25)),
+            distances(fresh.k_nearest(&boids, center, 25))
+        );
+    }
+    assert!(!q.update_position(400, Vector2::zero(), Vector2::zero()));
+}
+
+#[test]
+fn removed_boids_are_gone_and_empty_branches_merge() {
+    let r = Region::new(Vector2::new(0.0, 0.0), Vector2::new(800.0, 800.0));
+    /* A tight cluster makes a tall, thin tree. */
+    let mut boids: Vec<Boid> = (0..64)
+        .map(|i| {
+            let offset = Vector2::new((i % 8) as f32, (i / 8) as f32) * 0.1;
+            Boid::new(i, Vector2::new(100.0, 100.0) + offset, Vector2::zero())
+        })
+        .collect();
+    let mut q = QuadTree::with_leaf_capacity(r.clone(), 2);
+    q.rebuild(r, &boids);
+    let tall = q.node_count();
+    assert!(tall > 40, "{}", tall);
+
+    for i in 2..64 {
+        assert!(q.remove(i));
+    }
+    assert!(!q.remove(10));
+    assert_eq!(q.count(), 2);
+    assert_eq!(q.node_count(), 1);
+    let mut found = vec![];
+    q.query_radius(&boids, Vector2::new(100.0, 100.0), 10.0, &mut found);
+    found.sort();
+    assert_eq!(found, vec![0, 1]);
+
+    /* Spreading the last two apart splits again, moving them together merges back. */
+    let arena = q.nodes.len();
+    for _ in 0..3 {
+        let old = boids[1].position;
+        boids[1].position = Vector2::new(700.0, 700.0);
+        q.update_position(1, old, boids[1].position);
+        q.insert(&boids, 2);
+        assert_eq!(q.node_count(), 5);
+        let old = boids[1].position;
+        boids[1].position = Vector2::new(101.0, 101.0);
+        q.update_position(1, old, boids[1].position);
+        q.remove(2);
+        assert_eq!(q.node_count(), 1);
+    }
+    /* Merged blocks are reused instead of growing the arena. */
+    assert_eq!(q.nodes.len(), arena);
+}
@@ -1,15 +1,22 @@
 use crate::{
-    camera::Camera,
-    constants::{REGION_COLOR, VIEW_DISTANCE},
-    graphics::renderer::Renderable,
+    config::SimulationConfig,
     logic::boid::boid_impl::Boid,
     math::quadtree::traits::SubInto,
     math::vec::{V2f32, Vector2},
 };
-use sdl2::rect::Rect;
+#[cfg(feature = "render")]
+use {
+    crate::{
+        camera::Camera,
+        constants::{DrawPrimitives, REGION_COLOR},
+        graphics::renderer::Renderable,
+    },
+    sdl2::rect::Rect,
+};
 
 use super::traits::Intersect;
 
+#[cfg(feature = "render")]
 macro_rules! rect(
     ($x:expr, $y:expr, $w:expr, $h:expr) => (
         Rect::new($x as i32, $y as i32, $w as u32, $h as u32)
@@ -36,8 +43,8 @@ impl Region {
             width_height: V2f32::new(diagonal, diagonal),
         }
     }
-    pub fn rect_from_center(center: V2f32) -> Self {
-        Region::rect_from_center_with_distance(center, VIEW_DISTANCE)
+    pub fn rect_from_center(center: V2f32, config: &SimulationConfig) -> Self {
+        Region::rect_from_center_with_distance(center, config.view_distance)
     }
     pub fn new(left_up: V2f32, right_down: V2f32) -> Self {
         let height = right_down.y - left_up.y;
@@ -55,18 +62,50 @@ impl Region {
         )
     }
     pub fn is_empty(&self) -> bool {
-        return self.width_height.x == 0.0 || self.width_height.y == 0.0;
+        self.width_height.x == 0.0 || self.width_height.y == 0.0
+    }
+
+    /* Squared distance from `point` to the closest point of the region, 0 when inside. */
+    pub fn distance_squared_to(&self, point: V2f32) -> f32 {
+        let dx = (self.left_up.x - point.x)
+            .max(point.x - self.right_down.x)
+            .max(0.0);
+        let dy = (self.left_up.y - point.y)
+            .max(point.y - self.right_down.y)
+            .max(0.0);
+        dx * dx + dy * dy
     }
 
+    /*
+     * Half-open, left and top edges belong to the region, right and bottom don't.
+     * The four regions of `sub_into` therefore contain every point of the parent exactly once.
+     */
     pub fn contains_boid(&self, boid: &Boid) -> bool {
-        boid.position.x > self.left_up.x
-            && boid.position.x < self.right_down.x
-            && boid.position.y > self.left_up.y
-            && boid.position.y < self.right_down.y
+        self.contains_point(boid.position)
+    }
+    pub fn contains_point(&self, point: V2f32) -> bool {
+        point.x >= self.left_up.x
+            && point.x < self.right_down.x
+            && point.y >= self.left_up.y
+            && point.y < self.right_down.y
+    }
+
+    /* Closed version of `contains_point`, used for queries where the edge should count. */
+    pub fn covers_point(&self, point: V2f32) -> bool {
+        point.x >= self.left_up.x
+            && point.x <= self.right_down.x
+            && point.y >= self.left_up.y
+            && point.y <= self.right_down.y
     }
 }
+#[cfg(feature = "render")]
 impl Renderable for Region {
-    fn render(&mut self, canvas: &mut sdl2::render::WindowCanvas, camera: &Camera) {
+    fn render(
+        &mut self,
+        canvas: &mut sdl2::render::WindowCanvas,
+        _camera: &Camera,
+        _: DrawPrimitives,
+    ) {
         canvas.set_draw_color(REGION_COLOR);
         let _ = canvas.draw_rect(rect!(
             self.left_up.x,
@@ -118,10 +157,10 @@ impl Intersect for Region {
             return false;
         }
 
-        return self.left_up.x <= (other.right_down.x)
+        self.left_up.x <= (other.right_down.x)
             && (self.right_down.x) >= other.left_up.x
             && self.left_up.y <= (other.right_down.y)
-            && (self.right_down.y) >= other.left_up.y;
+            && (self.right_down.y) >= other.left_up.y
     }
 }
 /*
@@ -137,6 +176,7 @@ impl From<Rect> for Region
         }
     }
 }*/
+#[cfg(feature = "render")]
 impl From<Region> for Rect {
     fn from(value: Region) -> Self {
         Rect::new(
@@ -147,41 +187,67 @@ impl From<Region> for Rect {
         )
     }
 }
+#[test]
+fn distance_to_point() {
+    let r = Region::new(V2f32::new(0.0, 0.0), V2f32::new(100.0, 50.0));
+    assert_eq!(r.distance_squared_to(V2f32::new(20.0, 20.0)), 0.0);
+    assert_eq!(r.distance_squared_to(V2f32::new(110.0, 20.0)), 100.0);
+    assert_eq!(r.distance_squared_to(V2f32::new(-3.0, 54.0)), 25.0);
+}
+
+#[test]
+fn split_regions_contain_every_point_once() {
+    let r = Region::new(V2f32::new(0.0, 0.0), V2f32::new(100.0, 50.0));
+    let parts = Region::sub_into(&r);
+    for point in [
+        V2f32::new(0.0, 0.0),
+        V2f32::new(50.0, 25.0),
+        V2f32::new(50.0, 0.0),
+        V2f32::new(0.0, 25.0),
+        V2f32::new(99.9, 49.9),
+    ] {
+        assert!(r.contains_point(point));
+        assert_eq!(parts.iter().filter(|p| p.contains_point(point)).count(), 1);
+    }
+    assert!(!r.contains_point(V2f32::new(100.0, 10.0)));
+    assert!(r.covers_point(V2f32::new(100.0, 50.0)));
+}
+
 #[test]
 fn empty_region() {
     let _r = Region::new(V2f32::new(0.0, 0.0), V2f32::new(0.0, 0.0));
-    assert_eq!(_r.is_empty(), true);
+    assert!(_r.is_empty());
     let _r2 = Region::new(V2f32::new(100.0, 0.0), V2f32::new(0.0, 0.0));
-    assert_eq!(_r2.is_empty(), true);
+    assert!(_r2.is_empty());
 }
 
 #[test]
 fn region_intersects() {
     let r_1 = Region::new(V2f32::new(0.0, 0.0), V2f32::new(200.0, 200.0));
     let r_2 = Region::new(V2f32::new(199.0, 100.0), V2f32::new(340.0, 600.0));
-    assert_eq!(r_1.intersect_with(&r_2), true);
-    assert_eq!(r_2.intersect_with(&r_1), true);
+    assert!(r_1.intersect_with(&r_2));
+    assert!(r_2.intersect_with(&r_1));
 }
 
 #[test]
 fn region_intersects_1() {
     let r_1 = Region::new(V2f32::new(0.0, 0.0), V2f32::new(200.0, 200.0));
     let r_2 = Region::new(V2f32::new(100.0, 100.0), V2f32::new(340.0, 600.0));
-    assert_eq!(r_1.intersect_with(&r_2), true);
-    assert_eq!(r_2.intersect_with(&r_1), true);
+    assert!(r_1.intersect_with(&r_2));
+    assert!(r_2.intersect_with(&r_1));
 }
 
 #[test]
 fn region_intersects_2() {
     let r_1 = Region::new(V2f32::new(0.0, 0.0), V2f32::new(200.0, 200.0));
     let r_2 = Region::new(V2f32::new(300.0, 100.0), V2f32::new(340.0, 600.0));
-    assert_eq!(r_1.intersect_with(&r_2), false);
-    assert_eq!(r_2.intersect_with(&r_1), false);
+    assert!(!r_1.intersect_with(&r_2));
+    assert!(!r_2.intersect_with(&r_1));
 }
 #[test]
 fn region_intersects_3() {
     let r_1 = Region::new(V2f32::new(0.0, 0.0), V2f32::new(200.0, 200.0));
     let r_2 = Region::new(V2f32::new(0.0, 201.0), V2f32::new(340.0, 600.0));
-    assert_eq!(r_1.intersect_with(&r_2), false);
-    assert_eq!(r_2.intersect_with(&r_1), false);
+    assert!(!r_1.intersect_with(&r_2));
+    assert!(!r_2.intersect_with(&r_1));
 }
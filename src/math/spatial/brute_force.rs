@@ -0,0 +1,72 @@
+use crate::{
+    logic::boid::boid_impl::Boid,
+    math::{quadtree::region::Region, vec::V2f32},
+};
+#[cfg(feature = "render")]
+use {
+    crate::{camera::Camera, constants::DrawPrimitives, graphics::renderer::Renderable},
+    sdl2::render::WindowCanvas,
+};
+
+use super::{distance_squared, Neighbour, SpatialIndex};
+
+/* Checks every boid on every query. The reference the other backends are tested against. */
+#[derive(Clone, Debug, Default)]
+pub struct BruteForce {
+    boundary: Region,
+    len: usize,
+}
+
+impl BruteForce {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SpatialIndex for BruteForce {
+    fn build(&mut self, boundary: Region, boids: &[Boid]) {
+        self.boundary = boundary;
+        self.len = boids.len();
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn query_radius(&self, boids: &[Boid], center: V2f32, radius: f32, found: &mut Vec<u32>) {
+        let radius_squared = radius * radius;
+        found.extend(
+            (0..self.len as u32).filter(|&i| {
+                distance_squared(boids[i as usize].position, center) <= radius_squared
+            }),
+        );
+    }
+
+    fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32> {
+        let mut all: Vec<Neighbour> = (0..self.len as u32)
+            .map(|i| Neighbour(distance_squared(boids[i as usize].position, center), i))
+            .collect();
+        all.sort();
+        all.into_iter().take(k).map(|Neighbour(_, i)| i).collect()
+    }
+
+    fn query_rect(&self, boids: &[Boid], region: &Region, found: &mut Vec<u32>) {
+        found.extend(
+            (0..self.len as u32).filter(|&i| region.covers_point(boids[i as usize].position)),
+        );
+    }
+
+    /* Nothing to update, every query reads the positions from the slice. */
+    fn update_position(&mut self, _: u32, _: V2f32, _: V2f32) -> bool {
+        true
+    }
+
+    #[cfg(feature = "render")]
+    fn render_debug(&mut self, canvas: &mut WindowCanvas, camera: &Camera, _: &[Boid]) {
+        let mut r = Region::new(
+            self.boundary.left_up - camera.pos,
+            self.boundary.right_down - camera.pos,
+        );
+        r.render(canvas, camera, DrawPrimitives::ALL_DISABLED);
+    }
+}
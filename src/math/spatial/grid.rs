@@ -0,0 +1,210 @@
+use std::collections::BinaryHeap;
+
+use crate::{
+    logic::boid::boid_impl::Boid,
+    math::{quadtree::region::Region, vec::V2f32},
+};
+#[cfg(feature = "render")]
+use {
+    crate::{camera::Camera, constants::QUAD_TREE_COLOR},
+    sdl2::{rect::Rect, render::WindowCanvas},
+};
+
+use super::{distance_squared, Neighbour, SpatialIndex};
+
+const MAX_CELLS_PER_SIDE: usize = 1024;
+
+/*
+ * Uniform grid over the boundary with square cells of `cell_size`, normally the
+ * view distance so a neighbour query only touches the 3x3 cells around a boid.
+ * Boids are counting-sorted by cell into one array: the boids of cell `c` are
+ * `indices[cell_start[c]..cell_start[c + 1]]`. Boids outside the boundary are
+ * clamped into the border cells, queries clamp the same way so they are found.
+ * Cells grow past `cell_size` when the boundary would need more than
+ * MAX_CELLS_PER_SIDE of them along a side, so tiny view distances stay cheap.
+ */
+#[derive(Clone, Debug)]
+pub struct UniformGrid {
+    /* The cell size asked for, `cell_size` is what the boundary allows. */
+    min_cell_size: f32,
+    cell_size: f32,
+    boundary: Region,
+    columns: usize,
+    rows: usize,
+    cell_start: Vec<u32>,
+    indices: Vec<u32>,
+    cells: Vec<u32>,
+}
+
+impl UniformGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            min_cell_size: cell_size.max(f32::EPSILON),
+            cell_size: cell_size.max(f32::EPSILON),
+            boundary: Region::default(),
+            columns: 1,
+            rows: 1,
+            cell_start: vec![0, 0],
+            indices: Vec::new(),
+            cells: Vec::new(),
+        }
+    }
+
+    fn column(&self, x: f32) -> usize {
+        let c = ((x - self.boundary.left_up.x) / self.cell_size).floor();
+        (c.max(0.0) as usize).min(self.columns - 1)
+    }
+    fn row(&self, y: f32) -> usize {
+        let r = ((y - self.boundary.left_up.y) / self.cell_size).floor();
+        (r.max(0.0) as usize).min(self.rows - 1)
+    }
+    fn cell(&self, position: V2f32) -> usize {
+        self.row(position.y) * self.columns + self.column(position.x)
+    }
+    fn cell_indices(&self, column: usize, row: usize) -> &[u32] {
+        let cell = row * self.columns + column;
+        &self.indices[self.cell_start[cell] as usize..self.cell_start[cell + 1] as usize]
+    }
+
+    /* Calls `f` with the boids of every cell overlapping the rectangle, clamped to the grid. */
+    fn for_cells_in(&self, left_up: V2f32, right_down: V2f32, mut f: impl FnMut(&[u32])) {
+        for row in self.row(left_up.y)..=self.row(right_down.y) {
+            for column in self.column(left_up.x)..=self.column(right_down.x) {
+                f(self.cell_indices(column, row));
+            }
+        }
+    }
+}
+
+impl SpatialIndex for UniformGrid {
+    fn build(&mut self, boundary: Region, boids: &[Boid]) {
+        let extent = boundary.width_height.x.max(boundary.width_height.y);
+        self.cell_size = self.min_cell_size.max(extent / MAX_CELLS_PER_SIDE as f32);
+        self.columns = ((boundary.width_height.x / self.cell_size).ceil() as usize).max(1);
+        self.rows = ((boundary.width_height.y / self.cell_size).ceil() as usize).max(1);
+        self.boundary = boundary;
+
+        let cell_count = self.columns * self.rows;
+        self.cell_start.clear();
+        self.cell_start.resize(cell_count + 1, 0);
+        self.cells.clear();
+        for b in boids {
+            let cell = self.cell(b.position);
+            self.cells.push(cell as u32);
+            self.cell_start[cell + 1] += 1;
+        }
+        for cell in 0..cell_count {
+            self.cell_start[cell + 1] += self.cell_start[cell];
+        }
+        /* `cell_start[c]` is used as the write cursor of cell `c` and restored after. */
+        self.indices.clear();
+        self.indices.resize(boids.len(), 0);
+        for (i, &cell) in self.cells.iter().enumerate() {
+            let cursor = &mut self.cell_start[cell as usize];
+            self.indices[*cursor as usize] = i as u32;
+            *cursor += 1;
+        }
+        for cell in (1..=cell_count).rev() {
+            self.cell_start[cell] = self.cell_start[cell - 1];
+        }
+        self.cell_start[0] = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn query_radius(&self, boids: &[Boid], center: V2f32, radius: f32, found: &mut Vec<u32>) {
+        let radius_squared = radius * radius;
+        self.for_cells_in(center - radius, center + radius, |cell| {
+            found.extend(cell.iter().filter(|&&i| {
+                distance_squared(boids[i as usize].position, center) <= radius_squared
+            }));
+        });
+    }
+
+    /*
+     * Searches rings of cells around the cell of `center`. Every cell of ring `r`
+     * is at least (r - 1) cells away, so once that is further than the k-th best
+     * boid found so far nothing closer can follow.
+     */
+    fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32> {
+        let mut best: BinaryHeap<Neighbour> = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            let (column, row) = (self.column(center.x) as i64, self.row(center.y) as i64);
+            let last_ring = self.columns.max(self.rows) as i64;
+            for ring in 0..=last_ring {
+                let ring_distance = (ring - 1).max(0) as f32 * self.cell_size;
+                if best.len() == k
+                    && best.peek().map(|n| n.0).unwrap_or(f32::INFINITY)
+                        < ring_distance * ring_distance
+                {
+                    break;
+                }
+                for r in row - ring..=row + ring {
+                    for c in column - ring..=column + ring {
+                        let on_ring = (r - row).abs() == ring || (c - column).abs() == ring;
+                        if !on_ring
+                            || r < 0
+                            || c < 0
+                            || r >= self.rows as i64
+                            || c >= self.columns as i64
+                        {
+                            continue;
+                        }
+                        for &i in self.cell_indices(c as usize, r as usize) {
+                            let d = distance_squared(boids[i as usize].position, center);
+                            best.push(Neighbour(d, i));
+                            if best.len() > k {
+                                best.pop();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|Neighbour(_, i)| i)
+            .collect()
+    }
+
+    fn query_rect(&self, boids: &[Boid], region: &Region, found: &mut Vec<u32>) {
+        self.for_cells_in(region.left_up, region.right_down, |cell| {
+            found.extend(
+                cell.iter()
+                    .filter(|&&i| region.covers_point(boids[i as usize].position)),
+            );
+        });
+    }
+
+    #[cfg(feature = "render")]
+    fn render_debug(&mut self, canvas: &mut WindowCanvas, camera: &Camera, _: &[Boid]) {
+        canvas.set_draw_color(QUAD_TREE_COLOR);
+        let size = self.cell_size as u32;
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                if self.cell_indices(column, row).is_empty() {
+                    continue;
+                }
+                let x = self.boundary.left_up.x + column as f32 * self.cell_size - camera.pos.x;
+                let y = self.boundary.left_up.y + row as f32 * self.cell_size - camera.pos.y;
+                let _ = canvas.draw_rect(Rect::new(x as i32, y as i32, size, size));
+            }
+        }
+    }
+}
+
+#[test]
+fn tiny_cells_are_bounded() {
+    use crate::math::vec::Vector2;
+
+    let boundary = Region::new(Vector2::zero(), Vector2::new(800.0, 600.0));
+    let boids = vec![Boid::new(0, Vector2::new(400.0, 300.0), Vector2::zero())];
+    let mut grid = UniformGrid::new(0.001);
+    grid.build(boundary, &boids);
+    assert!(grid.columns <= MAX_CELLS_PER_SIDE && grid.rows <= MAX_CELLS_PER_SIDE);
+    let mut found = vec![];
+    grid.query_radius(&boids, Vector2::new(400.0, 300.0), 0.001, &mut found);
+    assert_eq!(found, [0]);
+}
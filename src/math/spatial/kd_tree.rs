@@ -0,0 +1,235 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{
+    logic::boid::boid_impl::Boid,
+    math::{quadtree::region::Region, vec::V2f32},
+};
+#[cfg(feature = "render")]
+use {
+    crate::{camera::Camera, constants::QUAD_TREE_COLOR},
+    sdl2::render::WindowCanvas,
+};
+
+use super::{distance_squared, Neighbour, SpatialIndex};
+
+/*
+ * Balanced 2-d tree stored implicitly in `indices`: the node of a range is its
+ * middle element, the left half holds smaller coordinates on the split axis and
+ * the right half the larger ones. The axis alternates with depth, starting at x.
+ * Built with select_nth_unstable, so a build is O(n log n) without allocating.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct KdTree {
+    boundary: Region,
+    indices: Vec<u32>,
+}
+
+fn coordinate(position: V2f32, axis: usize) -> f32 {
+    if axis == 0 {
+        position.x
+    } else {
+        position.y
+    }
+}
+
+/*
+ * `a <= b`, but also true when either is NaN. NaN positions sort last in the
+ * tree, so a NaN split must never prune the half with the real boids in it.
+ */
+fn at_most(a: f32, b: f32) -> bool {
+    a.partial_cmp(&b) != Some(Ordering::Greater)
+}
+
+impl KdTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn build_range(indices: &mut [u32], boids: &[Boid], axis: usize) {
+        if indices.len() <= 1 {
+            return;
+        }
+        let mid = indices.len() / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| {
+            coordinate(boids[a as usize].position, axis)
+                .total_cmp(&coordinate(boids[b as usize].position, axis))
+        });
+        let (left, right) = indices.split_at_mut(mid);
+        Self::build_range(left, boids, 1 - axis);
+        Self::build_range(&mut right[1..], boids, 1 - axis);
+    }
+
+    fn radius_range(
+        indices: &[u32],
+        boids: &[Boid],
+        axis: usize,
+        center: V2f32,
+        radius_squared: f32,
+        found: &mut Vec<u32>,
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        let mid = indices.len() / 2;
+        let node = indices[mid];
+        let position = boids[node as usize].position;
+        if distance_squared(position, center) <= radius_squared {
+            found.push(node);
+        }
+        let delta = coordinate(center, axis) - coordinate(position, axis);
+        let (near, far) = if delta < 0.0 {
+            (&indices[..mid], &indices[mid + 1..])
+        } else {
+            (&indices[mid + 1..], &indices[..mid])
+        };
+        Self::radius_range(near, boids, 1 - axis, center, radius_squared, found);
+        if at_most(delta * delta, radius_squared) {
+            Self::radius_range(far, boids, 1 - axis, center, radius_squared, found);
+        }
+    }
+
+    fn nearest_range(
+        indices: &[u32],
+        boids: &[Boid],
+        axis: usize,
+        center: V2f32,
+        k: usize,
+        best: &mut BinaryHeap<Neighbour>,
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        let mid = indices.len() / 2;
+        let node = indices[mid];
+        let position = boids[node as usize].position;
+        best.push(Neighbour(distance_squared(position, center), node));
+        if best.len() > k {
+            best.pop();
+        }
+        let delta = coordinate(center, axis) - coordinate(position, axis);
+        let (near, far) = if delta < 0.0 {
+            (&indices[..mid], &indices[mid + 1..])
+        } else {
+            (&indices[mid + 1..], &indices[..mid])
+        };
+        Self::nearest_range(near, boids, 1 - axis, center, k, best);
+        let worst = best.peek().map(|n| n.0).unwrap_or(f32::INFINITY);
+        if best.len() < k || at_most(delta * delta, worst) {
+            Self::nearest_range(far, boids, 1 - axis, center, k, best);
+        }
+    }
+
+    fn rect_range(
+        indices: &[u32],
+        boids: &[Boid],
+        axis: usize,
+        region: &Region,
+        found: &mut Vec<u32>,
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        let mid = indices.len() / 2;
+        let node = indices[mid];
+        let position = boids[node as usize].position;
+        if region.covers_point(position) {
+            found.push(node);
+        }
+        let split = coordinate(position, axis);
+        if at_most(coordinate(region.left_up, axis), split) {
+            Self::rect_range(&indices[..mid], boids, 1 - axis, region, found);
+        }
+        if at_most(split, coordinate(region.right_down, axis)) {
+            Self::rect_range(&indices[mid + 1..], boids, 1 - axis, region, found);
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn render_range(
+        indices: &[u32],
+        boids: &[Boid],
+        axis: usize,
+        bounds: Region,
+        canvas: &mut WindowCanvas,
+        camera: &Camera,
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        let mid = indices.len() / 2;
+        let position = boids[indices[mid] as usize].position;
+        let split = coordinate(position, axis).clamp(
+            coordinate(bounds.left_up, axis),
+            coordinate(bounds.right_down, axis),
+        );
+        let (from, to, left, right) = if axis == 0 {
+            (
+                V2f32::new(split, bounds.left_up.y),
+                V2f32::new(split, bounds.right_down.y),
+                Region::new(bounds.left_up, V2f32::new(split, bounds.right_down.y)),
+                Region::new(V2f32::new(split, bounds.left_up.y), bounds.right_down),
+            )
+        } else {
+            (
+                V2f32::new(bounds.left_up.x, split),
+                V2f32::new(bounds.right_down.x, split),
+                Region::new(bounds.left_up, V2f32::new(bounds.right_down.x, split)),
+                Region::new(V2f32::new(bounds.left_up.x, split), bounds.right_down),
+            )
+        };
+        let (from, to) = (camera.calc_pos_v2f32(from), camera.calc_pos_v2f32(to));
+        let _ = canvas.draw_line((from.x as i32, from.y as i32), (to.x as i32, to.y as i32));
+        Self::render_range(&indices[..mid], boids, 1 - axis, left, canvas, camera);
+        Self::render_range(&indices[mid + 1..], boids, 1 - axis, right, canvas, camera);
+    }
+}
+
+impl SpatialIndex for KdTree {
+    fn build(&mut self, boundary: Region, boids: &[Boid]) {
+        self.boundary = boundary;
+        self.indices.clear();
+        self.indices.extend(0..boids.len() as u32);
+        Self::build_range(&mut self.indices, boids, 0);
+    }
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn query_radius(&self, boids: &[Boid], center: V2f32, radius: f32, found: &mut Vec<u32>) {
+        Self::radius_range(&self.indices, boids, 0, center, radius * radius, found);
+    }
+
+    fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        Self::nearest_range(&self.indices, boids, 0, center, k, &mut best);
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|Neighbour(_, i)| i)
+            .collect()
+    }
+
+    fn query_rect(&self, boids: &[Boid], region: &Region, found: &mut Vec<u32>) {
+        Self::rect_range(&self.indices, boids, 0, region, found);
+    }
+
+    /* Split lines at the positions the boids have now, clipped to the boundary. */
+    #[cfg(feature = "render")]
+    fn render_debug(&mut self, canvas: &mut WindowCanvas, camera: &Camera, boids: &[Boid]) {
+        if self.indices.len() > boids.len() {
+            return;
+        }
+        canvas.set_draw_color(QUAD_TREE_COLOR);
+        Self::render_range(
+            &self.indices,
+            boids,
+            0,
+            self.boundary.clone(),
+            canvas,
+            camera,
+        );
+    }
+}
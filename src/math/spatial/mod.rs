@@ -0,0 +1,237 @@
+pub mod brute_force;
+pub mod grid;
+pub mod kd_tree;
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::SimulationConfig,
+    logic::boid::boid_impl::Boid,
+    math::{
+        quadtree::{quadt::QuadTree, region::Region},
+        vec::V2f32,
+    },
+};
+#[cfg(feature = "render")]
+use {crate::camera::Camera, sdl2::render::WindowCanvas};
+
+use self::{brute_force::BruteForce, grid::UniformGrid, kd_tree::KdTree};
+
+/*
+ * Neighbour search over indices into a boid slice. `build` is called with the
+ * boids of the current tick and every query has to be given the same slice.
+ * Implementations must accept boids outside of `boundary` (and NaN positions)
+ * and keep their memory between builds where they can.
+ */
+pub trait SpatialIndex: Send + Sync {
+    fn build(&mut self, boundary: Region, boids: &[Boid]);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /* Boids at most `radius` away from `center`, in no particular order. */
+    fn query_radius(&self, boids: &[Boid], center: V2f32, radius: f32, found: &mut Vec<u32>);
+    /* The `k` boids closest to `center`, closest first. */
+    fn k_nearest(&self, boids: &[Boid], center: V2f32, k: usize) -> Vec<u32>;
+    /* Boids inside `region`, edges included. */
+    fn query_rect(&self, boids: &[Boid], region: &Region, found: &mut Vec<u32>);
+    /*
+     * Tells the index that boid `index` moved from `old` to `new`. Returns false
+     * when it can't follow moves, the caller then has to `build` before querying.
+     */
+    fn update_position(&mut self, _index: u32, _old: V2f32, _new: V2f32) -> bool {
+        false
+    }
+    #[cfg(feature = "render")]
+    fn render_debug(&mut self, canvas: &mut WindowCanvas, camera: &Camera, boids: &[Boid]);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialIndexKind {
+    #[default]
+    QuadTree,
+    Grid,
+    KdTree,
+    BruteForce,
+}
+
+impl SpatialIndexKind {
+    pub const ALL: [SpatialIndexKind; 4] = [
+        SpatialIndexKind::QuadTree,
+        SpatialIndexKind::Grid,
+        SpatialIndexKind::KdTree,
+        SpatialIndexKind::BruteForce,
+    ];
+
+    pub fn create(self, config: &SimulationConfig) -> Box<dyn SpatialIndex> {
+        match self {
+            SpatialIndexKind::QuadTree => Box::new(QuadTree::new(config.view_port())),
+            SpatialIndexKind::Grid => Box::new(UniformGrid::new(config.view_distance)),
+            SpatialIndexKind::KdTree => Box::new(KdTree::new()),
+            SpatialIndexKind::BruteForce => Box::new(BruteForce::new()),
+        }
+    }
+
+    /* Next kind in `ALL`, wraps around. Used to switch backends from the keyboard. */
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&k| k == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SpatialIndexKind::QuadTree => "quad_tree",
+            SpatialIndexKind::Grid => "grid",
+            SpatialIndexKind::KdTree => "kd_tree",
+            SpatialIndexKind::BruteForce => "brute_force",
+        }
+    }
+}
+
+impl fmt::Display for SpatialIndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SpatialIndexKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| "expected one of quad_tree, grid, kd_tree, brute_force".to_string())
+    }
+}
+
+pub(crate) fn distance_squared(a: V2f32, b: V2f32) -> f32 {
+    let d = a - b;
+    d.x * d.x + d.y * d.y
+}
+
+/* (distance², index) ordered by distance, NaN last. Shared by the k-nearest searches. */
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Neighbour(pub f32, pub u32);
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Neighbour {}
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/* Standing boids spread over the rectangle from `left_up` to `right_down`. */
+#[cfg(test)]
+pub(crate) fn random_flock(
+    amount: usize,
+    left_up: V2f32,
+    right_down: V2f32,
+    seed: u64,
+) -> Vec<Boid> {
+    use crate::math::vec::Vector2;
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(seed);
+    (0..amount)
+        .map(|i| {
+            let position = Vector2::random_from_vec(
+                Vector2::new(left_up.x, right_down.x),
+                Vector2::new(left_up.y, right_down.y),
+                &mut rng,
+            );
+            Boid::new(i, position, Vector2::zero())
+        })
+        .collect()
+}
+
+#[test]
+fn parse_kind() {
+    for kind in SpatialIndexKind::ALL {
+        assert_eq!(kind.to_string().parse::<SpatialIndexKind>(), Ok(kind));
+    }
+    assert!("octree".parse::<SpatialIndexKind>().is_err());
+    assert_eq!(
+        SpatialIndexKind::BruteForce.next(),
+        SpatialIndexKind::QuadTree
+    );
+}
+
+#[test]
+fn backends_match_brute_force() {
+    use crate::math::vec::Vector2;
+    use rand::{Rng, SeedableRng};
+
+    let config = SimulationConfig::default();
+    let boundary = config.view_port();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(5);
+    /* Sparse, default and crowded flocks on the same view port, each with one NaN boid. */
+    for (amount, seed) in [(10, 1), (300, 2), (3000, 3)] {
+        /* A few boids outside of the boundary, like Reflect produces. */
+        let margin = Vector2::new(20.0, 20.0);
+        let mut boids = random_flock(
+            amount,
+            V2f32::zero() - margin,
+            config.view_port_size + margin,
+            seed,
+        );
+        boids[amount / 3].position.x = f32::NAN;
+        let mut reference = BruteForce::new();
+        reference.build(boundary.clone(), &boids);
+
+        for kind in SpatialIndexKind::ALL {
+            let mut index = kind.create(&config);
+            /* Build twice, the second build has to forget the first. */
+            index.build(boundary.clone(), &boids[..amount / 2]);
+            index.build(boundary.clone(), &boids);
+            assert_eq!(index.len(), amount, "{}", kind);
+
+            for _ in 0..30 {
+                let center = Vector2::new(rng.gen_range(-60.0..860.0), rng.gen_range(-60.0..660.0));
+                let radius = rng.gen_range(0.0..150.0);
+                let sorted = |mut v: Vec<u32>| {
+                    v.sort();
+                    v
+                };
+
+                let (mut found, mut expected) = (vec![], vec![]);
+                index.query_radius(&boids, center, radius, &mut found);
+                reference.query_radius(&boids, center, radius, &mut expected);
+                assert_eq!(sorted(found), sorted(expected), "{} radius", kind);
+
+                let region = Region::new(center, center + radius);
+                let (mut found, mut expected) = (vec![], vec![]);
+                index.query_rect(&boids, &region, &mut found);
+                reference.query_rect(&boids, &region, &mut expected);
+                assert_eq!(sorted(found), sorted(expected), "{} rect", kind);
+
+                let k = rng.gen_range(0..amount + 3);
+                let distances = |found: Vec<u32>| -> Vec<u32> {
+                    found
+                        .iter()
+                        .map(|&i| distance_squared(boids[i as usize].position, center).to_bits())
+                        .collect()
+                };
+                assert_eq!(
+                    distances(index.k_nearest(&boids, center, k)),
+                    distances(reference.k_nearest(&boids, center, k)),
+                    "{} k_nearest {}",
+                    kind,
+                    k
+                );
+            }
+        }
+    }
+}
@@ -1,18 +1,20 @@
 use num::integer::Roots;
 
 use num::Zero;
-use rand::{
-    distributions::{uniform::SampleUniform, Distribution, Uniform},
-    Rng,
-};
-use sdl2::pixels::Color;
+use rand::{distributions::uniform::SampleUniform, Rng};
 use std::{
     fmt,
     iter::Sum,
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign},
 };
+#[cfg(feature = "render")]
+use {
+    rand::distributions::{Distribution, Uniform},
+    sdl2::pixels::Color,
+};
 
 use num::NumCast;
+use serde::{Deserialize, Serialize};
 pub trait DotProduct<T = Self> {
     type Output;
     fn dot(self, other: Self) -> Self::Output;
@@ -34,6 +36,10 @@ pub trait InBetween<T = Self> {
     fn in_between(vector: Self, scalar: Self::Scalar) -> bool;
 }
 
+pub trait Finite {
+    fn is_finite(&self) -> bool;
+}
+
 pub trait Magnitude<T> {
     type Output;
     fn calc_magnitude(&self) -> Self::Output;
@@ -41,7 +47,7 @@ pub trait Magnitude<T> {
     fn limit(&mut self, magnitude: T);
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Vector2<T> {
     pub x: T,
     pub y: T,
@@ -66,15 +72,13 @@ where
             y: Default::default(),
         }
     }
-    pub fn random(start: T, end: T) -> Vector2<T> {
-        let mut rng = rand::thread_rng();
+    pub fn random<R: Rng>(start: T, end: T, rng: &mut R) -> Vector2<T> {
         Vector2 {
             x: rng.gen_range(start..end),
             y: rng.gen_range(start..end),
         }
     }
-    pub fn random_from_vec(start: Vector2<T>, end: Vector2<T>) -> Vector2<T> {
-        let mut rng = rand::thread_rng();
+    pub fn random_from_vec<R: Rng>(start: Vector2<T>, end: Vector2<T>, rng: &mut R) -> Vector2<T> {
         Vector2 {
             x: rng.gen_range(start.x..start.y),
             y: rng.gen_range(end.x..end.y),
@@ -99,7 +103,7 @@ macro_rules! vect_impl {
                 return (self.x * other.x) + (self.y * other.y);
             }
             fn dot_self(self) -> $t {
-                self.x * self.y
+                self.dot(self)
             }
         }
 
@@ -116,7 +120,7 @@ macro_rules! vect_impl {
             fn sum<I: Iterator<Item = Vector2<$t>>>(iter: I) -> Self {
                 let mut ret: Vector2<$t> = Vector2::zero();
                 for v in iter {
-                    ret = ret + v;
+                    ret += v;
                 }
                 ret
             }
@@ -472,8 +476,20 @@ macro_rules! in_between_impl {
         }
     };
 }
+macro_rules! finite_impl {
+    ($t:ty) => {
+        impl Finite for Vector2<$t> {
+            #[inline]
+            fn is_finite(&self) -> bool {
+                self.x.is_finite() && self.y.is_finite()
+            }
+        }
+    };
+}
 magnitude_impl_float!(f32);
 magnitude_impl_float!(f64);
+finite_impl!(f32);
+finite_impl!(f64);
 magnitude_impl_int!(u8);
 magnitude_impl_int!(u16);
 magnitude_impl_int!(u32);
@@ -510,6 +526,7 @@ pub type V2i32 = Vector2<i16>;
 pub type V2u32 = Vector2<u32>;
 pub type V2usize = Vector2<usize>;
 
+#[cfg(feature = "render")]
 pub fn random_color() -> Color {
     let mut rng = rand::thread_rng();
     let c = Uniform::from(0..255);
@@ -593,7 +610,7 @@ fn distance() {
     let y = 3.12;
     let input = Vector2::new(x, y);
     let input_2 = Vector2::new(x, y);
-    assert_eq!(Vector2::new(0.0, 0.0), Vector2::distance(input, input_2));
+    assert_eq!(0.0, Vector2::distance(input, input_2));
 }
 #[test]
 fn div_scalar() {
@@ -603,3 +620,9 @@ fn div_scalar() {
     let dot = input / 2.0;
     assert_eq!(dot, Vector2::new(1.15, 1.56));
 }
+#[test]
+fn dot_test() {
+    let input = Vector2::new(3, -4);
+    assert_eq!(input.dot(Vector2::new(2, 1)), 2);
+    assert_eq!(input.dot_self(), 25);
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Read, Write},
+};
+
+use crate::{
+    constants::{types::BoidId, BOID_MASS},
+    logic::boid::{boid_impl::Boid, species::Species},
+    math::vec::{V2f32, Vector2},
+};
+
+use super::{Frame, RecordingError, TrajectoryWriter};
+
+/*
+ * Layout, all integers are LEB128 varints unless noted:
+ *   header: b"BOIDTRAJ", version (u32 LE)
+ *   frame:  tick - previous tick, boid count, then per boid
+ *           id << 1 | kind changed, zigzag deltas of x, y, vx, vy against the
+ *           same id in the previous frame. When the low bit of the id is set the
+ *           species index and the mass (f32 LE) follow, new ids start as
+ *           prey of mass BOID_MASS.
+ * Values are quantised to 1/QUANTISATION before the delta is taken, so the
+ * error stays below half a step no matter how long the recording is.
+ */
+pub const RECORDING_VERSION: u32 = 2;
+const MAGIC: &[u8; 8] = b"BOIDTRAJ";
+const QUANTISATION: f32 = 1024.0;
+/* Boids reserved for up front, the count comes from the file and may be garbage. */
+const MAX_PREALLOCATED: usize = 64 * 1024;
+
+type Quantised = [i64; 4];
+
+/* What the reader and writer remember of each boid between frames. */
+#[derive(Clone, Copy)]
+struct Previous {
+    q: Quantised,
+    species: Species,
+    mass: f32,
+}
+
+impl Default for Previous {
+    fn default() -> Self {
+        Self {
+            q: Quantised::default(),
+            species: Species::default(),
+            mass: BOID_MASS,
+        }
+    }
+}
+
+fn quantise(boid: &Boid) -> Quantised {
+    [
+        boid.position.x,
+        boid.position.y,
+        boid.velocity.x,
+        boid.velocity.y,
+    ]
+    .map(|v| (v * QUANTISATION).round() as i64)
+}
+
+fn dequantise(id: BoidId, previous: Previous) -> Boid {
+    let [x, y, vx, vy] = previous.q.map(|v| v as f32 / QUANTISATION);
+    let position: V2f32 = Vector2::new(x, y);
+    let velocity: V2f32 = Vector2::new(vx, vy);
+    Boid::new(id, position, velocity)
+        .with_species(previous.species)
+        .with_mass(previous.mass)
+}
+
+pub struct BinaryWriter<W: Write> {
+    out: W,
+    last_tick: u64,
+    previous: HashMap<BoidId, Previous>,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub fn new(mut out: W) -> Result<Self, RecordingError> {
+        out.write_all(MAGIC)?;
+        out.write_all(&RECORDING_VERSION.to_le_bytes())?;
+        Ok(Self {
+            out,
+            last_tick: 0,
+            previous: HashMap::new(),
+        })
+    }
+}
+
+impl<W: Write + Send + Sync> TrajectoryWriter for BinaryWriter<W> {
+    fn write_frame(&mut self, tick: u64, boids: &[Boid]) -> Result<(), RecordingError> {
+        if tick < self.last_tick {
+            return Err(RecordingError::Io(format!(
+                "tick {} written after tick {}",
+                tick, self.last_tick
+            )));
+        }
+        write_varint(&mut self.out, tick - self.last_tick)?;
+        write_varint(&mut self.out, boids.len() as u64)?;
+        let mut current = HashMap::with_capacity(boids.len());
+        for b in boids {
+            let q = quantise(b);
+            let base = self.previous.get(&b.id).copied().unwrap_or_default();
+            let changed = b.species != base.species || b.mass.to_bits() != base.mass.to_bits();
+            write_varint(&mut self.out, (b.id as u64) << 1 | changed as u64)?;
+            if changed {
+                write_varint(&mut self.out, b.species.index() as u64)?;
+                self.out.write_all(&b.mass.to_le_bytes())?;
+            }
+            for (value, base) in q.iter().zip(base.q) {
+                write_varint(&mut self.out, zigzag(value - base))?;
+            }
+            current.insert(
+                b.id,
+                Previous {
+                    q,
+                    species: b.species,
+                    mass: b.mass,
+                },
+            );
+        }
+        self.previous = current;
+        self.last_tick = tick;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RecordingError> {
+        self.out.flush().map_err(RecordingError::from)
+    }
+}
+
+pub struct BinaryReader<R: BufRead> {
+    input: R,
+    last_tick: u64,
+    previous: HashMap<BoidId, Previous>,
+}
+
+impl<R: BufRead> BinaryReader<R> {
+    pub fn new(mut input: R) -> Result<Self, RecordingError> {
+        let mut magic = [0u8; 8];
+        let mut version = [0u8; 4];
+        input
+            .read_exact(&mut magic)
+            .and_then(|_| input.read_exact(&mut version))
+            .map_err(|_| RecordingError::Decode("not a binary recording".to_string()))?;
+        if &magic != MAGIC {
+            return Err(RecordingError::Decode("not a binary recording".to_string()));
+        }
+        match u32::from_le_bytes(version) {
+            RECORDING_VERSION => Ok(Self {
+                input,
+                last_tick: 0,
+                previous: HashMap::new(),
+            }),
+            other => Err(RecordingError::UnsupportedVersion(other)),
+        }
+    }
+
+    /* None at a clean end of file, a frame cut in half is an error. */
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, RecordingError> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let tick = self.last_tick + read_varint(&mut self.input)?;
+        let count = read_varint(&mut self.input)? as usize;
+        let mut boids = Vec::with_capacity(count.min(MAX_PREALLOCATED));
+        let mut current = HashMap::with_capacity(count.min(MAX_PREALLOCATED));
+        for _ in 0..count {
+            let tagged = read_varint(&mut self.input)?;
+            let id = (tagged >> 1) as BoidId;
+            let mut previous = self.previous.get(&id).copied().unwrap_or_default();
+            if tagged & 1 == 1 {
+                let index = read_varint(&mut self.input)? as usize;
+                previous.species = *Species::ALL
+                    .get(index)
+                    .ok_or_else(|| RecordingError::Decode(format!("unknown species {}", index)))?;
+                let mut mass = [0u8; 4];
+                self.input.read_exact(&mut mass).map_err(|_| {
+                    RecordingError::Decode("recording ends inside a frame".to_string())
+                })?;
+                previous.mass = f32::from_le_bytes(mass);
+            }
+            for value in previous.q.iter_mut() {
+                *value += unzigzag(read_varint(&mut self.input)?);
+            }
+            boids.push(dequantise(id, previous));
+            current.insert(id, previous);
+        }
+        self.previous = current;
+        self.last_tick = tick;
+        Ok(Some(Frame { tick, boids }))
+    }
+
+    pub fn read_all(mut self) -> Result<Vec<Frame>, RecordingError> {
+        let mut frames = vec![];
+        while let Some(frame) = self.read_frame()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn write_varint<W: Write>(out: &mut W, mut v: u64) -> Result<(), RecordingError> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    out.write_all(&buf[..len]).map_err(RecordingError::from)
+}
+
+fn read_varint<R: Read>(input: &mut R) -> Result<u64, RecordingError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        input
+            .read_exact(&mut byte)
+            .map_err(|_| RecordingError::Decode("recording ends inside a frame".to_string()))?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(RecordingError::Decode("varint too long".to_string()))
+}
+
+#[test]
+fn varint_and_zigzag_round_trip() {
+    for v in [0i64, 1, -1, 63, -64, 1 << 40, i64::MAX, i64::MIN] {
+        let mut bytes = vec![];
+        write_varint(&mut bytes, zigzag(v)).unwrap();
+        assert_eq!(unzigzag(read_varint(&mut bytes.as_slice()).unwrap()), v);
+    }
+}
+
+#[test]
+fn delta_frames_are_compact() {
+    let boids: Vec<Boid> = (0..100)
+        .map(|i| {
+            Boid::new(
+                i,
+                Vector2::new(i as f32 * 7.0, 300.0),
+                Vector2::new(1.0, 0.5),
+            )
+        })
+        .collect();
+    let mut writer = BinaryWriter::new(vec![]).unwrap();
+    writer.write_frame(0, &boids).unwrap();
+    let first_frame = writer.out.len();
+    let moved: Vec<Boid> = boids
+        .iter()
+        .map(|b| Boid::new(b.id, b.position + b.velocity, b.velocity))
+        .collect();
+    writer.write_frame(1, &moved).unwrap();
+    let second_frame = writer.out.len() - first_frame;
+    /* Raw f32s alone would take 16 bytes per boid. */
+    assert!(second_frame < first_frame);
+    assert!(second_frame < boids.len() * 16 / 2);
+
+    let mut reader = BinaryReader::new(writer.out.as_slice()).unwrap();
+    assert_eq!(reader.read_frame().unwrap().unwrap().boids, boids);
+    assert_eq!(reader.read_frame().unwrap().unwrap().boids, moved);
+    assert!(reader.read_frame().unwrap().is_none());
+}
+
+#[test]
+fn oversized_counts_are_decode_errors() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(RECORDING_VERSION.to_le_bytes());
+    write_varint(&mut bytes, 0).unwrap();
+    write_varint(&mut bytes, u64::MAX).unwrap();
+    write_varint(&mut bytes, 0).unwrap();
+    let mut reader = BinaryReader::new(bytes.as_slice()).unwrap();
+    assert!(matches!(
+        reader.read_frame(),
+        Err(RecordingError::Decode(_))
+    ));
+}
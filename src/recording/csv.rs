@@ -0,0 +1,133 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    logic::boid::{boid_impl::Boid, species::Species},
+    math::vec::{V2f32, Vector2},
+};
+
+use super::{Frame, RecordingError, TrajectoryWriter};
+
+const HEADER: &str = "tick,id,x,y,vx,vy,species,mass";
+
+/* Floats are written with `Display`, which round-trips an f32 exactly. */
+pub struct CsvWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(mut out: W) -> Result<Self, RecordingError> {
+        writeln!(out, "{}", HEADER)?;
+        Ok(Self { out })
+    }
+}
+
+impl<W: Write + Send + Sync> TrajectoryWriter for CsvWriter<W> {
+    fn write_frame(&mut self, tick: u64, boids: &[Boid]) -> Result<(), RecordingError> {
+        for b in boids {
+            writeln!(
+                self.out,
+                "{},{},{},{},{},{},{},{}",
+                tick,
+                b.id,
+                b.position.x,
+                b.position.y,
+                b.velocity.x,
+                b.velocity.y,
+                b.species,
+                b.mass
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RecordingError> {
+        self.out.flush().map_err(RecordingError::from)
+    }
+}
+
+pub struct CsvReader<R: BufRead> {
+    input: R,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(input: R) -> Self {
+        Self { input }
+    }
+
+    /* Rows of the same tick have to be next to each other, as CsvWriter writes them. */
+    pub fn read_all(self) -> Result<Vec<Frame>, RecordingError> {
+        let mut frames: Vec<Frame> = vec![];
+        for (number, line) in self.input.lines().enumerate() {
+            let line = line?;
+            if number == 0 {
+                if line.trim() != HEADER {
+                    return Err(RecordingError::Decode(format!(
+                        "expected header {}, got {}",
+                        HEADER, line
+                    )));
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (tick, boid) = parse_row(&line)
+                .map_err(|e| RecordingError::Decode(format!("line {}: {}", number + 1, e)))?;
+            match frames.last_mut() {
+                Some(frame) if frame.tick == tick => frame.boids.push(boid),
+                _ => frames.push(Frame {
+                    tick,
+                    boids: vec![boid],
+                }),
+            }
+        }
+        Ok(frames)
+    }
+}
+
+fn parse_row(line: &str) -> Result<(u64, Boid), String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() != 8 {
+        return Err(format!("expected 8 fields, got {}", fields.len()));
+    }
+    let float = |i: usize| -> Result<f32, String> {
+        fields[i]
+            .parse()
+            .map_err(|e| format!("{} ({})", fields[i], e))
+    };
+    let tick = fields[0]
+        .parse()
+        .map_err(|e| format!("{} ({})", fields[0], e))?;
+    let id = fields[1]
+        .parse()
+        .map_err(|e| format!("{} ({})", fields[1], e))?;
+    let position: V2f32 = Vector2::new(float(2)?, float(3)?);
+    let velocity: V2f32 = Vector2::new(float(4)?, float(5)?);
+    let species: Species = fields[6]
+        .parse()
+        .map_err(|e| format!("{} ({})", fields[6], e))?;
+    let boid = Boid::new(id, position, velocity)
+        .with_species(species)
+        .with_mass(float(7)?);
+    Ok((tick, boid))
+}
+
+#[test]
+fn rejects_malformed_rows() {
+    let read = |text: &str| CsvReader::new(text.as_bytes()).read_all();
+    let header = "tick,id,x,y,vx,vy,species,mass\n";
+    assert!(read(&format!("{}0,1,2,3,4,prey\n", header)).is_err());
+    assert!(read(&format!("{}0,1,2,3,4,abc,prey,1\n", header)).is_err());
+    assert!(read(&format!("{}0,1,2,3,4,5,wolf,1\n", header)).is_err());
+    assert!(read("tick,id,x,y,vx,vy\n0,0,1,2,3,4\n").is_err());
+    let frames = read(&format!(
+        "{}0,0,1,2,3,4,prey,1\n0,1,1,2,3,4,predator,2.5\n1,0,2,2,3,4,prey,1\n",
+        header
+    ))
+    .unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].boids.len(), 2);
+    assert_eq!(frames[0].boids[1].species, Species::Predator);
+    assert_eq!(frames[0].boids[1].mass, 2.5);
+    assert_eq!(frames[1].boids[0].position, Vector2::new(2.0, 2.0));
+}
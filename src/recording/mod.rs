@@ -0,0 +1,159 @@
+pub mod binary;
+pub mod csv;
+pub mod replay;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use crate::logic::boid::boid_impl::Boid;
+
+use self::{
+    binary::{BinaryReader, BinaryWriter},
+    csv::{CsvReader, CsvWriter},
+};
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(String),
+    Decode(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Io(e) => write!(f, "recording io error: {}", e),
+            RecordingError::Decode(e) => write!(f, "cannot decode recording: {}", e),
+            RecordingError::UnsupportedVersion(v) => write!(
+                f,
+                "recording version {} is not supported, expected {}",
+                v,
+                binary::RECORDING_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(e: std::io::Error) -> Self {
+        RecordingError::Io(e.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /* One `tick,id,x,y,vx,vy,species,mass` row per boid and tick, for spreadsheets and scripts. */
+    Csv,
+    /* Quantised and delta-encoded against the previous tick, see `binary`. */
+    Binary,
+}
+
+impl RecordingFormat {
+    /* `.csv` is Csv, anything else is Binary. */
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => RecordingFormat::Csv,
+            _ => RecordingFormat::Binary,
+        }
+    }
+}
+
+/* State of the whole flock after `tick` updates. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: u64,
+    pub boids: Vec<Boid>,
+}
+
+/*
+ * Sink for BoidManager::update, called once per tick with the boids after integration.
+ * Has to be Sync because the manager is shared with the rayon workers.
+ */
+pub trait TrajectoryWriter: Send + Sync {
+    fn write_frame(&mut self, tick: u64, boids: &[Boid]) -> Result<(), RecordingError>;
+    fn finish(&mut self) -> Result<(), RecordingError>;
+}
+
+pub fn create_writer(path: &Path) -> Result<Box<dyn TrajectoryWriter>, RecordingError> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(match RecordingFormat::from_path(path) {
+        RecordingFormat::Csv => Box::new(CsvWriter::new(file)?),
+        RecordingFormat::Binary => Box::new(BinaryWriter::new(file)?),
+    })
+}
+
+/* A finished recording, fully loaded so the replay can jump to any tick. */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, RecordingError> {
+        let file = BufReader::new(File::open(path)?);
+        let frames = match RecordingFormat::from_path(path) {
+            RecordingFormat::Csv => CsvReader::new(file).read_all()?,
+            RecordingFormat::Binary => BinaryReader::new(file)?.read_all()?,
+        };
+        log::info!("loaded {} frames from {}", frames.len(), path.display());
+        Ok(Self { frames })
+    }
+}
+
+#[test]
+fn record_and_load_both_formats() {
+    use crate::{
+        config::SimulationConfig,
+        logic::boid::{boid_mgr::BoidManager, species::Species, traits::Updatable},
+        math::vec::{Distance, V2f32},
+    };
+
+    let dir = std::env::temp_dir();
+    let csv = dir.join(format!("boids-recording-{}.csv", std::process::id()));
+    let binary = dir.join(format!("boids-recording-{}.bin", std::process::id()));
+
+    let mut expected = vec![];
+    for path in [&csv, &binary] {
+        let mut config = SimulationConfig::default();
+        config.predator.mass = 2.5;
+        let mut manager = BoidManager::new(config).with_seed(3);
+        manager.spawn_boid(25);
+        manager.add_species(Species::Predator, 3);
+        manager.record_to(path).unwrap();
+        expected = vec![manager.boids().to_vec()];
+        for _ in 0..30 {
+            manager.update();
+            expected.push(manager.boids().to_vec());
+        }
+        manager.stop_recording().unwrap();
+    }
+
+    let from_csv = Recording::load(&csv).unwrap();
+    let from_binary = Recording::load(&binary).unwrap();
+    let _ = std::fs::remove_file(csv);
+    let _ = std::fs::remove_file(binary);
+
+    assert!(expected[0]
+        .iter()
+        .any(|b| b.species == Species::Predator && b.mass == 2.5));
+    assert_eq!(from_csv.frames.len(), 31);
+    assert_eq!(from_binary.frames.len(), 31);
+    for (tick, boids) in expected.iter().enumerate() {
+        assert_eq!(from_csv.frames[tick].tick, tick as u64);
+        assert_eq!(&from_csv.frames[tick].boids, boids);
+        assert_eq!(from_binary.frames[tick].tick, tick as u64);
+        for (decoded, boid) in from_binary.frames[tick].boids.iter().zip(boids) {
+            assert_eq!(decoded.id, boid.id);
+            assert_eq!(decoded.species, boid.species);
+            assert_eq!(decoded.mass, boid.mass);
+            assert!(V2f32::distance(decoded.position, boid.position) < 1e-2);
+            assert!(V2f32::distance(decoded.velocity, boid.velocity) < 1e-2);
+        }
+    }
+}
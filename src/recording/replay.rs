@@ -0,0 +1,146 @@
+use crate::logic::boid::boid_mgr::BoidManager;
+
+use super::{Frame, Recording};
+
+pub const MIN_REPLAY_SPEED: f32 = 0.125;
+pub const MAX_REPLAY_SPEED: f32 = 16.0;
+
+/*
+ * Plays a Recording back in place of the simulation, advanced once per tick
+ * of the fixed timestep, so speed 1 shows the recorded ticks at `tick_rate`.
+ * The cursor is fractional, so speeds below 1 hold a recorded tick for
+ * several ticks. It never calls BoidManager::update, `apply` only copies the
+ * recorded boids into the manager.
+ */
+pub struct Replay {
+    recording: Recording,
+    cursor: f32,
+    speed: f32,
+    paused: bool,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            cursor: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.recording.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.recording.frames.is_empty()
+    }
+    pub fn frame_index(&self) -> usize {
+        self.cursor as usize
+    }
+    pub fn frame(&self) -> Option<&Frame> {
+        self.recording.frames.get(self.frame_index())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+    }
+
+    /* Moves by whole frames (negative goes back) and pauses, used for single stepping. */
+    pub fn step(&mut self, frames: i64) {
+        self.paused = true;
+        self.scrub_to(self.frame_index() as i64 + frames);
+    }
+
+    /* Jumps to a frame index, clamped to the recording. Keeps the pause state. */
+    pub fn scrub_to(&mut self, frame: i64) {
+        let last = self.len().saturating_sub(1) as i64;
+        self.cursor = frame.clamp(0, last) as f32;
+    }
+
+    /* Called once per simulation tick, stops on the last frame instead of looping. */
+    pub fn advance(&mut self) {
+        if self.paused || self.is_empty() {
+            return;
+        }
+        let last = (self.len() - 1) as f32;
+        self.cursor = (self.cursor + self.speed).min(last);
+        if self.cursor >= last {
+            self.paused = true;
+        }
+    }
+
+    pub fn apply(&self, boid_manager: &mut BoidManager) {
+        if let Some(frame) = self.frame() {
+            boid_manager.set_boids(frame.boids.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_replay(frames: u64) -> Replay {
+    use crate::{logic::boid::boid_impl::Boid, math::vec::Vector2};
+
+    Replay::new(Recording {
+        frames: (0..frames)
+            .map(|tick| Frame {
+                tick,
+                boids: vec![Boid::new(
+                    0,
+                    Vector2::new(tick as f32, 0.0),
+                    Vector2::new(1.0, 0.0),
+                )],
+            })
+            .collect(),
+    })
+}
+
+#[test]
+fn advance_respects_speed_and_stops_at_end() {
+    let mut replay = test_replay(10);
+    replay.set_speed(0.5);
+    replay.advance();
+    assert_eq!(replay.frame_index(), 0);
+    replay.advance();
+    assert_eq!(replay.frame_index(), 1);
+
+    replay.set_speed(100.0);
+    assert_eq!(replay.speed(), MAX_REPLAY_SPEED);
+    replay.advance();
+    assert_eq!(replay.frame_index(), 9);
+    assert!(replay.is_paused());
+}
+
+#[test]
+fn step_and_scrub_are_clamped() {
+    let mut replay = test_replay(5);
+    replay.step(2);
+    assert!(replay.is_paused());
+    assert_eq!(replay.frame().unwrap().tick, 2);
+    replay.step(-10);
+    assert_eq!(replay.frame_index(), 0);
+    replay.scrub_to(100);
+    assert_eq!(replay.frame_index(), 4);
+    replay.advance();
+    assert_eq!(replay.frame_index(), 4);
+}
+
+#[test]
+fn apply_does_not_run_the_simulation() {
+    let mut replay = test_replay(3);
+    let mut boid_manager = BoidManager::default();
+    replay.scrub_to(2);
+    replay.apply(&mut boid_manager);
+    assert_eq!(boid_manager.boids(), replay.frame().unwrap().boids);
+    assert_eq!(boid_manager.tick(), 0);
+}